@@ -0,0 +1,418 @@
+use reqwest::Url;
+use serde::{Deserialize, Deserializer, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+    Id,
+};
+use worker::{console_log, Date, Headers, Method, Request, Response, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+use crate::events::{emit_bookmark, EventKind};
+use crate::feed::authenticate;
+use crate::oauth::{session_user, OAuthConfig};
+use crate::rest;
+use crate::store::{BookmarkStore, ReadState, SourceStatus, StoredBookmark};
+
+const DEFAULT_PAGE: usize = 25;
+const MAX_PAGE: usize = 100;
+
+// Bookmarks read for one page, each one is a KV read. A search stopping here returns what it
+// found with `next` pointing at where it stopped
+const MAX_SCANNED: usize = 200;
+
+const MAX_NOTE: usize = 1000;
+const MAX_TAGS: usize = 10;
+const MAX_TAG: usize = 32;
+
+#[derive(Serialize)]
+struct ApiAuthor {
+    id: Id<UserMarker>,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct ApiMessage {
+    id: Id<MessageMarker>,
+    author: ApiAuthor,
+    content: String,
+    timestamp: String,
+}
+
+/// A bookmark as returned by the JSON API.
+#[derive(Serialize)]
+struct ApiBookmark {
+    id: Id<MessageMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
+    jump_url: String,
+    created_at: u64,
+    status: SourceStatus,
+    read_state: ReadState,
+    note: Option<String>,
+    tags: Vec<String>,
+    remind_at: Option<u64>,
+    messages: Vec<ApiMessage>,
+}
+
+impl ApiBookmark {
+    fn from_bookmark(bookmark: &StoredBookmark) -> ApiBookmark {
+        ApiBookmark {
+            id: bookmark.dm_message_id,
+            guild_id: bookmark.guild_id,
+            channel_id: bookmark.channel_id,
+            jump_url: bookmark.jump_url(),
+            created_at: bookmark.created_at,
+            status: bookmark.status,
+            read_state: bookmark.read_state,
+            note: bookmark.note.clone(),
+            tags: bookmark.tags.clone(),
+            remind_at: bookmark.remind_at,
+            messages: bookmark
+                .messages
+                .iter()
+                .map(|m| ApiMessage {
+                    id: m.id,
+                    author: ApiAuthor {
+                        id: m.author.id,
+                        name: m.author.name.clone(),
+                    },
+                    content: m.content.clone(),
+                    timestamp: m.timestamp.iso_8601().to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ApiPage {
+    bookmarks: Vec<ApiBookmark>,
+    /// Pass as `before` to get the next page, absent on the last page. A search can return fewer
+    /// bookmarks than asked for, or none, and still have a next page
+    next: Option<Id<MessageMarker>>,
+}
+
+/// The body of `PATCH /api/bookmarks/:id`, fields left out are not changed.
+#[derive(Deserialize)]
+struct Edit {
+    /// An empty note removes it
+    note: Option<String>,
+    tags: Option<Vec<String>>,
+    /// Unix time in ms, `null` removes the reminder
+    #[serde(default, deserialize_with = "present")]
+    remind_at: Option<Option<u64>>,
+    read_state: Option<ReadState>,
+}
+
+// Tells a field set to `null` apart from one left out
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn matches(bookmark: &StoredBookmark, query: &str) -> bool {
+    bookmark.messages.iter().any(|m| {
+        m.content.to_lowercase().contains(query) || m.author.name.to_lowercase().contains(query)
+    }) || bookmark
+        .note
+        .as_ref()
+        .is_some_and(|note| note.to_lowercase().contains(query))
+        || bookmark.tags.iter().any(|tag| tag == query)
+}
+
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, &'static str> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || normalized.contains(&tag) {
+            continue;
+        }
+        if tag.chars().count() > MAX_TAG {
+            return Err("Tags are at most 32 characters");
+        }
+        normalized.push(tag);
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err("A bookmark has at most 10 tags");
+    }
+    Ok(normalized)
+}
+
+/// Newest first, starting after `before`, keeping those matching `query` when given. Reads at
+/// most `MAX_SCANNED` bookmarks, also returns where to continue from when there may be more.
+pub(crate) async fn newest(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+    before: Option<Id<MessageMarker>>,
+    limit: usize,
+    query: Option<&str>,
+) -> Result<(Vec<StoredBookmark>, Option<Id<MessageMarker>>), InteractionError> {
+    let mut ids = store.list(user_id).await?;
+    if let Some(before) = before {
+        match ids.iter().position(|id| *id == before) {
+            Some(position) => ids.truncate(position),
+            None => ids.clear(),
+        }
+    }
+
+    let mut bookmarks = Vec::new();
+    let mut last = None;
+    for (scanned, id) in ids.into_iter().rev().enumerate() {
+        // Older ones are left for the next request, which starts after the last one read
+        if scanned == MAX_SCANNED {
+            return Ok((bookmarks, last));
+        }
+        last = Some(id);
+        let Some(bookmark) = store.get(user_id, id).await? else {
+            continue;
+        };
+        if query.is_none_or(|q| matches(&bookmark, q)) {
+            // One past the page tells whether there is another one
+            if bookmarks.len() == limit {
+                let next = bookmarks.last().map(|b| b.dm_message_id);
+                return Ok((bookmarks, next));
+            }
+            bookmarks.push(bookmark);
+        }
+    }
+    Ok((bookmarks, None))
+}
+
+fn query(req: &Request, name: &str) -> Option<String> {
+    req.url()
+        .ok()?
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+// The origin of the dashboard, the only one allowed to make credentialed requests
+fn allowed_origin(config: &OAuthConfig) -> Option<String> {
+    let url = Url::parse(config.dashboard_url.as_deref()?).ok()?;
+    Some(url.origin().ascii_serialization())
+}
+
+/// Lets the dashboard read the response, returned as is when no dashboard is configured.
+pub(crate) fn with_cors(ctx: &RouteContext<RouteData>, response: worker::Result<Response>) -> worker::Result<Response> {
+    let mut response = response?;
+    if let Some(origin) = OAuthConfig::from_ctx(ctx).as_ref().and_then(allowed_origin) {
+        let headers = response.headers_mut();
+        headers.set("Access-Control-Allow-Origin", &origin)?;
+        headers.set("Access-Control-Allow-Credentials", "true")?;
+        headers.set("Vary", "Origin")?;
+    }
+    Ok(response)
+}
+
+/// `OPTIONS /api/*path`, the preflight for the dashboard's requests.
+pub(crate) fn preflight(ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    let mut headers = Headers::new();
+    headers.set("Access-Control-Allow-Methods", "GET, PATCH, DELETE, POST")?;
+    headers.set("Access-Control-Allow-Headers", "Authorization, Content-Type")?;
+    headers.set("Access-Control-Max-Age", "86400")?;
+    with_cors(ctx, Ok(Response::empty()?.with_status(204).with_headers(headers)))
+}
+
+/// Whether a request may change something with the session cookie: browsers send the `Origin` of
+/// cross-site requests, only the dashboard's is let through.
+pub(crate) fn origin_allowed(req: &Request, config: &OAuthConfig) -> bool {
+    match req.headers().get("Origin") {
+        Ok(Some(origin)) => Some(origin) == allowed_origin(config),
+        _ => true,
+    }
+}
+
+// The user of the session cookie. Changes are refused from any origin but the dashboard's.
+fn dashboard_user(req: &Request, ctx: &RouteContext<RouteData>) -> Option<Id<UserMarker>> {
+    let config = OAuthConfig::from_ctx(ctx)?;
+    if req.method() != Method::Get && !origin_allowed(req, &config) {
+        return None;
+    }
+    session_user(req, &config)
+}
+
+fn bookmark_id(ctx: &RouteContext<RouteData>) -> Option<Id<MessageMarker>> {
+    ctx.param("id")?.parse().ok().and_then(Id::new_checked)
+}
+
+/// `GET /api/bookmarks?limit=&before=&q=`, authenticated with a feed token
+/// (`Authorization: Bearer <token>`) or a dashboard session.
+pub(crate) async fn list(req: Request, ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    let store = match BookmarkStore::new(ctx) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+
+    let token = req
+        .headers()
+        .get("Authorization")?
+        .and_then(|value| value.strip_prefix("Bearer ").map(str::to_string));
+    let user_id = match token {
+        Some(token) => match authenticate(&store, token.trim()).await {
+            Ok(user_id) => user_id,
+            Err(e) => return Response::error(e.to_string(), 500),
+        },
+        None => dashboard_user(&req, ctx),
+    };
+    let Some(user_id) = user_id else {
+        return Response::error("Unauthorized", 401);
+    };
+
+    let limit = query(&req, "limit")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(DEFAULT_PAGE)
+        .clamp(1, MAX_PAGE);
+    let before = before(&req);
+    let search = query(&req, "q")
+        .map(|q| q.trim().to_lowercase())
+        .filter(|q| !q.is_empty());
+
+    match page(&store, user_id, before, limit, search.as_deref()).await {
+        Ok(page) => Response::from_json(&page),
+        Err(e) => Response::error(e.to_string(), 500),
+    }
+}
+
+fn before(req: &Request) -> Option<Id<MessageMarker>> {
+    query(req, "before")
+        .and_then(|b| b.parse().ok())
+        .and_then(Id::<MessageMarker>::new_checked)
+}
+
+async fn page(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+    before: Option<Id<MessageMarker>>,
+    limit: usize,
+    search: Option<&str>,
+) -> Result<ApiPage, InteractionError> {
+    let (bookmarks, next) = newest(store, user_id, before, limit, search).await?;
+    Ok(ApiPage {
+        next,
+        bookmarks: bookmarks.iter().map(ApiBookmark::from_bookmark).collect(),
+    })
+}
+
+/// `GET /api/me`, who the dashboard is logged in as.
+pub(crate) fn me(req: Request, ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    match dashboard_user(&req, ctx) {
+        Some(user_id) => Response::from_json(&serde_json::json!({ "user_id": user_id })),
+        None => Response::error("Unauthorized", 401),
+    }
+}
+
+/// `PATCH /api/bookmarks/:id`, edits the note, tags, reminder and read state. The bookmark
+/// message shows the new state the next time one of its buttons is used.
+pub(crate) async fn edit(mut req: Request, ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    let Some(user_id) = dashboard_user(&req, ctx) else {
+        return Response::error("Unauthorized", 401);
+    };
+    let Some(id) = bookmark_id(ctx) else {
+        return Response::error("Not Found", 404);
+    };
+    let Ok(edit) = req.json::<Edit>().await else {
+        return Response::error("Bad Request", 400);
+    };
+
+    let store = match BookmarkStore::new(ctx) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    let mut bookmark = match store.get(user_id, id).await {
+        Ok(Some(bookmark)) => bookmark,
+        Ok(None) => return Response::error("Not Found", 404),
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+
+    let note_changed = edit.note.is_some() || edit.tags.is_some();
+    if let Some(note) = edit.note {
+        let note = note.trim();
+        if note.chars().count() > MAX_NOTE {
+            return Response::error("Notes are at most 1000 characters", 400);
+        }
+        bookmark.note = Some(note.to_string()).filter(|n| !n.is_empty());
+    }
+    if let Some(tags) = edit.tags {
+        match normalize_tags(tags) {
+            Ok(tags) => bookmark.tags = tags,
+            Err(message) => return Response::error(message, 400),
+        }
+    }
+
+    if let Some(remind_at) = edit.remind_at {
+        bookmark.remind_at = remind_at;
+    }
+    if let Some(state) = edit.read_state {
+        bookmark.read_state = state;
+    }
+
+    if let Err(e) = store.put(&bookmark).await {
+        return Response::error(e.to_string(), 500);
+    }
+    if note_changed {
+        emit_bookmark(ctx, EventKind::BookmarkNoteChanged, &bookmark).await;
+    }
+    Response::from_json(&ApiBookmark::from_bookmark(&bookmark))
+}
+
+/// `DELETE /api/bookmarks/:id`, moves the bookmark to the trash and removes its DM.
+pub(crate) async fn delete(req: Request, ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    let Some(user_id) = dashboard_user(&req, ctx) else {
+        return Response::error("Unauthorized", 401);
+    };
+    let Some(id) = bookmark_id(ctx) else {
+        return Response::error("Not Found", 404);
+    };
+
+    let store = match BookmarkStore::new(ctx) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    let bookmark = match store.get(user_id, id).await {
+        Ok(Some(bookmark)) => bookmark,
+        Ok(None) => return Response::error("Not Found", 404),
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    let (channel_id, message_id) = (bookmark.dm_channel_id, bookmark.dm_message_id);
+
+    emit_bookmark(ctx, EventKind::BookmarkDeleted, &bookmark).await;
+    if let Err(e) = store.trash(bookmark, Date::now().as_millis()).await {
+        return Response::error(e.to_string(), 500);
+    }
+
+    // The bookmark is in the trash either way, the message is only tidied up
+    match rest::bot_client(&ctx.env) {
+        Ok(client) => {
+            if let Err(err) = rest::delete_message(&client, channel_id, message_id).await {
+                console_log!("[API] deleting the bookmark message failed: {}", err);
+            }
+        }
+        Err(err) => console_log!("[API] no bot client: {}", err),
+    }
+    Ok(Response::empty()?.with_status(204))
+}
+
+/// `GET /api/export?before=`, the user's bookmarks as a JSON download. Each bookmark is a KV read,
+/// so they come `MAX_PAGE` at a time like `/api/bookmarks`, `next` points at the following page.
+pub(crate) async fn export(req: Request, ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    let Some(user_id) = dashboard_user(&req, ctx) else {
+        return Response::error("Unauthorized", 401);
+    };
+    let store = match BookmarkStore::new(ctx) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    let page = match page(&store, user_id, before(&req), MAX_PAGE, None).await {
+        Ok(page) => page,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/json")?;
+    headers.set("Content-Disposition", "attachment; filename=\"bookmarks.json\"")?;
+    Ok(Response::ok(serde_json::to_string(&page)?)?.with_headers(headers))
+}
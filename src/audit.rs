@@ -0,0 +1,48 @@
+use reqwest::Client;
+use worker::{console_log, RouteContext};
+
+use crate::RouteData;
+use crate::guild_config::GuildConfig;
+use crate::rest;
+use crate::store::StoredBookmark;
+
+// Who bookmarked what, never the content itself
+fn entry(bookmark: &StoredBookmark) -> String {
+    let first = &bookmark.messages[0];
+    let what = if bookmark.messages.len() > 1 {
+        format!(
+            "[a conversation of {} messages]({})",
+            bookmark.messages.len(),
+            bookmark.jump_url()
+        )
+    } else {
+        format!("[a message]({}) by <@{}>", bookmark.jump_url(), first.author.id)
+    };
+    format!(
+        "🔖 <@{}> bookmarked {} in <#{}> · <t:{}:f>",
+        bookmark.user_id,
+        what,
+        bookmark.channel_id,
+        bookmark.created_at / 1000
+    )
+}
+
+/// Posts a log entry to the server's log channel, when one is set. Logging is best-effort and
+/// never fails the bookmark.
+pub(crate) async fn log_bookmark(ctx: &RouteContext<RouteData>, client: &Client, bookmark: &StoredBookmark) {
+    let Some(guild_id) = bookmark.guild_id else {
+        return;
+    };
+    let Some(log_channel) = GuildConfig::for_guild(ctx, guild_id).await.log_channel else {
+        return;
+    };
+
+    let body = serde_json::json!({
+        "content": entry(bookmark),
+        // Log entries should not ping the people they mention
+        "allowed_mentions": { "parse": [] }
+    });
+    if let Err(err) = rest::create_message(client, log_channel, &body).await {
+        console_log!("[AUDIT] posting to {} failed: {}", log_channel, err);
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+    Id,
+};
+use worker::{console_log, Date, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::render::jump_url;
+use crate::rest;
+use crate::guild_state::{GuildOp, GuildRequest};
+use crate::store::{call_object, BookmarkStore, StoredBookmark};
+
+pub(crate) const DAY: u64 = 24 * 60 * 60 * 1000;
+pub(crate) const WEEK: u64 = 7 * DAY;
+
+// Messages tracked per server, the least bookmarked are dropped beyond this
+const MAX_TRACKED: usize = 1000;
+
+// Members remembered per message. Past this many every bookmark counts, far above any threshold
+// worth setting
+const MAX_MEMBERS: usize = 100;
+
+/// How many members bookmarked one message. Only ids are kept, the board never copies content out
+/// of the channel, and `/bookmarks top` only lists messages of channels the asker can view.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Tally {
+    pub(crate) channel_id: Id<ChannelMarker>,
+    pub(crate) author_id: Id<UserMarker>,
+    pub(crate) total: u32,
+    /// Who bookmarked it, so deleting and bookmarking again doesn't count twice
+    #[serde(default)]
+    pub(crate) members: Vec<Id<UserMarker>>,
+    /// When the message was bookmarked over the last week, in ms
+    #[serde(default)]
+    pub(crate) recent: Vec<u64>,
+    /// Whether it has been posted to the starboard
+    #[serde(default)]
+    pub(crate) starred: bool,
+}
+
+impl Tally {
+    pub(crate) fn count_since(&self, since: Option<u64>) -> u32 {
+        match since {
+            Some(since) => self.recent.iter().filter(|t| **t >= since).count() as u32,
+            None => self.total,
+        }
+    }
+}
+
+/// A change to or a question about a server's board. Applied in the server's `GuildState` when it
+/// is bound, so two bookmarks at once can't both post the same message to the starboard.
+#[derive(Deserialize, Serialize)]
+pub(crate) enum BoardOp {
+    /// Counts `user_id` for each (message, author) they haven't bookmarked before, returning the
+    /// messages that just reached `threshold` marked as starred
+    Record {
+        user_id: Id<UserMarker>,
+        channel_id: Id<ChannelMarker>,
+        messages: Vec<(Id<MessageMarker>, Id<UserMarker>)>,
+        now: u64,
+        threshold: Option<u32>,
+    },
+    /// Lets a message be starred again after posting it failed
+    Unstar(Id<MessageMarker>),
+    /// The most bookmarked messages since `since`, or of all time
+    Top { since: Option<u64>, limit: usize },
+}
+
+/// A message on the board with its count for the asked period.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct BoardEntry {
+    pub(crate) message_id: Id<MessageMarker>,
+    pub(crate) tally: Tally,
+    pub(crate) count: u32,
+}
+
+/// What applying a `BoardOp` returned and changed.
+#[derive(Debug, Default)]
+pub(crate) struct Applied {
+    pub(crate) entries: Vec<BoardEntry>,
+    pub(crate) updated: Vec<Id<MessageMarker>>,
+    pub(crate) removed: Vec<Id<MessageMarker>>,
+}
+
+/// Bookmark counts for one server.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Board {
+    #[serde(default)]
+    pub(crate) messages: HashMap<Id<MessageMarker>, Tally>,
+}
+
+impl Board {
+    fn key(guild_id: Id<GuildMarker>) -> String {
+        format!("board:{}", guild_id)
+    }
+
+    /// The board kept in KV, which `GuildState` starts from.
+    pub(crate) async fn load(
+        store: &BookmarkStore,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<Board, InteractionError> {
+        Ok(store
+            .get_value(&Self::key(guild_id))
+            .await?
+            .unwrap_or_default())
+    }
+
+    async fn save(&self, store: &BookmarkStore, guild_id: Id<GuildMarker>) -> Result<(), InteractionError> {
+        store.put_value(&Self::key(guild_id), self, None).await
+    }
+
+    /// Applies `op` to the server's board, in its Durable Object when bound and in KV otherwise,
+    /// where two bookmarks at once can still lose a count.
+    pub(crate) async fn update(
+        store: &BookmarkStore,
+        guild_id: Id<GuildMarker>,
+        op: BoardOp,
+    ) -> Result<Vec<BoardEntry>, InteractionError> {
+        if let Some(namespace) = store.guild_state() {
+            let request = GuildRequest {
+                guild_id,
+                op: GuildOp::Board(op),
+            };
+            return call_object(namespace, &guild_id.to_string(), &request).await;
+        }
+
+        let mut board = Self::load(store, guild_id).await?;
+        let applied = board.apply(op);
+        if !applied.updated.is_empty() || !applied.removed.is_empty() {
+            board.save(store, guild_id).await?;
+        }
+        Ok(applied.entries)
+    }
+
+    pub(crate) fn apply(&mut self, op: BoardOp) -> Applied {
+        let mut applied = Applied::default();
+        match op {
+            BoardOp::Record {
+                user_id,
+                channel_id,
+                messages,
+                now,
+                threshold,
+            } => {
+                for (message_id, author_id) in messages {
+                    let counted = self.messages.get(&message_id).is_some_and(|t| t.members.contains(&user_id));
+                    if counted {
+                        continue;
+                    }
+                    if let Some(evicted) = self.record(user_id, channel_id, message_id, author_id, now) {
+                        applied.updated.retain(|id| *id != evicted);
+                        applied.removed.push(evicted);
+                    }
+                    applied.updated.push(message_id);
+                    let tally = self.messages.get_mut(&message_id).expect("just recorded");
+                    if threshold.is_some_and(|threshold| !tally.starred && tally.total >= threshold) {
+                        tally.starred = true;
+                        applied.entries.push(BoardEntry {
+                            message_id,
+                            tally: tally.clone(),
+                            count: tally.total,
+                        });
+                    }
+                }
+            }
+            BoardOp::Unstar(message_id) => {
+                if let Some(tally) = self.messages.get_mut(&message_id).filter(|t| t.starred) {
+                    tally.starred = false;
+                    applied.updated.push(message_id);
+                }
+            }
+            BoardOp::Top { since, limit } => {
+                applied.entries = self
+                    .top(since, limit)
+                    .into_iter()
+                    .map(|(message_id, tally, count)| BoardEntry {
+                        message_id,
+                        tally: tally.clone(),
+                        count,
+                    })
+                    .collect();
+            }
+        }
+        applied
+    }
+
+    // Counts `user_id`'s bookmark of `message_id`, returning the message dropped to make room for it
+    fn record(
+        &mut self,
+        user_id: Id<UserMarker>,
+        channel_id: Id<ChannelMarker>,
+        message_id: Id<MessageMarker>,
+        author_id: Id<UserMarker>,
+        now: u64,
+    ) -> Option<Id<MessageMarker>> {
+        let mut evicted = None;
+        if !self.messages.contains_key(&message_id) && self.messages.len() >= MAX_TRACKED {
+            evicted = self
+                .messages
+                .iter()
+                .min_by_key(|(id, t)| (t.total, **id))
+                .map(|(id, _)| *id);
+            if let Some(least) = evicted {
+                self.messages.remove(&least);
+            }
+        }
+
+        let tally = self.messages.entry(message_id).or_insert(Tally {
+            channel_id,
+            author_id,
+            total: 0,
+            members: Vec::new(),
+            recent: Vec::new(),
+            starred: false,
+        });
+        tally.total += 1;
+        if tally.members.len() < MAX_MEMBERS {
+            tally.members.push(user_id);
+        }
+        tally.recent.retain(|t| now.saturating_sub(*t) < WEEK);
+        tally.recent.push(now);
+        evicted
+    }
+
+    /// The most bookmarked messages since `since`, or of all time.
+    pub(crate) fn top(&self, since: Option<u64>, limit: usize) -> Vec<(Id<MessageMarker>, &Tally, u32)> {
+        let mut top = self
+            .messages
+            .iter()
+            .map(|(id, tally)| (*id, tally, tally.count_since(since)))
+            .filter(|(_, _, count)| *count > 0)
+            .collect::<Vec<_>>();
+        top.sort_by(|a, b| b.2.cmp(&a.2).then(b.0.cmp(&a.0)));
+        top.truncate(limit);
+        top
+    }
+}
+
+fn starboard_entry(guild_id: Id<GuildMarker>, message_id: Id<MessageMarker>, tally: &Tally) -> serde_json::Value {
+    serde_json::json!({
+        "content": format!(
+            "⭐ **{}** bookmarks · a message by <@{}> in <#{}>\n{}",
+            tally.total,
+            tally.author_id,
+            tally.channel_id,
+            jump_url(Some(guild_id), tally.channel_id, message_id)
+        ),
+        "allowed_mentions": { "parse": [] }
+    })
+}
+
+/// Counts a new bookmark on its server's board and posts messages crossing the starboard
+/// threshold. Best-effort like the audit log.
+pub(crate) async fn record_bookmark(ctx: &RouteContext<RouteData>, client: &Client, bookmark: &StoredBookmark) {
+    let Some(guild_id) = bookmark.guild_id else {
+        return;
+    };
+    let Ok(store) = BookmarkStore::new(ctx) else {
+        return;
+    };
+    let config = GuildConfig::for_guild(ctx, guild_id).await;
+    let op = BoardOp::Record {
+        user_id: bookmark.user_id,
+        channel_id: bookmark.channel_id,
+        messages: bookmark.messages.iter().map(|m| (m.id, m.author.id)).collect(),
+        now: Date::now().as_millis(),
+        threshold: config.starboard_channel.map(|_| config.starboard_threshold),
+    };
+    let starred = match Board::update(&store, guild_id, op).await {
+        Ok(starred) => starred,
+        Err(err) => {
+            console_log!("[BOARD] recording failed: {}", err);
+            return;
+        }
+    };
+    let Some(starboard) = config.starboard_channel else {
+        return;
+    };
+
+    for entry in starred {
+        let body = starboard_entry(guild_id, entry.message_id, &entry.tally);
+        if let Err(err) = rest::create_message(client, starboard, &body).await {
+            console_log!("[BOARD] starboard post failed: {}", err);
+            if let Err(err) = Board::update(&store, guild_id, BoardOp::Unstar(entry.message_id)).await {
+                console_log!("[BOARD] unstarring failed: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNEL: u64 = 1;
+    const AUTHOR: u64 = 2;
+
+    fn record(board: &mut Board, messages: &[u64], user: u64, now: u64, threshold: Option<u32>) -> Applied {
+        board.apply(BoardOp::Record {
+            user_id: Id::new(user),
+            channel_id: Id::new(CHANNEL),
+            messages: messages.iter().map(|id| (Id::new(*id), Id::new(AUTHOR))).collect(),
+            now,
+            threshold,
+        })
+    }
+
+    fn ids(entries: &[BoardEntry]) -> Vec<u64> {
+        entries.iter().map(|e| e.message_id.get()).collect()
+    }
+
+    #[test]
+    fn messages_are_starred_once() {
+        let mut board = Board::default();
+        assert!(record(&mut board, &[10], 20, 0, Some(2)).entries.is_empty());
+        let applied = record(&mut board, &[10, 11], 21, 1, Some(2));
+        assert_eq!(ids(&applied.entries), [10]);
+        assert_eq!(applied.entries[0].count, 2);
+        assert_eq!(applied.updated.len(), 2);
+        assert!(record(&mut board, &[10], 22, 2, Some(2)).entries.is_empty());
+
+        // A failed post lets the next bookmark try again
+        assert_eq!(board.apply(BoardOp::Unstar(Id::new(10))).updated.len(), 1);
+        assert_eq!(ids(&record(&mut board, &[10], 23, 3, Some(2)).entries), [10]);
+        // Without a starboard nothing is starred
+        assert!(record(&mut board, &[11], 24, 4, None).entries.is_empty());
+        assert!(!board.messages[&Id::new(11)].starred);
+    }
+
+    #[test]
+    fn members_count_once() {
+        let mut board = Board::default();
+        // Bookmarking, deleting and bookmarking again
+        for now in 0..5 {
+            assert!(record(&mut board, &[10], 20, now, Some(2)).entries.is_empty());
+        }
+        assert_eq!(board.messages[&Id::new(10)].total, 1);
+        assert_eq!(board.messages[&Id::new(10)].recent, [0]);
+        assert!(record(&mut board, &[10], 20, 5, Some(2)).updated.is_empty());
+
+        assert_eq!(ids(&record(&mut board, &[10], 21, 6, Some(2)).entries), [10]);
+    }
+
+    #[test]
+    fn top_counts_the_period() {
+        let mut board = Board::default();
+        for user in 20..23 {
+            record(&mut board, &[10], user, 0, None);
+        }
+        for user in 20..22 {
+            record(&mut board, &[11], user, 3 * DAY, None);
+        }
+        record(&mut board, &[12], 20, 3 * DAY, None);
+
+        let top = |board: &mut Board, since, limit| board.apply(BoardOp::Top { since, limit }).entries;
+        assert_eq!(ids(&top(&mut board, None, 10)), [10, 11, 12]);
+        assert_eq!(ids(&top(&mut board, None, 2)), [10, 11]);
+        assert_eq!(ids(&top(&mut board, Some(DAY), 10)), [11, 12]);
+        assert_eq!(top(&mut board, Some(DAY), 10)[0].count, 2);
+
+        // Bookmarks older than a week only count towards all time
+        record(&mut board, &[12], 21, 9 * DAY, None);
+        assert_eq!(board.messages[&Id::new(10)].count_since(Some(2 * DAY)), 0);
+        assert_eq!(board.messages[&Id::new(12)].recent, [3 * DAY, 9 * DAY]);
+    }
+
+    #[test]
+    fn the_least_bookmarked_make_room() {
+        let mut board = Board::default();
+        let many: Vec<u64> = (1..=MAX_TRACKED as u64).map(|id| id + 100).collect();
+        record(&mut board, &many, 20, 0, None);
+        record(&mut board, &[101], 21, 0, None);
+
+        let applied = record(&mut board, &[5], 20, 0, None);
+        assert_eq!(applied.removed, [Id::new(102)]);
+        assert_eq!(applied.updated, [Id::new(5)]);
+        assert_eq!(board.messages.len(), MAX_TRACKED);
+        assert!(board.messages.contains_key(&Id::new(101)));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::RouteData;
 use crate::http::HttpError;
 use crate::error::Error;
 use crate::interaction::Context;
@@ -8,12 +9,12 @@ use twilight_model::http::interaction::InteractionResponse;
 
 pub struct App {
     req: Request, 
-    ctx: RouteContext<()>
+    ctx: RouteContext<RouteData>
 }
 
 impl App {
 
-    pub fn new(req: Request, ctx: RouteContext<()>) -> App {
+    pub fn new(req: Request, ctx: RouteContext<RouteData>) -> App {
         App{req, ctx}
     }
 
@@ -41,7 +42,7 @@ impl App {
         Ok(body)
     }
 
-    pub async fn handle_request(&mut self) -> Result<InteractionResponse, HttpError> {
+    pub async fn handle_request(mut self) -> Result<InteractionResponse, HttpError> {
         let body = self.validate_sig().await?;
 
         worker::console_log!("Request body : {}", body);
@@ -50,7 +51,19 @@ impl App {
         serde_json::from_str::<Interaction>(&body).map_err(Error::JsonFailed)?;
         
         worker::console_log!{"Request parsed : {}", serde_json::to_string_pretty(&interaction).unwrap()};
-        let handler  = Context {interaction};
+        let handler  = Context {interaction, body};
+        if let Some(response) = handler.rate_limited(&self.ctx).await {
+            return Ok(response);
+        }
+
+        // Slow handlers finish after the response, which only tells Discord to wait
+        if let Some(response) = handler.deferral(&mut self.ctx) {
+            let worker_ctx = self.ctx.data.clone();
+            let mut ctx = self.ctx;
+            worker_ctx.wait_until(async move { handler.perform_deferred(&mut ctx).await });
+            return Ok(response);
+        }
+
         let response = handler.perform(&mut self.ctx).await?;
         
         Ok(response)
@@ -0,0 +1,307 @@
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+    Id,
+};
+
+use crate::error::InteractionError;
+use crate::render::jump_url;
+use crate::store::{BookmarkStore, StoredBookmark};
+use crate::utils::random_token;
+
+// Discord caps embed fields and autocomplete choices at 25, collections hold a few pages of them
+pub(crate) const MAX_ITEMS: usize = 100;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CollectionRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl CollectionRole {
+    pub(crate) fn parse(role: &str) -> Option<CollectionRole> {
+        match role {
+            "viewer" => Some(CollectionRole::Viewer),
+            "editor" => Some(CollectionRole::Editor),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CollectionRole::Viewer => "viewer",
+            CollectionRole::Editor => "editor",
+            CollectionRole::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct CollectionMember {
+    pub(crate) user_id: Id<UserMarker>,
+    pub(crate) role: CollectionRole,
+}
+
+/// A code that lets anyone holding it join with `role`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Invite {
+    pub(crate) code: String,
+    pub(crate) role: CollectionRole,
+}
+
+/// A bookmarked message shared into a collection. Only what is needed to list and jump to it is
+/// copied, the member's own bookmark stays private.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct CollectionItem {
+    pub(crate) added_by: Id<UserMarker>,
+    pub(crate) added_at: u64,
+    pub(crate) guild_id: Option<Id<GuildMarker>>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    pub(crate) message_id: Id<MessageMarker>,
+    pub(crate) summary: String,
+}
+
+impl CollectionItem {
+    pub(crate) fn from_bookmark(bookmark: &StoredBookmark, added_by: Id<UserMarker>, now: u64) -> CollectionItem {
+        CollectionItem {
+            added_by,
+            added_at: now,
+            guild_id: bookmark.guild_id,
+            channel_id: bookmark.channel_id,
+            message_id: bookmark.messages[0].id,
+            summary: bookmark.summary(),
+        }
+    }
+
+    pub(crate) fn jump_url(&self) -> String {
+        jump_url(self.guild_id, self.channel_id, self.message_id)
+    }
+}
+
+/// A shared reading list, stored in the bookmark store next to personal bookmarks.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Collection {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) created_at: u64,
+    pub(crate) members: Vec<CollectionMember>,
+    #[serde(default)]
+    pub(crate) invites: Vec<Invite>,
+    #[serde(default)]
+    pub(crate) items: Vec<CollectionItem>,
+}
+
+impl Collection {
+    pub(crate) fn new(name: String, owner: Id<UserMarker>, now: u64) -> Collection {
+        Collection {
+            id: random_token(8),
+            name,
+            created_at: now,
+            members: vec![CollectionMember {
+                user_id: owner,
+                role: CollectionRole::Owner,
+            }],
+            invites: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    fn key(id: &str) -> String {
+        format!("collection:{}", id)
+    }
+
+    fn code_key(code: &str) -> String {
+        format!("collection-code:{}", code)
+    }
+
+    fn index_key(user_id: Id<UserMarker>) -> String {
+        format!("collections:{}", user_id)
+    }
+
+    pub(crate) fn role_of(&self, user_id: Id<UserMarker>) -> Option<CollectionRole> {
+        self.members
+            .iter()
+            .find(|m| m.user_id == user_id)
+            .map(|m| m.role)
+    }
+
+    /// Adds `user_id` or changes their role, the owner's role never changes. Returns whether they
+    /// were added, see `add_to_index`.
+    pub(crate) fn set_role(&mut self, user_id: Id<UserMarker>, role: CollectionRole) -> bool {
+        match self.members.iter_mut().find(|m| m.user_id == user_id) {
+            Some(member) if member.role == CollectionRole::Owner => false,
+            Some(member) => {
+                member.role = role;
+                false
+            }
+            None => {
+                self.members.push(CollectionMember { user_id, role });
+                true
+            }
+        }
+    }
+
+    pub(crate) fn invite(&mut self, role: CollectionRole) -> String {
+        let code = random_token(5);
+        self.invites.push(Invite {
+            code: code.clone(),
+            role,
+        });
+        code
+    }
+
+    /// Removes the invite with `code`, returning whether there was one.
+    pub(crate) fn revoke(&mut self, code: &str) -> bool {
+        let len = self.invites.len();
+        self.invites.retain(|i| i.code != code);
+        self.invites.len() != len
+    }
+
+    pub(crate) async fn load(
+        store: &BookmarkStore,
+        id: &str,
+    ) -> Result<Option<Collection>, InteractionError> {
+        store.get_value(&Self::key(id)).await
+    }
+
+    pub(crate) async fn by_code(
+        store: &BookmarkStore,
+        code: &str,
+    ) -> Result<Option<(Collection, CollectionRole)>, InteractionError> {
+        let Some(id) = store.get_value::<String>(&Self::code_key(code)).await? else {
+            return Ok(None);
+        };
+        let Some(collection) = Self::load(store, &id).await? else {
+            return Ok(None);
+        };
+        let role = collection
+            .invites
+            .iter()
+            .find(|i| i.code == code)
+            .map(|i| i.role);
+        Ok(role.map(|role| (collection, role)))
+    }
+
+    /// Stores the collection. Member indexes and invite codes are only written when they change,
+    /// with `add_to_index`, `save_code` and `delete_code`.
+    pub(crate) async fn save(&self, store: &BookmarkStore) -> Result<(), InteractionError> {
+        store.put_value(&Self::key(&self.id), self, None).await
+    }
+
+    /// Lists the collection for a member that was just added.
+    pub(crate) async fn add_to_index(
+        &self,
+        store: &BookmarkStore,
+        user_id: Id<UserMarker>,
+    ) -> Result<(), InteractionError> {
+        let mut ids = Self::ids_for(store, user_id).await?;
+        if ids.contains(&self.id) {
+            return Ok(());
+        }
+        ids.push(self.id.clone());
+        store.put_value(&Self::index_key(user_id), &ids, None).await
+    }
+
+    /// Makes a new invite `code` usable with `/collection join`.
+    pub(crate) async fn save_code(&self, store: &BookmarkStore, code: &str) -> Result<(), InteractionError> {
+        store.put_value(&Self::code_key(code), &self.id, None).await
+    }
+
+    /// Forgets a revoked invite `code`.
+    pub(crate) async fn delete_code(store: &BookmarkStore, code: &str) -> Result<(), InteractionError> {
+        store.delete_value(&Self::code_key(code)).await
+    }
+
+    /// Removes `user_id` from the collection. Collections whose owner leaves are deleted.
+    pub(crate) async fn leave(
+        mut self,
+        store: &BookmarkStore,
+        user_id: Id<UserMarker>,
+    ) -> Result<(), InteractionError> {
+        let mut ids = Self::ids_for(store, user_id).await?;
+        ids.retain(|id| *id != self.id);
+        store
+            .put_value(&Self::index_key(user_id), &ids, None)
+            .await?;
+
+        if self.role_of(user_id) != Some(CollectionRole::Owner) {
+            self.members.retain(|m| m.user_id != user_id);
+            return store.put_value(&Self::key(&self.id), &self, None).await;
+        }
+
+        for member in self.members.iter().filter(|m| m.user_id != user_id) {
+            let mut ids = Self::ids_for(store, member.user_id).await?;
+            ids.retain(|id| *id != self.id);
+            store
+                .put_value(&Self::index_key(member.user_id), &ids, None)
+                .await?;
+        }
+        for invite in &self.invites {
+            store.delete_value(&Self::code_key(&invite.code)).await?;
+        }
+        store.delete_value(&Self::key(&self.id)).await
+    }
+
+    async fn ids_for(
+        store: &BookmarkStore,
+        user_id: Id<UserMarker>,
+    ) -> Result<Vec<String>, InteractionError> {
+        Ok(store
+            .get_value(&Self::index_key(user_id))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The collections `user_id` is a member of.
+    pub(crate) async fn list_for(
+        store: &BookmarkStore,
+        user_id: Id<UserMarker>,
+    ) -> Result<Vec<Collection>, InteractionError> {
+        let mut collections = Vec::new();
+        for id in Self::ids_for(store, user_id).await? {
+            if let Some(collection) = Self::load(store, &id).await? {
+                collections.push(collection);
+            }
+        }
+        Ok(collections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_ordered_by_what_they_allow() {
+        assert!(CollectionRole::Viewer < CollectionRole::Editor);
+        assert!(CollectionRole::Editor < CollectionRole::Owner);
+        // Invites and role changes can't hand out ownership
+        assert_eq!(CollectionRole::parse("owner"), None);
+        assert_eq!(CollectionRole::parse("editor"), Some(CollectionRole::Editor));
+    }
+
+    #[test]
+    fn only_new_members_are_added() {
+        let (owner, member) = (Id::new(1), Id::new(2));
+        let mut collection = Collection::new("Reading".into(), owner, 0);
+        assert!(collection.set_role(member, CollectionRole::Viewer));
+        assert!(!collection.set_role(member, CollectionRole::Editor));
+        assert_eq!(collection.role_of(member), Some(CollectionRole::Editor));
+        assert!(!collection.set_role(owner, CollectionRole::Viewer));
+        assert_eq!(collection.role_of(owner), Some(CollectionRole::Owner));
+        assert_eq!(collection.members.len(), 2);
+    }
+
+    #[test]
+    fn revoked_invites_are_removed() {
+        let mut collection = Collection::new("Reading".into(), Id::new(1), 0);
+        let viewers = collection.invite(CollectionRole::Viewer);
+        let editors = collection.invite(CollectionRole::Editor);
+        assert!(collection.revoke(&viewers));
+        assert!(!collection.revoke(&viewers));
+        assert_eq!(collection.invites.len(), 1);
+        assert_eq!(collection.invites[0].code, editors);
+    }
+}
@@ -7,12 +7,13 @@ use twilight_model::{
         interaction::application_command::{CommandDataOption, CommandInteractionDataResolved, CommandOptionValue},
     },
     id::{
-        marker::{CommandMarker, GenericMarker, GuildMarker, ChannelMarker, UserMarker},
+        marker::{ApplicationMarker, CommandMarker, GenericMarker, GuildMarker, ChannelMarker, UserMarker},
         Id,
     }, guild::PartialMember
 };
 
-use crate::{commands, input::SharedInput};
+use crate::RouteData;
+use crate::commands;
 use crate::error::InteractionError;
 
 use async_trait::async_trait;
@@ -22,7 +23,11 @@ pub(crate) struct CommandInput<'a> {
     pub(crate) channel_id: Option<Id<ChannelMarker>>,
     pub(crate) user: Option<&'a twilight_model::user::User>,
     pub(crate) member: Option<&'a PartialMember>,
-    pub(crate) ctx: &'a mut worker::RouteContext<()>,
+    pub(crate) ctx: &'a mut worker::RouteContext<RouteData>,
+    pub(crate) body: &'a str,
+    // Lets a deferred command edit its response before it's done
+    pub(crate) application_id: Id<ApplicationMarker>,
+    pub(crate) token: &'a str,
 
     pub(crate) guild_id: Option<Id<GuildMarker>>,
     pub(crate) id: Id<CommandMarker>,
@@ -33,8 +38,6 @@ pub(crate) struct CommandInput<'a> {
     pub target_id: Option<Id<GenericMarker>>,
 }
 
-impl SharedInput<'_> for CommandInput<'_> {}
-
 #[allow(dead_code)]
 impl CommandInput<'_> {
     pub fn get_option(&self, name: &str) -> Option<CommandOptionValue> {
@@ -46,6 +49,22 @@ impl CommandInput<'_> {
         None
     }
 
+    /// The invoked subcommand path, i.e. `["trash", "restore"]`, and the options passed to it.
+    pub fn subcommand(&self) -> (Vec<&str>, &[CommandDataOption]) {
+        let mut path = Vec::new();
+        let mut options = self.options.as_slice();
+        while let Some(option) = options.first() {
+            match &option.value {
+                CommandOptionValue::SubCommandGroup(inner) | CommandOptionValue::SubCommand(inner) => {
+                    path.push(option.name.as_str());
+                    options = inner.as_slice();
+                }
+                _ => break,
+            }
+        }
+        (path, options)
+    }
+
     pub async fn kv_get(
         &self,
         namespace: &str,
@@ -110,6 +129,10 @@ impl CommandInput<'_> {
     }
 }
 
+pub(crate) fn find_option<'o>(options: &'o [CommandDataOption], name: &str) -> Option<&'o CommandOptionValue> {
+    options.iter().find(|o| o.name == name).map(|o| &o.value)
+}
+
 #[async_trait(?Send)]
 pub(crate) trait Command {
     async fn respond(
@@ -134,6 +157,12 @@ pub(crate) trait Command {
         CommandType::ChatInput
     }
 
+    fn deferred(&self, _input: &CommandInput) -> bool {
+        // Return true if responding may take longer than Discord's 3 seconds, the command then
+        // runs after an ephemeral "thinking" response and its reply replaces it
+        false
+    }
+
     async fn autocomplete(
         &self,
         _input: &CommandInput,
@@ -156,5 +185,12 @@ pub(crate) fn init_commands() -> Vec<Box<dyn Command + Sync>> {
     let mut v: Vec<Box<dyn Command + Sync>> = Vec::new();
     v.push(Box::new(commands::help::Help {}));
     v.push(Box::new(commands::bookmark::Bookmark {}));
+    v.push(Box::new(commands::bookmark::BookmarkLink {}));
+    v.push(Box::new(commands::range::BookmarkRange {}));
+    v.push(Box::new(commands::from_user::FromUser {}));
+    v.push(Box::new(commands::bookmarks::Bookmarks {}));
+    v.push(Box::new(commands::settings::UserSettings {}));
+    v.push(Box::new(commands::admin::BookmarkAdmin {}));
+    v.push(Box::new(commands::collection::Collections {}));
     v
 }
@@ -0,0 +1,202 @@
+use crate::command::{find_option, Command, CommandInput};
+use crate::delivery::ephemeral;
+use crate::commands::bookmarks::integration::ping;
+use crate::error::InteractionError;
+use crate::events::{parse_endpoint_url, Endpoint, SIGNATURE_HEADER};
+use crate::guild_config::{can_manage, set_listed, GuildConfig};
+use crate::store::BookmarkStore;
+
+use async_trait::async_trait;
+use twilight_model::application::command::CommandOption;
+use twilight_model::application::interaction::application_command::CommandOptionValue;
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_util::builder::command::{
+    BooleanBuilder, ChannelBuilder, IntegerBuilder, RoleBuilder, StringBuilder, SubCommandBuilder,
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+/// `/bookmark-admin`, the server config. Only members with Manage Server can use it, on top of
+/// whatever command permissions the server has set.
+pub(crate) struct BookmarkAdmin {}
+
+fn mentions<T: std::fmt::Display>(ids: &[T], prefix: &str) -> String {
+    if ids.is_empty() {
+        "None".to_string()
+    } else {
+        ids.iter()
+            .map(|id| format!("<{}{}>", prefix, id))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+fn overview(config: &GuildConfig) -> InteractionResponseData {
+    let embed = EmbedBuilder::new()
+        .title("Bookmark settings for this server")
+        .color(3092790)
+        .field(EmbedFieldBuilder::new(
+            "Disabled channels",
+            mentions(&config.disabled_channels, "#"),
+        ))
+        .field(EmbedFieldBuilder::new(
+            "Blocked roles",
+            mentions(&config.blocked_roles, "@&"),
+        ))
+        .field(
+            EmbedFieldBuilder::new(
+                "Attachments",
+                if config.strip_attachments {
+                    "Removed from bookmarks"
+                } else {
+                    "Included"
+                },
+            )
+            .inline(),
+        )
+        .field(
+            EmbedFieldBuilder::new(
+                "Log channel",
+                config
+                    .log_channel
+                    .map_or("None".to_string(), |id| format!("<#{}>", id)),
+            )
+            .inline(),
+        )
+        .field(
+            EmbedFieldBuilder::new(
+                "Integration",
+                config.endpoint.as_ref().map_or("None", |e| e.url.as_str()),
+            )
+            .inline(),
+        )
+        .field(
+            EmbedFieldBuilder::new(
+                "Starboard",
+                config.starboard_channel.map_or("None".to_string(), |id| {
+                    format!("<#{}> at {} bookmarks", id, config.starboard_threshold)
+                }),
+            )
+            .inline(),
+        )
+        .build();
+
+    InteractionResponseDataBuilder::new()
+        .embeds([embed])
+        .flags(MessageFlags::EPHEMERAL)
+        .build()
+}
+
+#[async_trait(?Send)]
+impl Command for BookmarkAdmin {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let Some(guild_id) = input.guild_id else {
+            return Ok(ephemeral("This command can only be used in a server"));
+        };
+        if !can_manage(input.member) {
+            return Ok(ephemeral("You need the Manage Server permission to use this command"));
+        }
+
+        let store = BookmarkStore::new(input.ctx)?;
+        let mut config = GuildConfig::load(&store, guild_id).await?;
+
+        let (path, options) = input.subcommand();
+        let enabled = match find_option(options, "enabled") {
+            Some(CommandOptionValue::Boolean(enabled)) => *enabled,
+            _ => true,
+        };
+        match (path.as_slice(), find_option(options, "channel"), find_option(options, "role")) {
+            (["view"], _, _) => return Ok(overview(&config)),
+            (["channel"], Some(CommandOptionValue::Channel(channel_id)), _) => {
+                set_listed(&mut config.disabled_channels, *channel_id, !enabled)
+            }
+            (["role"], _, Some(CommandOptionValue::Role(role_id))) => {
+                set_listed(&mut config.blocked_roles, *role_id, !enabled)
+            }
+            (["attachments"], _, _) => config.strip_attachments = !enabled,
+            (["log-channel"], channel, _) => {
+                config.log_channel = match channel {
+                    Some(CommandOptionValue::Channel(channel_id)) => Some(*channel_id),
+                    _ => None,
+                }
+            }
+            (["starboard"], channel, _) => {
+                config.starboard_channel = match channel {
+                    Some(CommandOptionValue::Channel(channel_id)) => Some(*channel_id),
+                    _ => None,
+                };
+                if let Some(CommandOptionValue::Integer(threshold)) = find_option(options, "threshold") {
+                    config.starboard_threshold = (*threshold).max(1) as u32;
+                }
+            }
+            (["integration"], _, _) => {
+                let url = match find_option(options, "url") {
+                    Some(CommandOptionValue::String(url)) => match parse_endpoint_url(url) {
+                        Some(url) => Some(url),
+                        None => return Ok(ephemeral("Events can only be sent to HTTPS URLs")),
+                    },
+                    _ => None,
+                };
+                config.endpoint = url.map(Endpoint::new);
+                config.save(&store, guild_id).await?;
+                let Some(endpoint) = config.endpoint.as_ref() else {
+                    return Ok(ephemeral("Events are no longer sent for this server"));
+                };
+                return Ok(ephemeral(format!(
+                    "Events about bookmarks in this server are now sent to {}.\nVerify them with the `{}` header using this secret, it won't be shown again: ||`{}`||\n{}",
+                    endpoint.url,
+                    SIGNATURE_HEADER,
+                    endpoint.secret,
+                    ping(endpoint).await
+                )));
+            }
+            _ => return Ok(ephemeral("Unknown subcommand")),
+        }
+
+        config.save(&store, guild_id).await?;
+        Ok(overview(&config))
+    }
+
+    fn name(&self) -> String {
+        "bookmark-admin".into()
+    }
+
+    fn description(&self) -> String {
+        "Configure bookmarking for this server".into()
+    }
+
+    fn options(&self) -> Option<Vec<CommandOption>> {
+        Some(vec![
+            SubCommandBuilder::new("view", "Show the current configuration").build(),
+            SubCommandBuilder::new("channel", "Allow or disallow bookmarking messages in a channel")
+                .option(ChannelBuilder::new("channel", "The channel").required(true))
+                .option(BooleanBuilder::new("enabled", "Whether its messages can be bookmarked").required(true))
+                .build(),
+            SubCommandBuilder::new("role", "Allow or disallow bookmarking messages from members with a role")
+                .option(RoleBuilder::new("role", "The role").required(true))
+                .option(BooleanBuilder::new("enabled", "Whether their messages can be bookmarked").required(true))
+                .build(),
+            SubCommandBuilder::new("attachments", "Include or remove attachments in bookmarks")
+                .option(BooleanBuilder::new("enabled", "Whether attachments are included").required(true))
+                .build(),
+            SubCommandBuilder::new("log-channel", "Set the channel bookmark activity is logged to")
+                .option(ChannelBuilder::new("channel", "Leave empty to stop logging"))
+                .build(),
+            SubCommandBuilder::new("starboard", "Announce messages once they are bookmarked often")
+                .option(ChannelBuilder::new("channel", "Leave empty to turn the starboard off"))
+                .option(
+                    IntegerBuilder::new("threshold", "Bookmarks needed, 5 by default")
+                        .min_value(1)
+                        .max_value(1000),
+                )
+                .build(),
+            SubCommandBuilder::new("integration", "Send signed bookmark events to a URL")
+                .option(StringBuilder::new("url", "The HTTPS URL, leave empty to stop sending events"))
+                .build(),
+        ])
+    }
+}
@@ -1,45 +1,78 @@
-use crate::command::{Command, CommandInput};
+use crate::RouteData;
+use crate::command::{find_option, Command, CommandInput};
+use crate::components::duplicate::offer;
+use crate::delivery::{deliver, ephemeral, NewBookmark};
 use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::input::default_components;
+use crate::permissions::can_read_history;
+use crate::render::{jump_url, message_embeds, raw_message, RawMessage};
+use crate::rest::{self, RestError};
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState};
 
 use async_trait::async_trait;
-use twilight_model::channel::message::sticker::{MessageSticker, StickerFormatType};
-
-use crate::input::SharedInput;
 use regex::Regex;
-use reqwest::StatusCode;
-use twilight_model::application::command::CommandType;
-use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle};
-use twilight_model::channel::message::{Component, Embed, MessageFlags, ReactionType, Sticker};
-use twilight_model::guild::Guild;
+use reqwest::Client;
+use twilight_model::application::command::{CommandOption, CommandType};
+use twilight_model::application::interaction::application_command::CommandOptionValue;
+use twilight_model::channel::Message;
 use twilight_model::http::interaction::InteractionResponseData;
-use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker};
 use twilight_model::id::Id;
-use twilight_util::builder::embed::{
-    EmbedAuthorBuilder, EmbedBuilder, EmbedFooterBuilder, ImageSource,
-};
-use twilight_util::builder::InteractionResponseDataBuilder;
-use twilight_validate::embed::embed as validate_embed;
-use worker::console_log;
-
-fn replace_links_with_markdown(text: &str) -> String {
-    let mdlink_regex = Regex::new(r#"\[.*?\]\(.*?\)"#).unwrap();
-    let mut replaced_text = text.to_string();
-
-    // Match and replace existing markdown links
-    // for mdlink in mdlink_regex.find_iter(text) {
-
-    //     let url = &text[mdlink.start()..mdlink.end()];
-    //     let markdown_link = format!("[{}]({})", url, url);
-    //     replaced_text = replaced_text.replace(&text[mdlink.start()..mdlink.end()], &markdown_link);
-    // }
+use twilight_util::builder::command::StringBuilder;
+use worker::{console_log, RouteContext};
+
+/// Checks the server's rules and delivers `msg_data` as a bookmark, for the message command and
+/// `/bookmark link` alike.
+async fn bookmark_message(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    user_id: Id<UserMarker>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    mut msg_data: Message,
+    raw: Option<RawMessage>,
+) -> Result<InteractionResponseData, InteractionError> {
+    let config = GuildConfig::for_guild(ctx, guild_id).await;
+    if config.channel_disabled(channel_id) {
+        return Ok(ephemeral("Bookmarking is disabled in this channel"));
+    }
+    if config.author_blocked(client, guild_id, &msg_data).await? {
+        return Ok(ephemeral("Messages from this member can't be bookmarked in this server"));
+    }
+    config.strip(&mut msg_data);
 
-    // Match and replace non-markdown links.
-    let link_regex = Regex::new(r#"(?:[^\(])(?P<url>https?://[^\s]+)"#).unwrap();
-    replaced_text = link_regex
-        .replace_all(&replaced_text, "[${url}](${url})")
-        .to_string();
+    // A second bookmark of the same message would be an identical DM, offer the first one
+    if let Ok(store) = BookmarkStore::new(ctx) {
+        if let Some(existing) = store.find_by_source(user_id, msg_data.id).await? {
+            return Ok(offer(&existing));
+        }
+    }
 
-    replaced_text
+    let t_url = jump_url(Some(guild_id), channel_id, msg_data.id);
+    let guild = rest::get_guild(client, guild_id).await?;
+
+    let settings = Settings::for_user(ctx, user_id).await;
+    let embeds = message_embeds(
+        ctx,
+        client,
+        &msg_data,
+        raw.as_ref(),
+        &guild,
+        &t_url,
+        &settings,
+    )
+    .await;
+    let components = default_components(&t_url, false, ReadState::Unread);
+
+    let bookmark = NewBookmark {
+        user_id,
+        guild_id: Some(guild_id),
+        channel_id,
+        messages: vec![msg_data],
+    };
+    deliver(ctx, client, bookmark, embeds, components, &settings).await
 }
 
 pub(crate) struct Bookmark {}
@@ -50,284 +83,165 @@ impl Command for Bookmark {
         &self,
         input: &CommandInput,
     ) -> Result<InteractionResponseData, InteractionError> {
-        if input.guild_id.is_none() {
-            return Ok(InteractionResponseDataBuilder::new()
-                .content("This command can only be used in a server")
-                .flags(MessageFlags::EPHEMERAL)
-                .build());
-        }
+        let (Some(guild_id), Some(channel_id)) = (input.guild_id, input.channel_id) else {
+            return Ok(ephemeral("This command can only be used in a server"));
+        };
 
         console_log!("Starting...");
 
         let client = input.http_client()?;
-        // Create a new dm channel
-        let url = format!("https://discord.com/api/v10/users/@me/channels");
-
-        let serialized = serde_json::to_string(&serde_json::json!({
-            "recipient_id": input.uid()?.to_string()
-        }))?;
-        console_log!("serialized: {:?}", serialized);
-        if let Ok(response) = client.post(url).body(serialized).send().await {
-            let status = response.status();
-            let text: String = response.text().await?;
-            console_log!("[CREATE DM CHANNEL] response: {:?}", text);
-            match status {
-                StatusCode::OK => {
-                    let channel_id: String = match serde_json::from_str::<serde_json::Value>(&text)?
-                        .get("id")
-                    {
-                        Some(id) => id.as_str().unwrap().to_string(),
-                        None => {
-                            return Ok(InteractionResponseDataBuilder::new()
-                                .content("An error occured while creating a dm channel with you")
-                                .flags(MessageFlags::EPHEMERAL)
-                                .build())
-                        }
-                    };
-
-                    let url = format!(
-                        "https://discord.com/api/v10/channels/{}/messages",
-                        channel_id
-                    );
 
-                    let og_msg_id = Id::<MessageMarker>::new(input.target_id.unwrap().get());
-                    let msg_data = input
-                        .resolved
-                        .as_ref()
-                        .unwrap()
-                        .messages
-                        .get(&og_msg_id)
-                        .expect("Message not found in resolved");
-                    let t_url = format!(
-                        "https://discord.com/channels/{}/{}/{}",
-                        input.guild_id.unwrap(),
-                        input.channel_id.unwrap(),
-                        og_msg_id
-                    );
-                    let components = input.default_components(&t_url);
-                    let mut embeds = msg_data
-                        .embeds
-                        .clone()
-                        .into_iter()
-                        .filter(|e| e.kind == "rich")
-                        .collect::<Vec<Embed>>();
+        let og_msg_id = Id::<MessageMarker>::new(input.target_id.unwrap().get());
+        let msg_data = input
+            .resolved
+            .as_ref()
+            .unwrap()
+            .messages
+            .get(&og_msg_id)
+            .expect("Message not found in resolved")
+            .clone();
+        let raw = raw_message(input.body, og_msg_id);
+
+        bookmark_message(input.ctx, &client, input.uid()?, guild_id, channel_id, msg_data, raw).await
+    }
 
-                    let guild_text = client
-                        .get(format!(
-                            "https://discord.com/api/v10/guilds/{}",
-                            input.guild_id.unwrap()
-                        ))
-                        .send()
-                        .await?
-                        .text()
-                        .await?;
-                    let guild: Guild = serde_json::from_str(&guild_text)?;
-                    let mut author = EmbedAuthorBuilder::new(format!("{} ({})", msg_data.author.name, msg_data.author.id.get()));
+    fn name(&self) -> String {
+        "Bookmark".into()
+    }
 
-                    if let Some(icon) = msg_data.author.avatar {
-                        author = author.icon_url(
-                            ImageSource::url(format!(
-                                "https://cdn.discordapp.com/avatars/{}/{}.png",
-                                msg_data.author.id.get(),
-                                icon
-                            ))
-                            .unwrap(),
-                        );
-                    } else {
-                        author = author.icon_url(
-                            ImageSource::url(format!(
-                                "https://cdn.discordapp.com/embed/avatars/{}.png",
-                                msg_data.author.discriminator % 5
-                            ))
-                            .unwrap(),
-                        );
-                    }
+    fn deferred(&self, _input: &CommandInput) -> bool {
+        // Rendering can archive attachments, which may take longer than Discord waits
+        true
+    }
 
-                    let mut footer =
-                        EmbedFooterBuilder::new(format!("{} ({})", guild.name, guild.id.get()));
+    fn kind(&self) -> CommandType {
+        CommandType::Message
+    }
+}
 
-                    if let Some(icon) = guild.icon {
-                        footer = footer.icon_url(
-                            ImageSource::url(format!(
-                                "https://cdn.discordapp.com/icons/{}/{}.png",
-                                guild.id.get(),
-                                icon
-                            ))
-                            .unwrap(),
-                        );
-                    } else {
-                        footer = footer.icon_url(
-                            ImageSource::url("https://cdn.discordapp.com/embed/avatars/0.png")
-                                .unwrap(),
-                        );
-                    }
+/// Where a message link points, `guild_id` is `None` for links to DMs (`/channels/@me/...`).
+struct MessageLink {
+    guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+}
 
-                    if msg_data.content.len() > 0 {
-                        
-                        embeds.insert(
-                            0,
-                            EmbedBuilder::new()
-                                .description(msg_data.content.clone())
-                                .build(),
-                        );
-                    };
+fn parse_link(link: &str) -> Option<MessageLink> {
+    let link_regex = Regex::new(
+        r#"^<?https?://(?:(?:www|ptb|canary)\.)?discord(?:app)?\.com/channels/(@me|\d+)/(\d+)/(\d+)/?(?:[?#]\S*)?>?$"#,
+    )
+    .unwrap();
+    let captures = link_regex.captures(link.trim())?;
+    let id = |i: usize| captures[i].parse::<u64>().ok();
+    let guild_id = match &captures[1] {
+        "@me" => None,
+        guild => Some(Id::new_checked(guild.parse().ok()?)?),
+    };
+    Some(MessageLink {
+        guild_id,
+        channel_id: Id::new_checked(id(2)?)?,
+        message_id: Id::new_checked(id(3)?)?,
+    })
+}
 
-                    let can_add = |embed: &Embed, desc: &String| {
-                        let mut temp = embed.clone();
-                        temp.description = Some(temp.description.unwrap_or_default() + &desc);
-                        validate_embed(&temp).is_ok()
-                    };
-                    
-                    for mut embed in embeds.iter_mut() {
-                        embed.description = embed.description.as_ref().map(|s| {
-                            replace_links_with_markdown(&s)
-                        });
-                    }
-                    // Attachments text
-                    let attachments = msg_data.attachments.clone();
+/// `/bookmark link:<message link>`, for messages the context menu can't reach, i.e. on mobile or
+/// in another channel.
+pub(crate) struct BookmarkLink {}
 
-                    if attachments.len() > 0 {
-                        if embeds.len() == 0 {
-                            embeds.push(EmbedBuilder::new().build());
-                        }
-                        let fmt = attachments
-                            .iter()
-                            .map(|a| format!("[{}]({})", a.filename, a.url))
-                            .collect::<Vec<String>>()
-                            .join("\n> ");
+#[async_trait(?Send)]
+impl Command for BookmarkLink {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let link = match find_option(&input.options, "link") {
+            Some(CommandOptionValue::String(link)) => link,
+            _ => return Ok(ephemeral("Paste the link of the message to bookmark")),
+        };
+        let Some(link) = parse_link(link) else {
+            return Ok(ephemeral(
+                "That is not a message link, use **Copy Message Link** on the message to get one",
+            ));
+        };
+        let Some(guild_id) = link.guild_id else {
+            return Ok(ephemeral("Only messages in servers can be bookmarked"));
+        };
+
+        let user_id = input.uid()?;
+        let client = input.http_client()?;
 
-                        let attachment_desc = format!("\n**Attachments:**\n> {}", fmt);
+        // The bot can read far more than most members, never copy a message the member can't see
+        let guild = match rest::get_guild(&client, guild_id).await {
+            Ok(guild) => guild,
+            Err(RestError::Forbidden | RestError::NotFound) => {
+                return Ok(ephemeral("I'm not in the server of that message"))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if !can_read_history(&client, &guild, link.channel_id, user_id).await? {
+            return Ok(ephemeral("You can't read the channel of that message"));
+        }
 
-                        if can_add(&embeds[0], &attachment_desc) {
-                            embeds[0].description = Some(format!(
-                                "{}{}",
-                                embeds[0].description.clone().unwrap_or_default(),
-                                attachment_desc
-                            ));
-                        } else if can_add(&embeds[embeds.len() - 1], &attachment_desc) {
-                            let last = embeds.len() - 1;
-                            embeds[last].description = Some(format!(
-                                "{}{}",
-                                embeds[last].description.clone().unwrap_or_default(),
-                                attachment_desc
-                            ));
-                        } else {
-                            embeds.push(EmbedBuilder::new().description(attachment_desc).build());
-                        }
-                    }
-                    // Stickers text
-                    let stickers = msg_data.sticker_items.clone();
-                    let make_sticker_url = |s: &MessageSticker| {
-                        let typ = match s.format_type {
-                            StickerFormatType::Png => "png",
-                            StickerFormatType::Apng => "png",
-                            StickerFormatType::Lottie => "json",
-                            StickerFormatType::Gif => "gif",
-                            _ => "png",
-                        };
-                        format!(
-                            "https://media.discordapp.net/stickers/{}.{}",
-                            s.id.get(),
-                            typ
-                        )
-                    };
-                    if stickers.len() == 1 && stickers[0].format_type != StickerFormatType::Lottie {
-                        embeds.push(
-                            EmbedBuilder::new()
-                                .image(ImageSource::url(make_sticker_url(&stickers[0])).unwrap())
-                                .build(),
-                        );
-                    } else if stickers.len() > 1 {
-                        let mut desc = String::new();
-                        for sticker in stickers {
-                            desc.push_str(&format!(
-                                "[{}]({})\n",
-                                sticker.name,
-                                make_sticker_url(&sticker)
-                            ));
-                        }
-                        embeds.push(EmbedBuilder::new().description(desc).build());
-                    }
-                    // Overiding the footer(server) and author data
-                    embeds[0].author = Some(author.build());
-                    embeds[0].footer = Some(footer.build());
+        let value = match rest::get_message_json(&client, link.channel_id, link.message_id).await {
+            Ok(value) => value,
+            Err(RestError::Forbidden) => {
+                return Ok(ephemeral("I can't read the message history of that channel"))
+            }
+            Err(RestError::NotFound) => return Ok(ephemeral("That message could not be found")),
+            Err(err) => return Err(err.into()),
+        };
+        let msg_data: Message = serde_json::from_value(value.clone())?;
+        let raw = serde_json::from_value(value).ok();
 
-                    let serialized = serde_json::to_string(&serde_json::json!({
-                        "embeds": embeds,
-                        "components": components
-                    }))?;
+        bookmark_message(input.ctx, &client, user_id, guild_id, link.channel_id, msg_data, raw).await
+    }
 
-                    if let Ok(response) = client.post(url).body(serialized).send().await {
-                        let status = response.status();
-                        console_log!("text: {:?}", &response.text().await);
-                        match status {
-                            StatusCode::OK => {
-                                return Ok(InteractionResponseDataBuilder::new()
-                                    .components(vec![Component::ActionRow(ActionRow {
-                                        components: vec![Component::Button(Button {
-                                            custom_id: Some("bookmark".to_string()),
-                                            disabled: true,
-                                            emoji: Some(ReactionType::Unicode {
-                                                name: "🔖".to_string(),
-                                            }),
-                                            label: Some("Bookmarked".to_string()),
-                                            style: ButtonStyle::Primary,
-                                            url: None,
-                                        })],
-                                    })])
-                                    .flags(MessageFlags::EPHEMERAL)
-                                    .build())
-                            }
+    fn name(&self) -> String {
+        "bookmark".into()
+    }
 
-                            StatusCode::FORBIDDEN => {
-                                return Ok(InteractionResponseDataBuilder::new()
-                                    .content("Open your dms in this server to use this command")
-                                    .flags(MessageFlags::EPHEMERAL)
-                                    .build())
-                            }
+    fn deferred(&self, _input: &CommandInput) -> bool {
+        // Rendering can archive attachments, which may take longer than Discord waits
+        true
+    }
 
-                            _ => {
-                                return Ok(InteractionResponseDataBuilder::new()
-                                    .content(
-                                        "An error occured while sending a message in this channel",
-                                    )
-                                    .flags(MessageFlags::EPHEMERAL)
-                                    .build())
-                            }
-                        }
-                    }
-                }
+    fn description(&self) -> String {
+        "Bookmark a message by its link".into()
+    }
 
-                StatusCode::FORBIDDEN => {
-                    return Ok(InteractionResponseDataBuilder::new()
-                        .content("The bot is not authorized to create a dm channel with you")
-                        .flags(MessageFlags::EPHEMERAL)
-                        .build())
-                }
+    fn options(&self) -> Option<Vec<CommandOption>> {
+        Some(vec![StringBuilder::new("link", "The message link, from Copy Message Link")
+            .required(true)
+            .build()])
+    }
+}
 
-                err => {
-                    return Ok(InteractionResponseDataBuilder::new()
-                        .content(format!(
-                            "An error occured while creating a dm channel with you ({:?})",
-                            err
-                        ))
-                        .flags(MessageFlags::EPHEMERAL)
-                        .build())
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(InteractionResponseDataBuilder::new()
-            .content("Open your dms to the bot")
-            .flags(MessageFlags::EPHEMERAL)
-            .build())
+    fn parsed(link: &str) -> Option<(Option<u64>, u64, u64)> {
+        parse_link(link).map(|l| (l.guild_id.map(Id::get), l.channel_id.get(), l.message_id.get()))
     }
 
-    fn name(&self) -> String {
-        "Bookmark".into()
+    #[test]
+    fn message_links_are_parsed() {
+        assert_eq!(parsed("https://discord.com/channels/1/2/3"), Some((Some(1), 2, 3)));
+        assert_eq!(parsed("https://discord.com/channels/@me/2/3"), Some((None, 2, 3)));
+        // Other clients, the old domain, surrounding spaces and suppressed embeds
+        assert_eq!(parsed("https://ptb.discord.com/channels/1/2/3"), Some((Some(1), 2, 3)));
+        assert_eq!(parsed("https://canary.discordapp.com/channels/1/2/3/"), Some((Some(1), 2, 3)));
+        assert_eq!(parsed("  <https://discord.com/channels/1/2/3>  "), Some((Some(1), 2, 3)));
+        assert_eq!(parsed("https://discord.com/channels/1/2/3?foo=bar"), Some((Some(1), 2, 3)));
     }
 
-    fn kind(&self) -> CommandType {
-        CommandType::Message
+    #[test]
+    fn other_links_are_refused() {
+        assert_eq!(parsed("https://discord.com/channels/1/2"), None);
+        assert_eq!(parsed("https://example.com/channels/1/2/3"), None);
+        assert_eq!(parsed("https://discord.com.example.com/channels/1/2/3"), None);
+        assert_eq!(parsed("see https://discord.com/channels/1/2/3"), None);
+        assert_eq!(parsed("https://discord.com/channels/0/2/3"), None);
+        assert_eq!(parsed("https://discord.com/channels/1/2/99999999999999999999"), None);
     }
 }
@@ -0,0 +1,39 @@
+use crate::command::CommandInput;
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::feed::{feed_url, issue, revoke as revoke_token};
+use crate::store::BookmarkStore;
+
+use twilight_model::http::interaction::InteractionResponseData;
+use worker::Date;
+
+pub(crate) async fn create(input: &CommandInput<'_>) -> Result<InteractionResponseData, InteractionError> {
+    let store = BookmarkStore::new(input.ctx)?;
+    let user_id = input.uid()?;
+    let token = issue(&store, user_id, Date::now().as_millis()).await?;
+
+    let link = |name: &str, route: &str, extension: &str| {
+        match feed_url(input.ctx, route, user_id, &format!("{}.{}", token, extension)) {
+            Some(url) => format!("{}: ||<{}>||", name, url),
+            None => format!(
+                "{}: `/{}/{}/<token>.{}` on this bot's URL",
+                name, route, user_id, extension
+            ),
+        }
+    };
+    Ok(ephemeral(format!(
+        "Your token, it won't be shown again and replaces any previous one: ||`{}`||\n{}\n{}\nJSON API: `GET /api/bookmarks` with the header `Authorization: Bearer <token>`",
+        token,
+        link("Atom feed", "feed", "atom"),
+        link("Reminder calendar", "calendar", "ics")
+    )))
+}
+
+pub(crate) async fn revoke(input: &CommandInput<'_>) -> Result<InteractionResponseData, InteractionError> {
+    let store = BookmarkStore::new(input.ctx)?;
+    if revoke_token(&store, input.uid()?).await? {
+        Ok(ephemeral("Your feed token no longer works"))
+    } else {
+        Ok(ephemeral("You have no feed token"))
+    }
+}
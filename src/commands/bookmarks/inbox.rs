@@ -0,0 +1,66 @@
+use crate::command::CommandInput;
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::store::{BookmarkStore, ReadState};
+
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+// Discord caps embed fields at 25
+const MAX_ENTRIES: usize = 25;
+
+/// Unread bookmarks, oldest first so the queue is read in the order it was filled.
+pub(crate) async fn inbox(input: &CommandInput<'_>) -> Result<InteractionResponseData, InteractionError> {
+    let store = BookmarkStore::new(input.ctx)?;
+    let user_id = input.uid()?;
+
+    let (ids, complete) = store.list_unread(user_id).await?;
+    let mut unread = Vec::new();
+    let mut stale = 0;
+    for id in &ids {
+        if unread.len() == MAX_ENTRIES {
+            break;
+        }
+        match store.get(user_id, *id).await? {
+            Some(bookmark) if bookmark.read_state == ReadState::Unread => unread.push(bookmark),
+            // Read in the meantime, or gone
+            _ => {
+                store.forget_unread(user_id, *id).await?;
+                stale += 1;
+            }
+        }
+    }
+    if unread.is_empty() && complete {
+        return Ok(ephemeral("Your inbox is empty, nothing left to read"));
+    }
+
+    let count = ids.len() - stale;
+    let description = if !complete {
+        format!("{} unread so far, older bookmarks are still being counted", count)
+    } else if count > MAX_ENTRIES {
+        format!("{} unread, showing the oldest {}", count, MAX_ENTRIES)
+    } else {
+        format!("{} unread", count)
+    };
+    let mut embed = EmbedBuilder::new()
+        .title("Inbox")
+        .description(description)
+        .color(3092790);
+    for bookmark in &unread {
+        embed = embed.field(EmbedFieldBuilder::new(
+            bookmark.summary(),
+            format!(
+                "Saved <t:{}:R> · [original]({})",
+                bookmark.created_at / 1000,
+                bookmark.jump_url()
+            ),
+        ));
+    }
+
+    Ok(InteractionResponseDataBuilder::new()
+        .embeds([embed.build()])
+        .flags(MessageFlags::EPHEMERAL)
+        .build())
+}
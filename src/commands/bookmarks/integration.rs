@@ -0,0 +1,66 @@
+use crate::command::{find_option, CommandInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::events::{parse_endpoint_url, send, Endpoint, Event, EventKind, SIGNATURE_HEADER};
+use crate::settings::Settings;
+use crate::store::BookmarkStore;
+
+use twilight_model::application::interaction::application_command::{
+    CommandDataOption, CommandOptionValue,
+};
+use twilight_model::http::interaction::InteractionResponseData;
+use worker::Date;
+
+/// Sends a `ping` event, reporting whether the endpoint accepted it.
+pub(crate) async fn ping(endpoint: &Endpoint) -> String {
+    let body = serde_json::to_string(&Event::new(EventKind::Ping, None)).unwrap_or_default();
+    match send(endpoint, &body, Date::now().as_millis() / 1000).await {
+        Ok(()) => format!("{} accepted a test event", endpoint.url),
+        Err(err) => format!("The test event failed: {}", err),
+    }
+}
+
+pub(crate) async fn set(
+    input: &CommandInput<'_>,
+    options: &[CommandDataOption],
+) -> Result<InteractionResponseData, InteractionError> {
+    let Some(CommandOptionValue::String(url)) = find_option(options, "url") else {
+        return Ok(ephemeral("Enter the URL events should be sent to"));
+    };
+    let Some(url) = parse_endpoint_url(url) else {
+        return Ok(ephemeral("Events can only be sent to HTTPS URLs"));
+    };
+
+    let store = BookmarkStore::new(input.ctx)?;
+    let mut settings = Settings::load(&store, input.uid()?).await?;
+    let endpoint = Endpoint::new(url);
+    settings.endpoint = Some(endpoint.clone());
+    settings.save(&store, input.uid()?).await?;
+
+    Ok(ephemeral(format!(
+        "Events about your bookmarks are now sent to {}.\nVerify them with the `{}` header using this secret, it won't be shown again: ||`{}`||\n{}",
+        endpoint.url,
+        SIGNATURE_HEADER,
+        endpoint.secret,
+        ping(&endpoint).await
+    )))
+}
+
+pub(crate) async fn remove(input: &CommandInput<'_>) -> Result<InteractionResponseData, InteractionError> {
+    let store = BookmarkStore::new(input.ctx)?;
+    let mut settings = Settings::load(&store, input.uid()?).await?;
+    if settings.endpoint.take().is_none() {
+        return Ok(ephemeral("You have no integration set up"));
+    }
+    settings.save(&store, input.uid()?).await?;
+    Ok(ephemeral("Events are no longer sent for your bookmarks"))
+}
+
+pub(crate) async fn test(input: &CommandInput<'_>) -> Result<InteractionResponseData, InteractionError> {
+    let store = BookmarkStore::new(input.ctx)?;
+    let settings = Settings::load(&store, input.uid()?).await?;
+    match settings.endpoint {
+        Some(endpoint) => Ok(ephemeral(ping(&endpoint).await)),
+        None => Ok(ephemeral("Set up an integration with `/bookmarks integration set` first")),
+    }
+}
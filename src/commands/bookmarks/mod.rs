@@ -0,0 +1,109 @@
+use crate::command::{Command, CommandInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+
+use async_trait::async_trait;
+use twilight_model::application::command::CommandOption;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_util::builder::command::{ChannelBuilder, StringBuilder, SubCommandBuilder, SubCommandGroupBuilder};
+
+pub mod feed;
+pub mod inbox;
+pub mod integration;
+pub mod pins;
+pub mod top;
+pub mod trash;
+
+/// `/bookmarks`, managing saved bookmarks. Each subcommand group lives in its own module.
+pub(crate) struct Bookmarks {}
+
+#[async_trait(?Send)]
+impl Command for Bookmarks {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let (path, options) = input.subcommand();
+        match path.as_slice() {
+            ["inbox"] => inbox::inbox(input).await,
+            ["top"] => top::top(input, options).await,
+            ["pins"] => pins::start(input, options).await,
+            ["feed", "create"] => feed::create(input).await,
+            ["feed", "revoke"] => feed::revoke(input).await,
+            ["integration", "set"] => integration::set(input, options).await,
+            ["integration", "remove"] => integration::remove(input).await,
+            ["integration", "test"] => integration::test(input).await,
+            ["trash", "list"] => trash::list(input).await,
+            ["trash", "restore"] => trash::restore(input, options).await,
+            _ => Ok(ephemeral("Unknown subcommand")),
+        }
+    }
+
+    fn name(&self) -> String {
+        "bookmarks".into()
+    }
+
+    fn deferred(&self, input: &CommandInput) -> bool {
+        // Both render and send bookmarks, archiving their attachments on the way, top checks
+        // which channels the member can view and inbox reads up to a hundred bookmarks
+        matches!(
+            input.subcommand().0.as_slice(),
+            ["inbox"] | ["pins"] | ["trash", "restore"] | ["top"]
+        )
+    }
+
+    fn description(&self) -> String {
+        "Manage your bookmarks".into()
+    }
+
+    fn options(&self) -> Option<Vec<CommandOption>> {
+        Some(vec![
+            SubCommandBuilder::new("inbox", "Your unread bookmarks, oldest first").build(),
+            SubCommandBuilder::new("top", "The most bookmarked messages of this server")
+                .option(
+                    StringBuilder::new("period", "Which bookmarks to count, this week by default")
+                        .choices([("Today", "day"), ("This week", "week"), ("All time", "all")]),
+                )
+                .build(),
+            SubCommandBuilder::new("pins", "Bookmark every pinned message of a channel")
+                .option(ChannelBuilder::new("channel", "This channel by default"))
+                .build(),
+            SubCommandGroupBuilder::new("feed", "Read your bookmarks in a feed reader or script")
+                .subcommands([
+                    SubCommandBuilder::new("create", "Create a token for the Atom feed, reminder calendar and JSON API"),
+                    SubCommandBuilder::new("revoke", "Stop your token from working"),
+                ])
+                .build(),
+            SubCommandGroupBuilder::new("integration", "Send events about your bookmarks to another app")
+                .subcommands([
+                    SubCommandBuilder::new("set", "Send signed events to a URL").option(
+                        StringBuilder::new("url", "The HTTPS URL to post events to").required(true),
+                    ),
+                    SubCommandBuilder::new("remove", "Stop sending events"),
+                    SubCommandBuilder::new("test", "Send a test event"),
+                ])
+                .build(),
+            SubCommandGroupBuilder::new("trash", "Bookmarks deleted in the last 30 days")
+                .subcommands([
+                    SubCommandBuilder::new("list", "List deleted bookmarks"),
+                    SubCommandBuilder::new("restore", "Restore a deleted bookmark").option(
+                        StringBuilder::new("bookmark", "The bookmark to restore")
+                            .autocomplete(true)
+                            .required(true),
+                    ),
+                ])
+                .build(),
+        ])
+    }
+
+    async fn autocomplete(
+        &self,
+        input: &CommandInput,
+    ) -> Result<Option<InteractionResponseData>, InteractionError> {
+        let (path, options) = input.subcommand();
+        match path.as_slice() {
+            ["trash", "restore"] => trash::autocomplete(input, options).await,
+            _ => Ok(None),
+        }
+    }
+}
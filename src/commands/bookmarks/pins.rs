@@ -0,0 +1,179 @@
+use crate::command::{find_option, CommandInput};
+use crate::delivery::{ephemeral, send_bookmark, NewBookmark};
+use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::input::default_components;
+use crate::permissions::can_read_history;
+use crate::render::{jump_url, message_embeds, RawMessage};
+use crate::rest::{self, RestError};
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState};
+
+use twilight_model::application::interaction::application_command::{
+    CommandDataOption, CommandOptionValue,
+};
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::channel::Message;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_model::id::marker::{ChannelMarker, MessageMarker};
+use twilight_model::id::Id;
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::{console_log, Date};
+
+// How often the response is edited with the progress so far, in pins
+const PROGRESS_EVERY: usize = 5;
+
+// Deferred work is cut off 30 seconds after the response. No pin is started after this many
+// milliseconds, leaving the slowest one time to archive its attachments and the final edit to
+// go out
+const TIME_BUDGET: u64 = 18_000;
+
+// Pins saved per run. Each one takes a dozen or so subrequests between archiving attachments,
+// sending and storing it, this keeps a run well under the Workers limit
+const MAX_PINS: usize = 10;
+
+/// Where an import stands.
+#[derive(Default)]
+struct Progress {
+    total: usize,
+    saved: usize,
+    skipped: usize,
+}
+
+fn progress(
+    channel_id: Id<ChannelMarker>,
+    done: &Progress,
+    stopped: Option<&str>,
+) -> InteractionResponseData {
+    let mut content = format!(
+        "📌 Saved {} of {} pins from <#{}>",
+        done.saved, done.total, channel_id
+    );
+    if done.skipped > 0 {
+        content.push_str(&format!(
+            ", skipped {} already bookmarked or not allowed in this server",
+            done.skipped
+        ));
+    }
+    if let Some(reason) = stopped {
+        content.push_str(&format!("\n{}", reason));
+    }
+    InteractionResponseDataBuilder::new()
+        .content(content)
+        .flags(MessageFlags::EPHEMERAL)
+        .build()
+}
+
+/// `/bookmarks pins`, bookmarks every pinned message of a channel. Deferred, the response is
+/// edited with the progress as pins are saved.
+pub(crate) async fn start(
+    input: &CommandInput<'_>,
+    options: &[CommandDataOption],
+) -> Result<InteractionResponseData, InteractionError> {
+    let started = Date::now().as_millis();
+    let Some(guild_id) = input.guild_id else {
+        return Ok(ephemeral("This command can only be used in a server"));
+    };
+    let channel_id = match find_option(options, "channel") {
+        Some(CommandOptionValue::Channel(channel_id)) => Some(*channel_id),
+        _ => input.channel_id,
+    };
+    let Some(channel_id) = channel_id else {
+        return Ok(ephemeral("Pick the channel to import the pins of"));
+    };
+    let Ok(store) = BookmarkStore::new(input.ctx) else {
+        return Ok(ephemeral("Importing pins is not enabled for this bot"));
+    };
+    let user_id = input.uid()?;
+    let client = input.http_client()?;
+
+    let config = GuildConfig::for_guild(input.ctx, guild_id).await;
+    if config.channel_disabled(channel_id) {
+        return Ok(ephemeral("Bookmarking is disabled in this channel"));
+    }
+    let guild = rest::get_guild(&client, guild_id).await?;
+    if !can_read_history(&client, &guild, channel_id, user_id).await? {
+        return Ok(ephemeral("You can't read the history of that channel"));
+    }
+    let mut pins = match rest::get_pins(&client, channel_id).await {
+        Ok(pins) => pins,
+        Err(RestError::Forbidden) => {
+            return Ok(ephemeral("I can't read the message history of that channel"))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if pins.is_empty() {
+        return Ok(ephemeral("That channel has no pinned messages"));
+    }
+    // Oldest first, so the bookmarks arrive in the order the messages were sent
+    pins.sort_by_key(|pin| {
+        pin["id"]
+            .as_str()
+            .and_then(|id| id.parse::<Id<MessageMarker>>().ok())
+    });
+    let settings = Settings::for_user(input.ctx, user_id).await;
+
+    let mut done = Progress {
+        total: pins.len(),
+        ..Default::default()
+    };
+    let mut dm_channel = None;
+    for (i, value) in pins.into_iter().enumerate() {
+        if done.saved == MAX_PINS {
+            return Ok(progress(
+                channel_id,
+                &done,
+                Some(&format!(
+                    "Pins are saved {} at a time, run `/bookmarks pins` again to save the rest",
+                    MAX_PINS
+                )),
+            ));
+        }
+        if Date::now().as_millis() - started > TIME_BUDGET {
+            // Saved pins are skipped by the next run, which picks up where this one stopped
+            return Ok(progress(
+                channel_id,
+                &done,
+                Some("Ran out of time, run `/bookmarks pins` again to save the rest"),
+            ));
+        }
+        if i > 0 && i % PROGRESS_EVERY == 0 {
+            let mut body = serde_json::to_value(progress(channel_id, &done, None))?;
+            if let Some(body) = body.as_object_mut() {
+                body.remove("flags");
+            }
+            if let Err(err) = rest::edit_original_response(input.application_id, input.token, &body).await {
+                console_log!("[PINS] updating the progress failed: {}", err);
+            }
+        }
+
+        let mut message: Message = serde_json::from_value(value.clone())?;
+        let raw: Option<RawMessage> = serde_json::from_value(value).ok();
+        if store.find_by_source(user_id, message.id).await?.is_some()
+            || config.author_blocked(&client, guild_id, &message).await?
+        {
+            done.skipped += 1;
+            continue;
+        }
+        config.strip(&mut message);
+
+        let t_url = jump_url(Some(guild_id), channel_id, message.id);
+        let embeds = message_embeds(input.ctx, &client, &message, raw.as_ref(), &guild, &t_url, &settings).await;
+        let bookmark = NewBookmark {
+            user_id,
+            guild_id: Some(guild_id),
+            channel_id,
+            messages: vec![message],
+        };
+        let components = default_components(&t_url, false, ReadState::Unread);
+        match send_bookmark(input.ctx, &client, bookmark, embeds, components, &settings, &mut dm_channel).await? {
+            Ok(_) => done.saved += 1,
+            // Closed DMs and a full quota would fail every other pin too
+            Err(data) => {
+                let reason = data.content.unwrap_or_default();
+                return Ok(progress(channel_id, &done, Some(&reason)));
+            }
+        }
+    }
+    Ok(progress(channel_id, &done, None))
+}
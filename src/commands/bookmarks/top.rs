@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use crate::board::{Board, BoardOp, DAY, WEEK};
+use crate::command::{find_option, CommandInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::permissions::MemberAccess;
+use crate::render::jump_url;
+use crate::rest;
+use crate::store::BookmarkStore;
+
+use twilight_model::application::interaction::application_command::{
+    CommandDataOption, CommandOptionValue,
+};
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::guild::Permissions;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_util::builder::embed::EmbedBuilder;
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::Date;
+
+const TOP_SIZE: usize = 10;
+
+// Messages looked at to fill the list, those in channels the member can't view are left out
+const CANDIDATES: usize = 25;
+
+/// `/bookmarks top`, the most bookmarked messages of the server in channels the member can view.
+/// Only shown to them, the list depends on what they can see.
+pub(crate) async fn top(
+    input: &CommandInput<'_>,
+    options: &[CommandDataOption],
+) -> Result<InteractionResponseData, InteractionError> {
+    let Some(guild_id) = input.guild_id else {
+        return Ok(ephemeral("This command can only be used in a server"));
+    };
+    let now = Date::now().as_millis();
+    let (since, label) = match find_option(options, "period") {
+        Some(CommandOptionValue::String(period)) if period == "day" => (Some(now - DAY), "today"),
+        Some(CommandOptionValue::String(period)) if period == "all" => (None, "of all time"),
+        _ => (Some(now - WEEK), "this week"),
+    };
+
+    let store = BookmarkStore::new(input.ctx)?;
+    let op = BoardOp::Top {
+        since,
+        limit: CANDIDATES,
+    };
+    let candidates = Board::update(&store, guild_id, op).await?;
+
+    let client = input.http_client()?;
+    let guild = rest::get_guild(&client, guild_id).await?;
+    let access = MemberAccess {
+        client: &client,
+        guild: &guild,
+        user_id: input.uid()?,
+        roles: input.member.map(|m| m.roles.clone()).unwrap_or_default(),
+    };
+    let mut visible = HashMap::new();
+    let mut top = Vec::new();
+    for entry in candidates {
+        let channel_id = entry.tally.channel_id;
+        let can_view = match visible.get(&channel_id) {
+            Some(can_view) => *can_view,
+            None => {
+                let can_view = access
+                    .permissions(channel_id)
+                    .await?
+                    .contains(Permissions::VIEW_CHANNEL);
+                visible.insert(channel_id, can_view);
+                can_view
+            }
+        };
+        if can_view {
+            top.push(entry);
+        }
+        if top.len() == TOP_SIZE {
+            break;
+        }
+    }
+    if top.is_empty() {
+        return Ok(ephemeral(format!("Nothing has been bookmarked {}", label)));
+    }
+
+    let lines = top
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            format!(
+                "**{}.** [Message]({}) by <@{}> in <#{}> · 🔖 {}",
+                i + 1,
+                jump_url(Some(guild_id), entry.tally.channel_id, entry.message_id),
+                entry.tally.author_id,
+                entry.tally.channel_id,
+                entry.count
+            )
+        })
+        .collect::<Vec<String>>();
+
+    let embed = EmbedBuilder::new()
+        .title(format!("Most bookmarked {}", label))
+        .description(lines.join("\n"))
+        .color(3092790)
+        .build();
+
+    Ok(InteractionResponseDataBuilder::new()
+        .embeds([embed])
+        .flags(MessageFlags::EPHEMERAL)
+        .build())
+}
@@ -0,0 +1,135 @@
+use crate::command::{find_option, CommandInput};
+use crate::delivery::{deliver, ephemeral, NewBookmark};
+use crate::error::InteractionError;
+use crate::input::default_components;
+use crate::render::{bookmark_embeds, jump_url};
+use crate::rest;
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState, TrashedBookmark, TRASH_RETENTION};
+
+use twilight_model::application::command::{CommandOptionChoice, CommandOptionChoiceValue};
+use twilight_model::application::interaction::application_command::{
+    CommandDataOption, CommandOptionValue,
+};
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+// Discord caps both autocomplete choices and embed fields at 25
+const MAX_ENTRIES: usize = 25;
+
+fn summary(trashed: &TrashedBookmark) -> String {
+    trashed.bookmark.summary()
+}
+
+pub(crate) async fn list(input: &CommandInput<'_>) -> Result<InteractionResponseData, InteractionError> {
+    let store = BookmarkStore::new(input.ctx)?;
+    let trashed = store.list_trash(input.uid()?).await?;
+    if trashed.is_empty() {
+        return Ok(ephemeral("Your trash is empty"));
+    }
+
+    let mut embed = EmbedBuilder::new()
+        .title("Trash")
+        .description("Restore a bookmark with `/bookmarks trash restore`")
+        .color(3092790);
+    for t in trashed.iter().take(MAX_ENTRIES) {
+        embed = embed.field(EmbedFieldBuilder::new(
+            summary(t),
+            format!(
+                "Deleted <t:{}:R>, removed for good <t:{}:R> · [original]({})",
+                t.deleted_at / 1000,
+                (t.deleted_at + TRASH_RETENTION) / 1000,
+                t.bookmark.jump_url()
+            ),
+        ));
+    }
+
+    Ok(InteractionResponseDataBuilder::new()
+        .embeds([embed.build()])
+        .flags(MessageFlags::EPHEMERAL)
+        .build())
+}
+
+pub(crate) async fn restore(
+    input: &CommandInput<'_>,
+    options: &[CommandDataOption],
+) -> Result<InteractionResponseData, InteractionError> {
+    let Some(CommandOptionValue::String(id)) = find_option(options, "bookmark") else {
+        return Ok(ephemeral("Pick a bookmark to restore"));
+    };
+    let Some(id) = id.parse().ok().and_then(Id::<MessageMarker>::new_checked) else {
+        return Ok(ephemeral("Pick a bookmark from the list"));
+    };
+
+    let store = BookmarkStore::new(input.ctx)?;
+    let Some(trashed) = store.take_from_trash(input.uid()?, id).await? else {
+        return Ok(ephemeral("That bookmark is no longer in your trash"));
+    };
+    let bookmark = trashed.bookmark;
+
+    let client = input.http_client()?;
+    let guild_id = bookmark
+        .guild_id
+        .ok_or_else(|| InteractionError::WorkerError("Bookmark without a server".into()))?;
+    let guild = rest::get_guild(&client, guild_id).await?;
+    let t_url = jump_url(Some(guild_id), bookmark.channel_id, bookmark.messages[0].id);
+    let settings = Settings::for_user(input.ctx, bookmark.user_id).await;
+    let embeds = bookmark_embeds(
+        input.ctx,
+        &client,
+        &bookmark.messages,
+        &guild,
+        &t_url,
+        &settings,
+    )
+    .await;
+
+    let restored = NewBookmark {
+        user_id: bookmark.user_id,
+        guild_id: bookmark.guild_id,
+        channel_id: bookmark.channel_id,
+        messages: bookmark.messages,
+    };
+    deliver(
+        input.ctx,
+        &client,
+        restored,
+        embeds,
+        default_components(&t_url, false, ReadState::Unread),
+        &settings,
+    )
+    .await
+}
+
+pub(crate) async fn autocomplete(
+    input: &CommandInput<'_>,
+    options: &[CommandDataOption],
+) -> Result<Option<InteractionResponseData>, InteractionError> {
+    let query = match find_option(options, "bookmark") {
+        Some(CommandOptionValue::Focused(query, _)) => query.to_lowercase(),
+        _ => String::new(),
+    };
+
+    let store = BookmarkStore::new(input.ctx)?;
+    let choices = store
+        .list_trash(input.uid()?)
+        .await?
+        .iter()
+        .map(|t| (summary(t), t.bookmark.dm_message_id))
+        .filter(|(name, _)| name.to_lowercase().contains(&query))
+        .take(MAX_ENTRIES)
+        .map(|(name, id)| CommandOptionChoice {
+            name,
+            name_localizations: None,
+            value: CommandOptionChoiceValue::String(id.to_string()),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Some(
+        InteractionResponseDataBuilder::new().choices(choices).build(),
+    ))
+}
@@ -0,0 +1,364 @@
+use crate::collections::{Collection, CollectionItem, CollectionRole, MAX_ITEMS};
+use crate::command::{find_option, Command, CommandInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::store::BookmarkStore;
+
+use async_trait::async_trait;
+use twilight_model::application::command::{
+    CommandOption, CommandOptionChoice, CommandOptionChoiceValue,
+};
+use twilight_model::application::interaction::application_command::{
+    CommandDataOption, CommandOptionValue,
+};
+use twilight_model::channel::message::MessageFlags;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+use twilight_util::builder::command::{StringBuilder, SubCommandBuilder, UserBuilder};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::Date;
+
+// Discord caps both autocomplete choices and embed fields at 25
+const MAX_ENTRIES: usize = 25;
+
+// Bookmarks searched when autocompleting, each one is a KV read
+const MAX_SCANNED: usize = 50;
+
+/// `/collection`, shared reading lists. Members join with an invite code and get the role the
+/// code was created for.
+pub(crate) struct Collections {}
+
+fn string_option<'o>(options: &'o [CommandDataOption], name: &str) -> Option<&'o str> {
+    match find_option(options, name) {
+        Some(CommandOptionValue::String(value)) => Some(value.as_str()),
+        _ => None,
+    }
+}
+
+// Loads the collection picked in the `collection` option, if the caller has at least `role` in it
+async fn collection_for(
+    input: &CommandInput<'_>,
+    store: &BookmarkStore,
+    options: &[CommandDataOption],
+    role: CollectionRole,
+) -> Result<Result<Collection, &'static str>, InteractionError> {
+    let Some(id) = string_option(options, "collection") else {
+        return Ok(Err("Pick a collection"));
+    };
+    let Some(collection) = Collection::load(store, id).await? else {
+        return Ok(Err("That collection no longer exists"));
+    };
+    match collection.role_of(input.uid()?) {
+        Some(have) if have >= role => Ok(Ok(collection)),
+        Some(_) => Ok(Err("You don't have permission to do that in this collection")),
+        None => Ok(Err("You are not a member of that collection")),
+    }
+}
+
+fn view(collection: &Collection, owner: bool) -> InteractionResponseData {
+    let mut description = format!(
+        "{} bookmarks · {} members",
+        collection.items.len(),
+        collection.members.len()
+    );
+    // Only the owner can invite, and revoke with the codes listed here
+    if owner && !collection.invites.is_empty() {
+        let codes = collection
+            .invites
+            .iter()
+            .map(|i| format!("`{}` ({})", i.code, i.role.name()))
+            .collect::<Vec<_>>();
+        description = format!("{}\nInvite codes: {}", description, codes.join(", "));
+    }
+    let mut embed = EmbedBuilder::new()
+        .title(&collection.name)
+        .description(description)
+        .color(3092790);
+    for item in collection.items.iter().rev().take(MAX_ENTRIES) {
+        embed = embed.field(EmbedFieldBuilder::new(
+            item.summary.clone(),
+            format!(
+                "Added by <@{}> <t:{}:R> · [original]({})",
+                item.added_by,
+                item.added_at / 1000,
+                item.jump_url()
+            ),
+        ));
+    }
+
+    InteractionResponseDataBuilder::new()
+        .embeds([embed.build()])
+        .flags(MessageFlags::EPHEMERAL)
+        .build()
+}
+
+#[async_trait(?Send)]
+impl Command for Collections {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let store = BookmarkStore::new(input.ctx)?;
+        let user_id = input.uid()?;
+        let now = Date::now().as_millis();
+        let (path, options) = input.subcommand();
+
+        let required = match path.as_slice() {
+            ["create"] | ["join"] => None,
+            ["view"] | ["leave"] => Some(CollectionRole::Viewer),
+            ["add"] => Some(CollectionRole::Editor),
+            ["invite"] | ["revoke"] | ["role"] => Some(CollectionRole::Owner),
+            _ => return Ok(ephemeral("Unknown subcommand")),
+        };
+        let collection = match required {
+            Some(role) => match collection_for(input, &store, options, role).await? {
+                Ok(collection) => Some(collection),
+                Err(message) => return Ok(ephemeral(message)),
+            },
+            None => None,
+        };
+
+        match (path.as_slice(), collection) {
+            (["create"], _) => {
+                let name = string_option(options, "name").unwrap_or("Reading list");
+                let collection = Collection::new(name.to_string(), user_id, now);
+                collection.save(&store).await?;
+                collection.add_to_index(&store, user_id).await?;
+                Ok(ephemeral(format!(
+                    "Created **{}**. Share it with `/collection invite`",
+                    collection.name
+                )))
+            }
+            (["join"], _) => {
+                let code = string_option(options, "code").unwrap_or_default().trim();
+                let Some((mut collection, role)) = Collection::by_code(&store, code).await? else {
+                    return Ok(ephemeral("That invite code is not valid"));
+                };
+                if collection.set_role(user_id, role) {
+                    collection.add_to_index(&store, user_id).await?;
+                }
+                collection.save(&store).await?;
+                Ok(ephemeral(format!(
+                    "You joined **{}** as {}",
+                    collection.name,
+                    role.name()
+                )))
+            }
+            (["view"], Some(collection)) => {
+                let owner = collection.role_of(user_id) == Some(CollectionRole::Owner);
+                Ok(view(&collection, owner))
+            }
+            (["leave"], Some(collection)) => {
+                let name = collection.name.clone();
+                let owner = collection.role_of(user_id) == Some(CollectionRole::Owner);
+                collection.leave(&store, user_id).await?;
+                Ok(ephemeral(if owner {
+                    format!("Deleted **{}**", name)
+                } else {
+                    format!("You left **{}**", name)
+                }))
+            }
+            (["add"], Some(mut collection)) => {
+                let Some(id) = string_option(options, "bookmark")
+                    .and_then(|id| id.parse().ok())
+                    .and_then(Id::<MessageMarker>::new_checked)
+                else {
+                    return Ok(ephemeral("Pick one of your bookmarks"));
+                };
+                let Some(bookmark) = store.get(user_id, id).await? else {
+                    return Ok(ephemeral("That bookmark no longer exists"));
+                };
+                if collection
+                    .items
+                    .iter()
+                    .any(|item| item.message_id == bookmark.messages[0].id)
+                {
+                    return Ok(ephemeral("That message is already in the collection"));
+                }
+                if collection.items.len() >= MAX_ITEMS {
+                    return Ok(ephemeral(format!(
+                        "Collections hold at most {} bookmarks",
+                        MAX_ITEMS
+                    )));
+                }
+                collection
+                    .items
+                    .push(CollectionItem::from_bookmark(&bookmark, user_id, now));
+                collection.save(&store).await?;
+                Ok(ephemeral(format!("Added to **{}**", collection.name)))
+            }
+            (["invite"], Some(mut collection)) => {
+                let role = string_option(options, "role")
+                    .and_then(CollectionRole::parse)
+                    .unwrap_or(CollectionRole::Viewer);
+                let code = collection.invite(role);
+                collection.save(&store).await?;
+                collection.save_code(&store, &code).await?;
+                Ok(ephemeral(format!(
+                    "Anyone can join **{}** as {} with `/collection join code:{}`",
+                    collection.name,
+                    role.name(),
+                    code
+                )))
+            }
+            (["revoke"], Some(mut collection)) => {
+                let code = string_option(options, "code").unwrap_or_default().trim();
+                if !collection.revoke(code) {
+                    return Ok(ephemeral("That invite code is not one of this collection's"));
+                }
+                collection.save(&store).await?;
+                Collection::delete_code(&store, code).await?;
+                Ok(ephemeral(format!(
+                    "`{}` can no longer be used to join **{}**",
+                    code, collection.name
+                )))
+            }
+            (["role"], Some(mut collection)) => {
+                let Some(CommandOptionValue::User(member)) = find_option(options, "user") else {
+                    return Ok(ephemeral("Pick a member"));
+                };
+                if collection.role_of(*member).is_none() {
+                    return Ok(ephemeral("They are not a member of this collection"));
+                }
+                match string_option(options, "role").and_then(CollectionRole::parse) {
+                    Some(role) => {
+                        collection.set_role(*member, role);
+                        collection.save(&store).await?;
+                        Ok(ephemeral(format!("<@{}> is now {}", member, role.name())))
+                    }
+                    None if *member == user_id => Ok(ephemeral(
+                        "Use `/collection leave` to delete your collection",
+                    )),
+                    None => {
+                        collection.leave(&store, *member).await?;
+                        Ok(ephemeral(format!("Removed <@{}>", member)))
+                    }
+                }
+            }
+            _ => Ok(ephemeral("Unknown subcommand")),
+        }
+    }
+
+    fn name(&self) -> String {
+        "collection".into()
+    }
+
+    fn description(&self) -> String {
+        "Shared bookmark collections".into()
+    }
+
+    fn options(&self) -> Option<Vec<CommandOption>> {
+        let collection = || {
+            StringBuilder::new("collection", "The collection")
+                .autocomplete(true)
+                .required(true)
+        };
+        Some(vec![
+            SubCommandBuilder::new("create", "Create a collection")
+                .option(
+                    StringBuilder::new("name", "Its name")
+                        .max_length(100)
+                        .required(true),
+                )
+                .build(),
+            SubCommandBuilder::new("join", "Join a collection with an invite code")
+                .option(StringBuilder::new("code", "The invite code").required(true))
+                .build(),
+            SubCommandBuilder::new("view", "List the bookmarks of a collection")
+                .option(collection())
+                .build(),
+            SubCommandBuilder::new("add", "Add one of your bookmarks to a collection")
+                .option(collection())
+                .option(
+                    StringBuilder::new("bookmark", "The bookmark to add")
+                        .autocomplete(true)
+                        .required(true),
+                )
+                .build(),
+            SubCommandBuilder::new("invite", "Create an invite code for a collection you own")
+                .option(collection())
+                .option(
+                    StringBuilder::new("role", "What people joining can do, view only by default")
+                        .choices([("Viewer", "viewer"), ("Editor", "editor")]),
+                )
+                .build(),
+            SubCommandBuilder::new("revoke", "Stop an invite code of a collection you own from working")
+                .option(collection())
+                .option(StringBuilder::new("code", "The invite code, listed by `/collection view`").required(true))
+                .build(),
+            SubCommandBuilder::new("role", "Change what a member of your collection can do")
+                .option(collection())
+                .option(UserBuilder::new("user", "The member").required(true))
+                .option(
+                    StringBuilder::new("role", "Their new role")
+                        .choices([("Viewer", "viewer"), ("Editor", "editor"), ("Remove", "remove")])
+                        .required(true),
+                )
+                .build(),
+            SubCommandBuilder::new("leave", "Leave a collection, or delete one you own")
+                .option(collection())
+                .build(),
+        ])
+    }
+
+    async fn autocomplete(
+        &self,
+        input: &CommandInput,
+    ) -> Result<Option<InteractionResponseData>, InteractionError> {
+        let (path, options) = input.subcommand();
+        let store = BookmarkStore::new(input.ctx)?;
+        let user_id = input.uid()?;
+
+        let choices = match (find_option(options, "collection"), find_option(options, "bookmark")) {
+            (Some(CommandOptionValue::Focused(query, _)), _) => {
+                let query = query.to_lowercase();
+                let needed = match path.as_slice() {
+                    ["add"] => CollectionRole::Editor,
+                    ["invite"] | ["revoke"] | ["role"] => CollectionRole::Owner,
+                    _ => CollectionRole::Viewer,
+                };
+                Collection::list_for(&store, user_id)
+                    .await?
+                    .into_iter()
+                    .filter(|c| c.role_of(user_id).is_some_and(|role| role >= needed))
+                    .filter(|c| c.name.to_lowercase().contains(&query))
+                    .map(|c| (c.name, c.id))
+                    .collect::<Vec<_>>()
+            }
+            (_, Some(CommandOptionValue::Focused(query, _))) => {
+                let query = query.to_lowercase();
+                let mut choices = Vec::new();
+                // Newest first, stopping once the list is full
+                for id in store.list(user_id).await?.into_iter().rev().take(MAX_SCANNED) {
+                    if choices.len() >= MAX_ENTRIES {
+                        break;
+                    }
+                    if let Some(bookmark) = store.get(user_id, id).await? {
+                        let summary = bookmark.summary();
+                        if summary.to_lowercase().contains(&query) {
+                            choices.push((summary, id.to_string()));
+                        }
+                    }
+                }
+                choices
+            }
+            _ => Vec::new(),
+        };
+
+        let choices = choices
+            .into_iter()
+            .take(MAX_ENTRIES)
+            .map(|(name, value)| CommandOptionChoice {
+                name,
+                name_localizations: None,
+                value: CommandOptionChoiceValue::String(value),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Some(
+            InteractionResponseDataBuilder::new().choices(choices).build(),
+        ))
+    }
+}
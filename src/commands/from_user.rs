@@ -0,0 +1,199 @@
+use crate::RouteData;
+use crate::command::{Command, CommandInput};
+use crate::delivery::{deliver, ephemeral, NewBookmark};
+use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::input::default_components;
+use crate::permissions::can_read_history;
+use crate::render::{conversation_embeds, jump_url};
+use crate::rest::{self, RestError};
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use twilight_model::application::command::CommandType;
+use twilight_model::channel::message::component::{ActionRow, SelectMenu, SelectMenuOption};
+use twilight_model::channel::message::{Component, MessageFlags};
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_model::id::marker::{ChannelMarker, GuildMarker, UserMarker};
+use twilight_model::id::Id;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::RouteContext;
+
+// Discord caps embed fields at 25
+const MAX_ENTRIES: usize = 25;
+
+// Messages searched for the target's recent ones, the most one request returns
+const SEARCH_DEPTH: u8 = 100;
+
+// Bookmarks searched for the member's messages, each one is a KV read
+const MAX_SCANNED: usize = 100;
+
+/// Saves the last `count` messages of `author_id` in a channel as one bookmark.
+pub(crate) async fn bookmark_recent(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    user_id: Id<UserMarker>,
+    guild_id: Id<GuildMarker>,
+    channel_id: Id<ChannelMarker>,
+    author_id: Id<UserMarker>,
+    count: usize,
+) -> Result<InteractionResponseData, InteractionError> {
+    let config = GuildConfig::for_guild(ctx, guild_id).await;
+    if config.channel_disabled(channel_id) {
+        return Ok(ephemeral("Bookmarking is disabled in this channel"));
+    }
+    // The bot can read channels the member can't, recent messages would leak them
+    let guild = rest::get_guild(client, guild_id).await?;
+    if !can_read_history(client, &guild, channel_id, user_id).await? {
+        return Ok(ephemeral("You can't read the history of this channel"));
+    }
+    let recent = match rest::get_recent_messages(client, channel_id, SEARCH_DEPTH).await {
+        Ok(messages) => messages,
+        Err(RestError::Forbidden) => {
+            return Ok(ephemeral("I can't read the message history of this channel"))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mut messages: Vec<_> = recent
+        .into_iter()
+        .filter(|m| m.author.id == author_id)
+        .take(count)
+        .collect();
+    if messages.is_empty() {
+        return Ok(ephemeral("They have no recent messages in this channel"));
+    }
+    if config.author_blocked(client, guild_id, &messages[0]).await? {
+        return Ok(ephemeral("Messages from this member can't be bookmarked in this server"));
+    }
+    messages.reverse();
+    for message in messages.iter_mut() {
+        config.strip(message);
+    }
+
+    let t_url = jump_url(Some(guild_id), channel_id, messages[0].id);
+    let embeds = conversation_embeds(&messages, &guild, &t_url);
+    let settings = Settings::for_user(ctx, user_id).await;
+
+    let bookmark = NewBookmark {
+        user_id,
+        guild_id: Some(guild_id),
+        channel_id,
+        messages,
+    };
+    deliver(
+        ctx,
+        client,
+        bookmark,
+        embeds,
+        default_components(&t_url, false, ReadState::Unread),
+        &settings,
+    )
+    .await
+}
+
+/// The "Bookmarks from this user" user command, listing the caller's bookmarks of a member's
+/// messages and offering to save their latest ones in this channel.
+pub(crate) struct FromUser {}
+
+#[async_trait(?Send)]
+impl Command for FromUser {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let user_id = input.uid()?;
+        let author_id = Id::<UserMarker>::new(input.target_id.unwrap().get());
+        let name = input
+            .resolved
+            .as_ref()
+            .and_then(|r| r.users.get(&author_id))
+            .map_or_else(|| "this member".to_string(), |u| u.name.clone());
+
+        let mut found = Vec::new();
+        let mut truncated = false;
+        if let Ok(store) = BookmarkStore::new(input.ctx) {
+            let ids = store.list(user_id).await?;
+            truncated = ids.len() > MAX_SCANNED;
+            // Newest first, only the latest ones are searched
+            for id in ids.into_iter().rev().take(MAX_SCANNED) {
+                if let Some(bookmark) = store.get(user_id, id).await? {
+                    if bookmark.messages.iter().any(|m| m.author.id == author_id) {
+                        found.push(bookmark);
+                    }
+                }
+            }
+        }
+
+        let mut description = match found.len() {
+            0 => format!("You have no bookmarks of messages from {}", name),
+            n => format!("{} bookmarks of messages from {}, newest first", n, name),
+        };
+        if truncated {
+            description.push_str(&format!(" (searched your latest {} bookmarks)", MAX_SCANNED));
+        }
+        if found.len() > MAX_ENTRIES {
+            description.push_str(&format!(", showing the latest {}", MAX_ENTRIES));
+        }
+        let mut embed = EmbedBuilder::new()
+            .title(format!("Bookmarks from {}", name))
+            .description(description)
+            .color(3092790);
+        for bookmark in found.iter().take(MAX_ENTRIES) {
+            embed = embed.field(EmbedFieldBuilder::new(
+                bookmark.summary(),
+                format!(
+                    "Saved <t:{}:R> · [original]({})",
+                    bookmark.created_at / 1000,
+                    bookmark.jump_url()
+                ),
+            ));
+        }
+
+        let mut data = InteractionResponseDataBuilder::new()
+            .embeds([embed.build()])
+            .flags(MessageFlags::EPHEMERAL);
+        // Saving recent messages needs a channel to read them from
+        if let (Some(_), Some(channel_id)) = (input.guild_id, input.channel_id) {
+            let options = [1, 3, 5, 10]
+                .iter()
+                .map(|n| SelectMenuOption {
+                    default: false,
+                    description: None,
+                    emoji: None,
+                    label: match n {
+                        1 => "Their last message here".to_string(),
+                        n => format!("Their last {} messages here", n),
+                    },
+                    value: n.to_string(),
+                })
+                .collect();
+            data = data.components([Component::ActionRow(ActionRow {
+                components: vec![Component::SelectMenu(SelectMenu {
+                    custom_id: format!("from-user:{}:{}", channel_id, author_id),
+                    disabled: false,
+                    max_values: Some(1),
+                    min_values: Some(1),
+                    options,
+                    placeholder: Some("Bookmark their recent messages".into()),
+                })],
+            })]);
+        }
+        Ok(data.build())
+    }
+
+    fn name(&self) -> String {
+        "Bookmarks from this user".into()
+    }
+
+    fn deferred(&self, _input: &CommandInput) -> bool {
+        // Reads up to a hundred bookmarks, which may take longer than Discord waits
+        true
+    }
+
+    fn kind(&self) -> CommandType {
+        CommandType::User
+    }
+}
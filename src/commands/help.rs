@@ -26,9 +26,37 @@ impl Command for Help {
               "description": "Bookermarker is a simple bot that allows users to bookmark messages by using interactions. Right click on a message --> Apps --> Bookmark. The bot will DM you with the contents of the message.",
               "color": 3092790,
               "fields": [
+                {
+                  "name": "Bookmark by Link",
+                  "value": "`/bookmark link:` with a message link (Copy Message Link) bookmarks messages from any channel you can read, handy on mobile."
+                },
+                {
+                  "name": "Bookmarks From a Member",
+                  "value": "Right click a member --> Apps --> Bookmarks from this user lists your bookmarks of their messages, and can save their last few messages in this channel."
+                },
+                {
+                  "name": "Import Pins",
+                  "value": "`/bookmarks pins` bookmarks every pinned message of a channel, a few at a time. Messages you already bookmarked are skipped."
+                },
+                {
+                  "name": "Bookmark a Conversation",
+                  "value": "Right click the first message --> Apps --> Bookmark conversation, then do the same on the last message or pick how many messages to save. Up to 20 messages are saved as a single bookmark."
+                },
+                {
+                  "name": "Most Bookmarked",
+                  "value": "`/bookmarks top` lists the messages of this server that were bookmarked most today, this week or of all time."
+                },
+                {
+                  "name": "Shared Collections",
+                  "value": "`/collection create` starts a shared reading list. Invite others with `/collection invite`, they join with `/collection join` and can view or add bookmarks depending on their role. `/collection revoke` stops a code from working."
+                },
+                {
+                  "name": "Settings",
+                  "value": "Use `/settings` to pick a default colour, where bookmarks are sent, whether replies and attachments are saved and your reminder timezone."
+                },
                 {
                   "name": "Command Permissions",
-                  "value": "To manage in which roles / channels Bookmarker can be used, head to Server settings --> Integrations --> Bookmarker and adjust the **Bookmark** command. For more information on managing slash command perms see this [discord article.](https://support.discord.com/hc/en-us/articles/10952896421783)"
+                  "value": "To manage in which roles / channels Bookmarker can be used, head to Server settings --> Integrations --> Bookmarker and adjust the **Bookmark** command. For more information on managing slash command perms see this [discord article.](https://support.discord.com/hc/en-us/articles/10952896421783)\n\nMembers with Manage Server can also use `/bookmark-admin` to disable bookmarking in channels, block messages from roles, remove attachments from bookmarks and set a log channel."
                 }
               ],
               "image": {
@@ -49,12 +77,22 @@ impl Command for Help {
                 },
                 {
                   "name": ":art: Change Embed Colour",
-                  "value": "Update the colour of the embed from a selection.",
+                  "value": "Pick a colour, enter a custom hex code or save one to your palette. You can also make it the default for new bookmarks.",
+                  "inline": true
+                },
+                {
+                  "name": ":arrows_counterclockwise: Refresh",
+                  "value": "Reloads the original message and shows whether it was edited or deleted.",
                   "inline": true
                 },
                 {
                   "name": ":x: Delete Bookmark",
-                  "value": "Deletes the bookmark instantly.",
+                  "value": "Moves the bookmark to the trash after confirming. Restore it within 30 days with `/bookmarks trash restore`.",
+                  "inline": true
+                },
+                {
+                  "name": ":white_check_mark: Read Later",
+                  "value": "New bookmarks start unread. Mark them read or archive them, and see what's left with `/bookmarks inbox`.",
                   "inline": true
                 }
               ]
@@ -1,2 +1,8 @@
 pub mod help;
-pub mod bookmark;
\ No newline at end of file
+pub mod bookmark;
+pub mod range;
+pub mod bookmarks;
+pub mod settings;
+pub mod admin;
+pub mod collection;
+pub mod from_user;
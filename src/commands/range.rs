@@ -0,0 +1,243 @@
+use crate::RouteData;
+use crate::command::{Command, CommandInput};
+use crate::delivery::{bookmarked, ephemeral, send_bookmark, NewBookmark};
+use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::input::default_components;
+use crate::render::{conversation_embeds, jump_url};
+use crate::rest::{self, RestError};
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use twilight_model::application::command::CommandType;
+use twilight_model::channel::message::component::{ActionRow, SelectMenu, SelectMenuOption};
+use twilight_model::channel::message::{Component, MessageFlags};
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_model::id::marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker};
+use twilight_model::id::Id;
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::RouteContext;
+
+// Longest conversation a single bookmark can hold
+pub(crate) const MAX_MESSAGES: u8 = 20;
+
+// How long the start of a conversation is remembered, in seconds
+const PENDING_TTL: u64 = 600;
+
+#[derive(Deserialize, Serialize)]
+struct PendingRange {
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+}
+
+pub(crate) fn pending_key(user_id: Id<UserMarker>) -> String {
+    format!("range:{}", user_id)
+}
+
+/// Up to `count` messages of a channel starting at `start`, stopping at `end` when given.
+pub(crate) struct Conversation {
+    pub(crate) guild_id: Id<GuildMarker>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    pub(crate) start: Id<MessageMarker>,
+    pub(crate) end: Option<Id<MessageMarker>>,
+    pub(crate) count: u8,
+}
+
+/// Fetches the messages of a conversation and delivers them as one bookmark.
+pub(crate) async fn bookmark_conversation(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    user_id: Id<UserMarker>,
+    conversation: Conversation,
+) -> Result<InteractionResponseData, InteractionError> {
+    let Conversation {
+        guild_id,
+        channel_id,
+        start,
+        end,
+        count,
+    } = conversation;
+    let config = GuildConfig::for_guild(ctx, guild_id).await;
+    if config.channel_disabled(channel_id) {
+        return Ok(ephemeral("Bookmarking is disabled in this channel"));
+    }
+    // `after` is exclusive, step back one snowflake to include the first message
+    let mut messages =
+        match rest::get_messages_after(client, channel_id, start.get() - 1, count.min(MAX_MESSAGES)).await {
+            Ok(messages) => messages,
+            Err(RestError::Forbidden) => {
+                return Ok(ephemeral("I can't read the message history of this channel"))
+            }
+            Err(err) => return Err(err.into()),
+        };
+    let truncated = cut_short(messages.len(), messages.last().map(|m| m.id), end);
+    if let Some(end) = end {
+        messages.retain(|m| m.id <= end);
+    }
+    // Messages the server does not allow to be bookmarked are left out of the conversation
+    let mut allowed = Vec::with_capacity(messages.len());
+    let mut blocked_authors = Vec::new();
+    for mut message in messages {
+        if blocked_authors.contains(&message.author.id) {
+            continue;
+        }
+        if config.author_blocked(client, guild_id, &message).await? {
+            blocked_authors.push(message.author.id);
+            continue;
+        }
+        config.strip(&mut message);
+        allowed.push(message);
+    }
+    let messages = allowed;
+    if messages.is_empty() {
+        return Ok(ephemeral("Those messages could not be found"));
+    }
+
+    let guild = rest::get_guild(client, guild_id).await?;
+    let t_url = jump_url(Some(guild_id), channel_id, messages[0].id);
+    let embeds = conversation_embeds(&messages, &guild, &t_url);
+    let settings = Settings::for_user(ctx, user_id).await;
+
+    let bookmark = NewBookmark {
+        user_id,
+        guild_id: Some(guild_id),
+        channel_id,
+        messages,
+    };
+    let components = default_components(&t_url, false, ReadState::Unread);
+    match send_bookmark(ctx, client, bookmark, embeds, components, &settings, &mut None).await? {
+        Ok(note) if truncated => {
+            let notice = format!(
+                "Only the first {} messages fit in one bookmark, bookmark the rest as another conversation",
+                MAX_MESSAGES
+            );
+            let content = match note {
+                Some(note) => format!("{}\n{}", note, notice),
+                None => notice,
+            };
+            Ok(bookmarked(Some(&content)))
+        }
+        Ok(note) => Ok(bookmarked(note)),
+        Err(data) => Ok(data),
+    }
+}
+
+// Whether a fetch of `fetched` messages ending at `last` stopped before reaching `end`
+fn cut_short(
+    fetched: usize,
+    last: Option<Id<MessageMarker>>,
+    end: Option<Id<MessageMarker>>,
+) -> bool {
+    match (last, end) {
+        (Some(last), Some(end)) => fetched >= MAX_MESSAGES as usize && last < end,
+        _ => false,
+    }
+}
+
+pub(crate) struct BookmarkRange {}
+
+#[async_trait(?Send)]
+impl Command for BookmarkRange {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let (Some(guild_id), Some(channel_id)) = (input.guild_id, input.channel_id) else {
+            return Ok(ephemeral("This command can only be used in a server"));
+        };
+        let Ok(store) = BookmarkStore::new(input.ctx) else {
+            return Ok(ephemeral("Conversation bookmarks are not enabled for this bot"));
+        };
+
+        let user_id = input.uid()?;
+        let target = Id::<MessageMarker>::new(input.target_id.unwrap().get());
+        let key = pending_key(user_id);
+
+        match store.get_value::<PendingRange>(&key).await? {
+            // The second message picked in the same channel closes the range
+            Some(pending) if pending.channel_id == channel_id => {
+                store.delete_value(&key).await?;
+                let (start, end) = if pending.message_id <= target {
+                    (pending.message_id, target)
+                } else {
+                    (target, pending.message_id)
+                };
+                let conversation = Conversation {
+                    guild_id,
+                    channel_id,
+                    start,
+                    end: Some(end),
+                    count: MAX_MESSAGES,
+                };
+                bookmark_conversation(input.ctx, &input.http_client()?, user_id, conversation).await
+            }
+            _ => {
+                let pending = PendingRange {
+                    channel_id,
+                    message_id: target,
+                };
+                store.put_value(&key, &pending, Some(PENDING_TTL)).await?;
+
+                let options = [5, 10, 15, 20]
+                    .iter()
+                    .map(|n| SelectMenuOption {
+                        default: false,
+                        description: None,
+                        emoji: None,
+                        label: format!("This message and the next {}", n - 1),
+                        value: n.to_string(),
+                    })
+                    .collect();
+                let select = Component::ActionRow(ActionRow {
+                    components: vec![Component::SelectMenu(SelectMenu {
+                        custom_id: format!("range:{}:{}", channel_id, target),
+                        disabled: false,
+                        max_values: Some(1),
+                        min_values: Some(1),
+                        options,
+                        placeholder: Some("Save a number of messages".into()),
+                    })],
+                });
+
+                Ok(InteractionResponseDataBuilder::new()
+                    .content("Start of the conversation saved. Use **Bookmark conversation** on the last message within 10 minutes, or pick how many messages to save:")
+                    .components([select])
+                    .flags(MessageFlags::EPHEMERAL)
+                    .build())
+            }
+        }
+    }
+
+    fn name(&self) -> String {
+        "Bookmark conversation".into()
+    }
+
+    fn kind(&self) -> CommandType {
+        CommandType::Message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u64) -> Option<Id<MessageMarker>> {
+        Some(Id::new(n))
+    }
+
+    #[test]
+    fn long_ranges_are_reported_as_cut_short() {
+        let full = MAX_MESSAGES as usize;
+        assert!(cut_short(full, id(20), id(30)));
+        assert!(!cut_short(full, id(30), id(30)));
+        assert!(!cut_short(full, id(35), id(30)));
+        // Fewer messages than asked for means the channel has no more
+        assert!(!cut_short(full - 1, id(20), id(30)));
+        // A picked count has no end to reach
+        assert!(!cut_short(full, id(20), None));
+        assert!(!cut_short(0, None, id(30)));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::command::{Command, CommandInput};
+use crate::error::InteractionError;
+use crate::settings::{format_hex, format_utc_offset, Delivery, Settings, LOCALES};
+use crate::store::BookmarkStore;
+
+use async_trait::async_trait;
+use twilight_model::channel::message::component::{
+    ActionRow, Button, ButtonStyle, SelectMenu, SelectMenuOption,
+};
+use twilight_model::channel::message::{Component, MessageFlags, ReactionType};
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+/// `/settings`, an ephemeral panel for the caller's preferences. Its components are handled by
+/// `components::settings`.
+pub(crate) struct UserSettings {}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+fn toggle(custom_id: &str, label: &str, enabled: bool) -> Component {
+    Component::Button(Button {
+        custom_id: Some(custom_id.to_string()),
+        disabled: false,
+        emoji: None,
+        label: Some(format!("{}: {}", label, on_off(enabled))),
+        style: if enabled {
+            ButtonStyle::Success
+        } else {
+            ButtonStyle::Secondary
+        },
+        url: None,
+    })
+}
+
+fn select(custom_id: &str, placeholder: &str, options: Vec<SelectMenuOption>) -> Component {
+    Component::ActionRow(ActionRow {
+        components: vec![Component::SelectMenu(SelectMenu {
+            custom_id: custom_id.to_string(),
+            disabled: false,
+            max_values: Some(1),
+            min_values: Some(1),
+            options,
+            placeholder: Some(placeholder.to_string()),
+        })],
+    })
+}
+
+fn choice(label: &str, value: &str, default: bool) -> SelectMenuOption {
+    SelectMenuOption {
+        default,
+        description: None,
+        emoji: None,
+        label: label.to_string(),
+        value: value.to_string(),
+    }
+}
+
+/// The settings panel, `in_guild` offers delivery to the channel the panel was opened in.
+/// Bookmarks fall back to DMs whenever a channel or webhook can't be posted to.
+pub(crate) fn panel(settings: &Settings, in_guild: bool) -> InteractionResponseData {
+    let delivery = match &settings.delivery {
+        Delivery::Dm => "Direct messages".to_string(),
+        Delivery::Channel { channel_id, .. } => format!("<#{}>", channel_id),
+        Delivery::Webhook { .. } => "Webhook".to_string(),
+    };
+    let language = settings
+        .locale
+        .as_deref()
+        .and_then(|code| LOCALES.iter().find(|(c, _)| *c == code))
+        .map_or("English", |(_, name)| name);
+    let palette = if settings.palette.is_empty() {
+        "Empty, save colors from the 🎨 picker".to_string()
+    } else {
+        settings
+            .palette
+            .iter()
+            .map(|c| format!("{} `{}`", c.name, format_hex(c.color)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    let embed = EmbedBuilder::new()
+        .title("Your settings")
+        .color(settings.default_color.unwrap_or(3092790))
+        .field(
+            EmbedFieldBuilder::new(
+                "Default color",
+                settings.default_color.map_or("Not set".to_string(), format_hex),
+            )
+            .inline(),
+        )
+        .field(EmbedFieldBuilder::new("Delivery", delivery).inline())
+        .field(EmbedFieldBuilder::new("Language", language).inline())
+        .field(EmbedFieldBuilder::new("Reply context", on_off(settings.reply_context)).inline())
+        .field(
+            EmbedFieldBuilder::new("Attachment archiving", on_off(settings.archive_attachments))
+                .inline(),
+        )
+        .field(EmbedFieldBuilder::new("Weekly digest", on_off(settings.digest)).inline())
+        .field(
+            EmbedFieldBuilder::new(
+                "Reminder timezone",
+                format_utc_offset(settings.utc_offset_minutes()),
+            )
+            .inline(),
+        )
+        .field(EmbedFieldBuilder::new("Palette", palette))
+        .build();
+
+    let mut delivery_options = vec![choice(
+        "Direct messages",
+        "dm",
+        settings.delivery == Delivery::Dm,
+    )];
+    if in_guild {
+        delivery_options.push(choice("This channel", "channel", false));
+    }
+    if let Delivery::Channel { channel_id, .. } = settings.delivery {
+        delivery_options.push(choice(
+            "Current bookmark channel",
+            &format!("channel:{}", channel_id),
+            true,
+        ));
+    }
+    delivery_options.push(choice(
+        "Webhook…",
+        "webhook",
+        matches!(settings.delivery, Delivery::Webhook { .. }),
+    ));
+
+    let locale_options = LOCALES
+        .iter()
+        .map(|(code, name)| {
+            let selected = settings.locale.as_deref().unwrap_or("en-US") == *code;
+            choice(name, code, selected)
+        })
+        .collect();
+
+    let components = vec![
+        select("settings:delivery", "Where bookmarks are sent", delivery_options),
+        select("settings:locale", "Language bookmarks are written in", locale_options),
+        Component::ActionRow(ActionRow {
+            components: vec![
+                toggle("settings:toggle:reply", "Reply context", settings.reply_context),
+                toggle(
+                    "settings:toggle:archive",
+                    "Archiving",
+                    settings.archive_attachments,
+                ),
+                toggle("settings:toggle:digest", "Digest", settings.digest),
+                Component::Button(Button {
+                    custom_id: Some("settings:edit".to_string()),
+                    disabled: false,
+                    emoji: Some(ReactionType::Unicode {
+                        name: "✏️".to_string(),
+                    }),
+                    label: Some("Color & timezone".to_string()),
+                    style: ButtonStyle::Primary,
+                    url: None,
+                }),
+            ],
+        }),
+    ];
+
+    InteractionResponseDataBuilder::new()
+        .embeds([embed])
+        .components(components)
+        .flags(MessageFlags::EPHEMERAL)
+        .build()
+}
+
+#[async_trait(?Send)]
+impl Command for UserSettings {
+    async fn respond(
+        &self,
+        input: &CommandInput,
+    ) -> Result<InteractionResponseData, InteractionError> {
+        let store = BookmarkStore::new(input.ctx)?;
+        let settings = Settings::load(&store, input.uid()?).await?;
+        Ok(panel(&settings, input.guild_id.is_some()))
+    }
+
+    fn name(&self) -> String {
+        "settings".into()
+    }
+
+    fn description(&self) -> String {
+        "View and change your bookmark settings".into()
+    }
+}
@@ -1,16 +1,18 @@
+use std::collections::HashMap;
+
 use twilight_model::{
     channel::{message::component::ComponentType, Message},
-    guild::PartialMember,
+    guild::{PartialMember, Permissions},
     http::interaction::InteractionResponse,
     id::{
-        marker::{ChannelMarker, GuildMarker},
+        marker::{ChannelMarker, GuildMarker, UserMarker},
         Id,
     },
 };
 
 use reqwest::{Client, ClientBuilder, header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE}};
+use crate::RouteData;
 use crate::components;
-use crate::input::SharedInput;
 use crate::error::InteractionError;
 use async_trait::async_trait;
 
@@ -18,19 +20,21 @@ use async_trait::async_trait;
 pub(crate) struct ComponentInput<'a> {
     pub(crate) guild_id: Option<Id<GuildMarker>>,
     pub(crate) channel_id: Option<Id<ChannelMarker>>,
-    pub(crate) user: Option<&'a PartialMember>,
+    pub(crate) user: Option<&'a twilight_model::user::User>,
     pub(crate) member: Option<&'a PartialMember>,
-    pub(crate) ctx: &'a mut worker::RouteContext<()>,
+    pub(crate) ctx: &'a mut worker::RouteContext<RouteData>,
+    // The bot's permissions in the channel of the interaction
+    pub(crate) app_permissions: Option<Permissions>,
 
     pub(crate) message: Option<&'a Message>,
     pub(crate) custom_id: String,
     pub(crate) component_type: ComponentType,
     pub(crate) values: Vec<String>,
+    // Text input values by custom_id when answering a modal submit, empty otherwise
+    pub(crate) fields: HashMap<String, String>,
 }
 
 
-impl SharedInput<'_> for ComponentInput<'_> {}
-
 #[allow(dead_code)]
 impl ComponentInput<'_> {
     pub async fn kv_get(
@@ -68,6 +72,23 @@ impl ComponentInput<'_> {
         Ok(())
     }
 
+    pub(crate) fn field(&self, custom_id: &str) -> Option<&str> {
+        self.fields
+            .get(custom_id)
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+
+    pub(crate) fn uid(&self) -> Result<Id<UserMarker>, InteractionError> {
+        if let Some(u) = self.member.as_ref().and_then(|m| m.user.as_ref()) {
+            Ok(u.id)
+        } else if let Some(u) = self.user.as_ref() {
+            Ok(u.id)
+        } else {
+            Err(InteractionError::WorkerError("No member".into()))
+        }
+    }
+
     pub(crate) fn http_client(&self) -> Result<Client, InteractionError> {
         let token = self.ctx.var("DISCORD_TOKEN")?.to_string();
 
@@ -101,11 +122,24 @@ pub(crate) trait Component {
         // The command name, ie `return "greet".to_string()` for /greet
         unimplemented!()
     }
+
+    fn deferred(&self, _input: &ComponentInput) -> bool {
+        // Return true if responding may take longer than Discord's 3 seconds. An `UpdateMessage`
+        // response then edits the component's message, any other reply is sent as a follow-up
+        false
+    }
 }
 
 pub(crate) fn init_components() -> Vec<Box<dyn Component + Sync>> {
     let mut v: Vec<Box<dyn Component + Sync>> = Vec::new();
     v.push(Box::new(components::delete::Delete {}));
     v.push(Box::new(components::color::Color {}));
+    v.push(Box::new(components::range::Range {}));
+    v.push(Box::new(components::refresh::Refresh {}));
+    v.push(Box::new(components::settings::SettingsPanel {}));
+    v.push(Box::new(components::state::QueueState {}));
+    v.push(Box::new(components::digest::DigestActions {}));
+    v.push(Box::new(components::duplicate::Duplicate {}));
+    v.push(Box::new(components::from_user::FromUserSelect {}));
     v
 }
@@ -1,144 +1,274 @@
 use crate::component::{Component as ComponentTrait, ComponentInput};
+use crate::delivery::ephemeral;
 use crate::error::InteractionError;
-use crate::input::SharedInput;
+use crate::input::default_components;
+use crate::render::show_read_state;
+use crate::settings::{format_hex, parse_hex, Settings};
+use crate::store::{BookmarkStore, ReadState, SourceStatus};
 
 use twilight_model::channel::message::{
-    component::{ActionRow, Button, ButtonStyle},
+    component::{
+        ActionRow, Button, ButtonStyle, SelectMenu, SelectMenuOption, TextInput, TextInputStyle,
+    },
     Component,
 };
 
 use async_trait::async_trait;
-use twilight_model::channel::message::{ReactionType};
+use twilight_model::channel::message::ReactionType;
 use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
-use twilight_model::id::Id;
 use twilight_util::builder::InteractionResponseDataBuilder;
-use worker::console_log;
 
-pub(crate) struct Color {}
+/// Discord's brand colors, shown in every picker.
+const NAMED_COLORS: [(&str, &str, u32); 8] = [
+    ("Blurple", "🔵", 5793266),
+    ("Red", "🔴", 15548997),
+    ("Green", "🟢", 5763719),
+    ("Yellow", "🟡", 16705372),
+    ("Fuchsia", "🟣", 15418782),
+    ("Orange", "🟠", 15105570),
+    ("White", "⚪", 16777215),
+    ("Black", "⚫", 2303786),
+];
 
-#[async_trait(?Send)]
-impl ComponentTrait for Color {
-    async fn respond(
-        &self,
-        input: &ComponentInput,
-    ) -> Result<InteractionResponse, InteractionError> {
-        let custom_id: String = input.custom_id.clone();
+const JUMP_PREFIX: &str = "https://discord.com/channels/";
 
-        // If a colon exists in the custom_id then the user has picked a color, otherwise show the color picker
+pub(crate) struct Color {}
+
+fn option(label: String, value: String, emoji: &str, description: Option<String>) -> SelectMenuOption {
+    SelectMenuOption {
+        default: false,
+        description,
+        emoji: Some(ReactionType::Unicode {
+            name: emoji.to_string(),
+        }),
+        label,
+        value,
+    }
+}
 
-        if custom_id.contains(":") {
-            let (_name, data) = custom_id.split_once(":").unwrap();
-            let (color, url) = data.split_once(":").unwrap();
-            let url = format!("https://discord.com/channels/{}", url);
-            console_log!("color: {}, url: {}", color, url);
-            let msg = input.message.unwrap();
-            let mut embeds = msg.embeds.clone();
+impl Color {
+    // `url` is the jump link without its prefix throughout, custom ids are limited to 100 characters
+    async fn picker(&self, input: &ComponentInput<'_>, url: &str) -> Result<InteractionResponse, InteractionError> {
+        let settings = match BookmarkStore::new(input.ctx) {
+            Ok(store) => Settings::load(&store, input.uid()?).await?,
+            Err(_) => Settings::default(),
+        };
 
-            for mut embed in embeds.iter_mut() {
-                embed.color = Some(color.parse::<u32>().unwrap());
-            }
+        let mut options: Vec<SelectMenuOption> = settings
+            .palette
+            .iter()
+            .map(|c| option(c.name.clone(), c.color.to_string(), "⭐", Some(format_hex(c.color))))
+            .collect();
+        options.extend(NAMED_COLORS.iter().map(|(name, emoji, color)| {
+            option(name.to_string(), color.to_string(), emoji, Some(format_hex(*color)))
+        }));
+        options.push(option(
+            "Custom…".to_string(),
+            "custom".to_string(),
+            "🎨",
+            Some("Enter a hex color".to_string()),
+        ));
 
-            let default_components = input.default_components(url);
-
-            Ok(InteractionResponse {
-                kind: InteractionResponseType::UpdateMessage,
-                data: Some(
-                    InteractionResponseDataBuilder::new()
-                        .embeds(embeds)
-                        .components(default_components)
-                        .content("")
-                        .build(),
-                ),
-            })
-        } else {
-            let Some(Component::ActionRow(row)) = input.message.unwrap().components.last() else {
-                    return Err(InteractionError::WorkerError("No components found".to_string()))
-                };
-            let Some(Component::Button(Button {url, ..})) = row.components.last() else {
-                    return Err(InteractionError::WorkerError("No components found".to_string()))
-                };
-            let url = {
-                let u = url.as_ref().unwrap();
-                let (_, data) = u.split_at(29);
-                data.to_string()
-            };
-            let components = Component::ActionRow(ActionRow {
+        let components = vec![
+            Component::ActionRow(ActionRow {
+                components: vec![Component::SelectMenu(SelectMenu {
+                    custom_id: format!("color:pick:{url}", url = url),
+                    disabled: false,
+                    max_values: Some(1),
+                    min_values: Some(1),
+                    options,
+                    placeholder: Some("Pick a color".to_string()),
+                })],
+            }),
+            Component::ActionRow(ActionRow {
                 components: vec![
                     Component::Button(Button {
-                        style: ButtonStyle::Secondary,
-                        label: None,
-                        custom_id: Some(format!("color:5793266:{url}", url = url)),
-                        emoji: Some(ReactionType::Custom {
-                            id: Id::new(1086977694312439869),
-                            name: Some("bk_blurple".to_string()),
-                            animated: false
-                        }),
-                        url: None,
+                        custom_id: Some(format!("color:default:{url}", url = url)),
                         disabled: false,
-                    }),
-                    Component::Button(Button {
-                        style: ButtonStyle::Secondary,
-                        label: None,
-                        custom_id: Some(format!("color:15548997:{url}", url = url)),
-                        // <:bk_red:1086977702386466867>
-                        emoji: Some(ReactionType::Custom {
-                            id: Id::new(1086977702386466867),
-                            name: Some("bk_red".to_string()),
-                            animated: false
+                        emoji: Some(ReactionType::Unicode {
+                            name: "⭐".to_string(),
                         }),
-                        url: None,
-                        disabled: false,
-                    }),
-                    Component::Button(Button {
+                        label: Some("Make current color my default".to_string()),
                         style: ButtonStyle::Secondary,
-                        label: None,
-                        custom_id: Some(format!("color:5763719:{url}", url = url)),
-                        // <:bk_green:1086977699488223384>
-                        emoji: Some(ReactionType::Custom {
-                            id: Id::new(1086977699488223384),
-                            name: Some("bk_green".to_string()),
-                            animated: false
-                        }),
                         url: None,
-                        disabled: false,
                     }),
-                    // <:bk_yellow:1086977704194216066>
                     Component::Button(Button {
-                        style: ButtonStyle::Secondary,
-                        label: None,
-                        custom_id: Some(format!("color:16705372:{url}", url = url)),
-                        emoji: Some(ReactionType::Custom {
-                            id: Id::new(1086977704194216066),
-                            name: Some("bk_yellow".to_string()),
-                            animated: false
-                        }),
-                        url: None,
+                        custom_id: Some(format!("color:cancel:{url}", url = url)),
                         disabled: false,
-                    }),
-                    // <:bk_fucahsia:1086977696216645657>
-                    Component::Button(Button {
+                        emoji: None,
+                        label: Some("Cancel".to_string()),
                         style: ButtonStyle::Secondary,
-                        label: None,
-                        custom_id: Some(format!("color:15418782:{url}", url = url)),
-                        emoji: Some(ReactionType::Custom {
-                            id: Id::new(1086977696216645657),
-                            name: Some("bk_fucahsia".to_string()),
-                            animated: false
-                        }),
                         url: None,
-                        disabled: false,
                     }),
                 ],
-            });
+            }),
+        ];
 
-            let data = InteractionResponseDataBuilder::new()
-                .components([components])
-                .build();
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .components(components)
+                    .build(),
+            ),
+        })
+    }
 
-            Ok(InteractionResponse {
-                kind: InteractionResponseType::UpdateMessage,
-                data: Some(data),
+    fn hex_modal(&self, url: &str) -> InteractionResponse {
+        let input = |custom_id: &str, label: &str, placeholder: &str, required: bool, max: u16| {
+            Component::ActionRow(ActionRow {
+                components: vec![Component::TextInput(TextInput {
+                    custom_id: custom_id.to_string(),
+                    label: label.to_string(),
+                    max_length: Some(max),
+                    min_length: None,
+                    placeholder: Some(placeholder.to_string()),
+                    required: Some(required),
+                    style: TextInputStyle::Short,
+                    value: None,
+                })],
             })
+        };
+
+        InteractionResponse {
+            kind: InteractionResponseType::Modal,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .custom_id(format!("color:hex:{url}", url = url))
+                    .title("Custom color")
+                    .components([
+                        input("hex", "Hex color", "#5865F2", true, 8),
+                        input("name", "Save to my palette as (optional)", "Team orange", false, 25),
+                    ])
+                    .build(),
+            ),
+        }
+    }
+
+    // Recolors the bookmark and puts the regular buttons back
+    async fn apply(
+        &self,
+        input: &ComponentInput<'_>,
+        url: &str,
+        color: Option<u32>,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let url = format!("{}{}", JUMP_PREFIX, url);
+        let msg = input.message.unwrap();
+        let mut embeds = msg.embeds.clone();
+
+        if let Some(color) = color {
+            for embed in embeds.iter_mut() {
+                embed.color = Some(color);
+            }
+        }
+
+        // Keep the jump link disabled if a refresh found the original deleted, and the queue state
+        let (source_deleted, state) = match BookmarkStore::new(input.ctx) {
+            Ok(store) => store
+                .get(input.uid()?, msg.id)
+                .await?
+                .map_or((false, ReadState::Unread), |b| {
+                    (b.status == SourceStatus::Deleted, b.read_state)
+                }),
+            Err(_) => (false, ReadState::Unread),
+        };
+
+        show_read_state(&mut embeds, state);
+
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .embeds(embeds)
+                    .components(default_components(url, source_deleted, state))
+                    .content(msg.content.clone())
+                    .build(),
+            ),
+        })
+    }
+
+    async fn custom(&self, input: &ComponentInput<'_>, url: &str) -> Result<InteractionResponse, InteractionError> {
+        let Some(color) = input.field("hex").and_then(parse_hex) else {
+            return Ok(InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(ephemeral("That is not a hex color, try something like `#5865F2`")),
+            });
+        };
+
+        if let Some(name) = input.field("name") {
+            let store = BookmarkStore::new(input.ctx)?;
+            let mut settings = Settings::load(&store, input.uid()?).await?;
+            settings.save_color(name.to_string(), color);
+            settings.save(&store, input.uid()?).await?;
+        }
+
+        self.apply(input, url, Some(color)).await
+    }
+
+    async fn make_default(&self, input: &ComponentInput<'_>) -> Result<InteractionResponse, InteractionError> {
+        let color = input
+            .message
+            .unwrap()
+            .embeds
+            .first()
+            .and_then(|e| e.color);
+
+        let store = BookmarkStore::new(input.ctx)?;
+        let mut settings = Settings::load(&store, input.uid()?).await?;
+        settings.default_color = color;
+        settings.save(&store, input.uid()?).await?;
+
+        let content = match color {
+            Some(color) => format!("New bookmarks will use {}", format_hex(color)),
+            None => "New bookmarks will use the default color".to_string(),
+        };
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::ChannelMessageWithSource,
+            data: Some(ephemeral(content)),
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl ComponentTrait for Color {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        // Bookmarks delivered to a channel can be seen by others, only their owner may recolor them.
+        // Bookmarks in DMs can predate the store, their owner is the only one who can click
+        if input.guild_id.is_some() {
+            let store = BookmarkStore::new(input.ctx)?;
+            if store.get(input.uid()?, input.message.unwrap().id).await?.is_none() {
+                return Ok(InteractionResponse {
+                    kind: InteractionResponseType::ChannelMessageWithSource,
+                    data: Some(ephemeral("Only the member who saved this bookmark can change its color")),
+                });
+            }
+        }
+
+        // `color` opens the picker, `color:<action>:<url>` comes from the picker or the hex modal.
+        // `color:<number>:<url>` is sent by pickers opened before the select menu existed.
+        let mut parts = input.custom_id.splitn(3, ':').skip(1);
+        match (parts.next(), parts.next()) {
+            (Some("pick"), Some(url)) => match input.values.first().map(String::as_str) {
+                Some("custom") => Ok(self.hex_modal(url)),
+                Some(value) => self.apply(input, url, value.parse().ok()).await,
+                None => self.apply(input, url, None).await,
+            },
+            (Some("hex"), Some(url)) => self.custom(input, url).await,
+            (Some("default"), Some(_)) => self.make_default(input).await,
+            (Some("cancel"), Some(url)) => self.apply(input, url, None).await,
+            (Some(color), Some(url)) => self.apply(input, url, color.parse().ok()).await,
+            _ => {
+                let Some(Component::ActionRow(row)) = input.message.unwrap().components.first() else {
+                    return Err(InteractionError::WorkerError("No components found".to_string()))
+                };
+                let Some(Component::Button(Button {url: Some(url), ..})) = row.components.last() else {
+                    return Err(InteractionError::WorkerError("No components found".to_string()))
+                };
+                self.picker(input, url.trim_start_matches(JUMP_PREFIX)).await
+            }
         }
     }
 
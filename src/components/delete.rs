@@ -1,53 +1,147 @@
-use crate::component::{Component, ComponentInput};
+use crate::component::{Component as ComponentTrait, ComponentInput};
+use crate::delivery::ephemeral;
 use crate::error::InteractionError;
+use crate::events::{emit_bookmark, EventKind};
+use crate::input::default_components;
+use crate::rest;
+use crate::store::{BookmarkStore, ReadState, SourceStatus};
 
 use async_trait::async_trait;
-use reqwest::{StatusCode};
-use twilight_model::channel::message::MessageFlags;
+use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle};
+use twilight_model::channel::message::{Component, MessageFlags, ReactionType};
 use twilight_model::http::interaction::{InteractionResponseType, InteractionResponse};
 use twilight_util::builder::InteractionResponseDataBuilder;
-use worker::console_log;
+use worker::{console_log, Date};
 
 pub(crate) struct Delete {}
 
-#[async_trait(?Send)]
-impl Component for Delete {
-    async fn respond(
+impl Delete {
+    // Swaps the bookmark buttons for a confirmation, `url` is the jump link without its prefix
+    fn confirm(&self, url: &str) -> InteractionResponse {
+        let components = Component::ActionRow(ActionRow {
+            components: vec![
+                Component::Button(Button {
+                    custom_id: Some("delete:confirm".to_string()),
+                    disabled: false,
+                    emoji: Some(ReactionType::Unicode {
+                        name: "🗑️".to_string(),
+                    }),
+                    label: Some("Move to trash".to_string()),
+                    style: ButtonStyle::Danger,
+                    url: None,
+                }),
+                Component::Button(Button {
+                    custom_id: Some(format!("delete:cancel:{url}", url = url)),
+                    disabled: false,
+                    emoji: None,
+                    label: Some("Cancel".to_string()),
+                    style: ButtonStyle::Secondary,
+                    url: None,
+                }),
+            ],
+        });
+
+        InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .components([components])
+                    .build(),
+            ),
+        }
+    }
+
+    async fn cancel(
         &self,
-        input: &ComponentInput,
+        input: &ComponentInput<'_>,
+        url: &str,
     ) -> Result<InteractionResponse, InteractionError> {
+        let url = format!("https://discord.com/channels/{}", url);
+        let (source_deleted, state) = match BookmarkStore::new(input.ctx) {
+            Ok(store) => store
+                .get(input.uid()?, input.message.unwrap().id)
+                .await?
+                .map_or((false, ReadState::Unread), |b| {
+                    (b.status == SourceStatus::Deleted, b.read_state)
+                }),
+            Err(_) => (false, ReadState::Unread),
+        };
+
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .components(default_components(url, source_deleted, state))
+                    .build(),
+            ),
+        })
+    }
+
+    async fn delete(&self, input: &ComponentInput<'_>) -> Result<InteractionResponse, InteractionError> {
+        let message_id = input.message.unwrap().id;
+
+        // Keep a copy in the trash when the bookmark is known to the store
+        let mut trashed = false;
+        if let Ok(store) = BookmarkStore::new(input.ctx) {
+            if let Some(bookmark) = store.get(input.uid()?, message_id).await? {
+                emit_bookmark(input.ctx, EventKind::BookmarkDeleted, &bookmark).await;
+                store.trash(bookmark, Date::now().as_millis()).await?;
+                trashed = true;
+            } else if input.guild_id.is_some() {
+                // Bookmarks delivered to a channel can be seen by others, only their owner may delete them
+                return Ok(InteractionResponse {
+                    kind: InteractionResponseType::ChannelMessageWithSource,
+                    data: Some(ephemeral("Only the member who saved this bookmark can delete it")),
+                });
+            }
+        }
+
+        let content = if trashed {
+            "Bookmark moved to the trash. Restore it within 30 days with `/bookmarks trash restore`"
+        } else {
+            "Bookmark Deleted"
+        };
         let data = InteractionResponseDataBuilder::new()
-            .content("Bookmark Deleted")
+            .content(content)
             .flags(MessageFlags::EPHEMERAL)
             .build();
-        
+
         let client = input.http_client()?;
 
-        // Delete message endpoint
-        let url = format!("https://discord.com/api/v10/channels/{}/messages/{}", input.channel_id.unwrap().get(), input.message.unwrap().id.get());
-        match client.delete(url).send().await {
-            Ok(response) => {
-                let status = response.status();
-                let text: String = response.text().await?;
-                console_log!("[DELETE MESSAGE] response: {:?}", text);
-                match status {
-                    StatusCode::OK => {
-                        console_log!("Message deleted");
-                    }
-                    _ => {
-                        console_log!("Error deleting message");
-                    }
-                }
-            }
-            Err(e) => {
-                console_log!("Error deleting message: {:?}", e);
-            }
+        let channel_id = input.channel_id.unwrap();
+        if let Err(err) = rest::delete_message(&client, channel_id, message_id).await {
+            console_log!("Error deleting message: {}", err);
         }
         Ok(InteractionResponse {
             kind: InteractionResponseType::ChannelMessageWithSource,
             data: Some(data)
         })
     }
+}
+
+#[async_trait(?Send)]
+impl ComponentTrait for Delete {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        // `delete` asks for confirmation, `delete:confirm` and `delete:cancel:<url>` answer it
+        match input.custom_id.split_once(':') {
+            Some((_, "confirm")) => self.delete(input).await,
+            Some((_, data)) if data.starts_with("cancel:") => {
+                self.cancel(input, data.trim_start_matches("cancel:")).await
+            }
+            _ => {
+                let Some(Component::ActionRow(row)) = input.message.unwrap().components.first() else {
+                    return Err(InteractionError::WorkerError("No components found".to_string()))
+                };
+                let Some(Component::Button(Button {url: Some(url), ..})) = row.components.last() else {
+                    return Err(InteractionError::WorkerError("No components found".to_string()))
+                };
+                Ok(self.confirm(url.trim_start_matches("https://discord.com/channels/")))
+            }
+        }
+    }
 
     fn custom_id(&self) -> String {
         "delete".into()
@@ -0,0 +1,78 @@
+use crate::component::{Component, ComponentInput};
+use crate::delivery::ephemeral;
+use crate::digest::{digest_key, snooze_until};
+use crate::error::InteractionError;
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState};
+
+use async_trait::async_trait;
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::Date;
+
+/// The buttons of the weekly digest, `digest:archive` and `digest:snooze`.
+pub(crate) struct DigestActions {}
+
+#[async_trait(?Send)]
+impl Component for DigestActions {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let msg = input.message.unwrap();
+        let user_id = input.uid()?;
+        let store = BookmarkStore::new(input.ctx)?;
+        let key = digest_key(user_id, msg.id);
+
+        let Some(ids) = store.get_value::<Vec<Id<MessageMarker>>>(&key).await? else {
+            return Ok(InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(ephemeral("This digest has expired")),
+            });
+        };
+
+        let archive = input.custom_id == "digest:archive";
+        let now = Date::now().as_millis();
+        let snooze = snooze_until(now, Settings::for_user(input.ctx, user_id).await.utc_offset_minutes());
+        let mut changed = 0;
+        for id in ids {
+            let Some(mut bookmark) = store.get(user_id, id).await? else {
+                continue;
+            };
+            if archive {
+                bookmark.read_state = ReadState::Archived;
+            } else {
+                bookmark.remind_at = Some(snooze);
+            }
+            store.put(&bookmark).await?;
+            changed += 1;
+        }
+        store.delete_value(&key).await?;
+
+        let content = if archive {
+            format!("📦 Archived {} bookmarks", changed)
+        } else {
+            format!(
+                "⏰ {} bookmarks will be in next week's digest, with a reminder <t:{}:f>",
+                changed,
+                snooze / 1000
+            )
+        };
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .content(content)
+                    .embeds(msg.embeds.clone())
+                    .components([])
+                    .build(),
+            ),
+        })
+    }
+
+    fn custom_id(&self) -> String {
+        "digest".into()
+    }
+}
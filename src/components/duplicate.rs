@@ -0,0 +1,162 @@
+use crate::component::{Component as ComponentTrait, ComponentInput};
+use crate::components::refresh::refresh_bookmark;
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::rest;
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, StoredBookmark};
+
+use async_trait::async_trait;
+use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle};
+use twilight_model::channel::message::{Component, MessageFlags, ReactionType};
+use twilight_model::http::interaction::{
+    InteractionResponse, InteractionResponseData, InteractionResponseType,
+};
+use twilight_model::id::marker::MessageMarker;
+use twilight_model::id::Id;
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::console_log;
+
+/// Answers the offer made when a message is bookmarked again, `duplicate:resend:<id>` and
+/// `duplicate:refresh:<id>` with the id of the existing bookmark message.
+pub(crate) struct Duplicate {}
+
+fn button(custom_id: String, emoji: &str, label: &str) -> Component {
+    Component::Button(Button {
+        custom_id: Some(custom_id),
+        disabled: false,
+        emoji: Some(ReactionType::Unicode {
+            name: emoji.to_string(),
+        }),
+        label: Some(label.to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    })
+}
+
+fn link(bookmark: &StoredBookmark) -> Component {
+    Component::Button(Button {
+        custom_id: None,
+        disabled: false,
+        emoji: Some(ReactionType::Unicode {
+            name: "🔖".to_string(),
+        }),
+        label: Some("Go to bookmark".to_string()),
+        style: ButtonStyle::Link,
+        url: Some(bookmark.delivered_url()),
+    })
+}
+
+/// The ephemeral reply to bookmarking a message that already has a bookmark.
+pub(crate) fn offer(existing: &StoredBookmark) -> InteractionResponseData {
+    let id = existing.dm_message_id;
+    InteractionResponseDataBuilder::new()
+        .content(format!(
+            "You already bookmarked this message <t:{}:R>",
+            existing.created_at / 1000
+        ))
+        .components([Component::ActionRow(ActionRow {
+            components: vec![
+                link(existing),
+                button(format!("duplicate:resend:{}", id), "📨", "Send again"),
+                button(format!("duplicate:refresh:{}", id), "🔄", "Refresh"),
+            ],
+        })])
+        .flags(MessageFlags::EPHEMERAL)
+        .build()
+}
+
+fn reply(content: String, bookmark: &StoredBookmark) -> InteractionResponse {
+    InteractionResponse {
+        kind: InteractionResponseType::UpdateMessage,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .content(content)
+                .components([Component::ActionRow(ActionRow {
+                    components: vec![link(bookmark)],
+                })])
+                .build(),
+        ),
+    }
+}
+
+#[async_trait(?Send)]
+impl ComponentTrait for Duplicate {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let mut parts = input.custom_id.splitn(3, ':').skip(1);
+        let (Some(action), Some(id)) = (
+            parts.next(),
+            parts
+                .next()
+                .and_then(|id| id.parse().ok())
+                .and_then(Id::<MessageMarker>::new_checked),
+        ) else {
+            return Err(InteractionError::WorkerError("Invalid custom id".into()));
+        };
+
+        let user_id = input.uid()?;
+        let store = BookmarkStore::new(input.ctx)?;
+        let Some(mut bookmark) = store.get(user_id, id).await? else {
+            return Ok(InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(ephemeral("That bookmark no longer exists")),
+            });
+        };
+
+        let client = input.http_client()?;
+        let (channel_id, old_id) = (bookmark.dm_channel_id, bookmark.dm_message_id);
+        // Keep the colour the user picked, the message may be gone when sending it again
+        let color = match rest::get_message(&client, channel_id, old_id).await {
+            Ok(message) => message.embeds.first().and_then(|e| e.color),
+            Err(_) => Settings::for_user(input.ctx, user_id).await.default_color,
+        };
+
+        let data = match refresh_bookmark(input.ctx, &client, &store, &mut bookmark, color).await? {
+            Ok(data) => data,
+            Err(message) => {
+                return Ok(InteractionResponse {
+                    kind: InteractionResponseType::ChannelMessageWithSource,
+                    data: Some(ephemeral(message)),
+                })
+            }
+        };
+        let body = serde_json::to_value(&data)?;
+
+        if action == "refresh" {
+            rest::edit_message(&client, channel_id, old_id, &body).await?;
+            let status = data.content.unwrap_or_default();
+            return Ok(reply(format!("Refreshed your bookmark\n{}", status), &bookmark));
+        }
+
+        // Sending again moves the bookmark to a new message, keeping its note, tags and state
+        let sent = match rest::create_message(&client, channel_id, &body).await {
+            Ok(sent) => sent,
+            Err(err) => {
+                return Ok(InteractionResponse {
+                    kind: InteractionResponseType::ChannelMessageWithSource,
+                    data: Some(ephemeral(format!("I couldn't send your bookmark again ({})", err))),
+                })
+            }
+        };
+        store.delete(user_id, old_id).await?;
+        bookmark.dm_message_id = sent.id;
+        store.put(&bookmark).await?;
+        if let Err(err) = rest::delete_message(&client, channel_id, old_id).await {
+            console_log!("[DUPLICATE] deleting the old bookmark message failed: {}", err);
+        }
+
+        Ok(reply("Sent your bookmark again".to_string(), &bookmark))
+    }
+
+    fn custom_id(&self) -> String {
+        "duplicate".into()
+    }
+
+    fn deferred(&self, _input: &ComponentInput) -> bool {
+        // Rendering can archive attachments, which may take longer than Discord waits
+        true
+    }
+}
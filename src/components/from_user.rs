@@ -0,0 +1,47 @@
+use crate::commands::from_user::bookmark_recent;
+use crate::component::{Component, ComponentInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+
+use async_trait::async_trait;
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use twilight_model::id::Id;
+
+pub(crate) struct FromUserSelect {}
+
+#[async_trait(?Send)]
+impl Component for FromUserSelect {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        // custom_id is `from-user:<channel id>:<author id>`, the value is the message count
+        let mut parts = input.custom_id.split(':').skip(1);
+        let channel_id = parts.next().and_then(|id| id.parse().ok()).and_then(Id::new_checked);
+        let author_id = parts.next().and_then(|id| id.parse().ok()).and_then(Id::new_checked);
+        let count = input.values.first().and_then(|v| v.parse::<usize>().ok());
+
+        let data = match (input.guild_id, channel_id, author_id, count) {
+            (Some(guild_id), Some(channel_id), Some(author_id), Some(count)) => {
+                let client = input.http_client()?;
+                bookmark_recent(input.ctx, &client, input.uid()?, guild_id, channel_id, author_id, count)
+                    .await?
+            }
+            _ => ephemeral("That selection is no longer valid"),
+        };
+
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(data),
+        })
+    }
+
+    fn custom_id(&self) -> String {
+        "from-user".into()
+    }
+
+    fn deferred(&self, _input: &ComponentInput) -> bool {
+        // Rendering can archive attachments, which may take longer than Discord waits
+        true
+    }
+}
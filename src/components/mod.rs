@@ -1,2 +1,9 @@
 pub mod delete;
-pub mod color;
\ No newline at end of file
+pub mod color;
+pub mod range;
+pub mod refresh;
+pub mod settings;
+pub mod state;
+pub mod digest;
+pub mod duplicate;
+pub mod from_user;
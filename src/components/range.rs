@@ -0,0 +1,85 @@
+use crate::commands::range::{bookmark_conversation, pending_key, Conversation, MAX_MESSAGES};
+use crate::component::{Component, ComponentInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::store::BookmarkStore;
+
+use async_trait::async_trait;
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use twilight_model::id::marker::{ChannelMarker, MessageMarker};
+use twilight_model::id::Id;
+
+pub(crate) struct Range {}
+
+#[async_trait(?Send)]
+impl Component for Range {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let data = match (input.guild_id, parse_selection(&input.custom_id, &input.values)) {
+            (Some(guild_id), Some((channel_id, start, count))) => {
+                // Picking a count finishes the range, the pending start must not linger
+                if let Ok(store) = BookmarkStore::new(input.ctx) {
+                    store.delete_value(&pending_key(input.uid()?)).await?;
+                }
+                let conversation = Conversation {
+                    guild_id,
+                    channel_id,
+                    start,
+                    end: None,
+                    count,
+                };
+                bookmark_conversation(input.ctx, &input.http_client()?, input.uid()?, conversation)
+                    .await?
+            }
+            _ => ephemeral("That selection is no longer valid"),
+        };
+
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(data),
+        })
+    }
+
+    fn custom_id(&self) -> String {
+        "range".into()
+    }
+
+    fn deferred(&self, _input: &ComponentInput) -> bool {
+        // Fetching and checking up to 20 messages may take longer than Discord waits
+        true
+    }
+}
+
+// custom_id is `range:<channel id>:<first message id>`, the value is the message count
+fn parse_selection(
+    custom_id: &str,
+    values: &[String],
+) -> Option<(Id<ChannelMarker>, Id<MessageMarker>, u8)> {
+    let mut parts = custom_id.split(':').skip(1);
+    let channel_id = parts.next()?.parse().ok().and_then(Id::new_checked)?;
+    let start = parts.next()?.parse().ok().and_then(Id::new_checked)?;
+    let count = values.first()?.parse::<u8>().ok().filter(|n| (1..=MAX_MESSAGES).contains(n))?;
+    Some((channel_id, start, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selections_are_parsed_from_the_custom_id() {
+        let values = vec!["10".to_string()];
+        assert_eq!(
+            parse_selection("range:12:34", &values),
+            Some((Id::new(12), Id::new(34), 10))
+        );
+        assert_eq!(parse_selection("range:12", &values), None);
+        assert_eq!(parse_selection("range:x:34", &values), None);
+        assert_eq!(parse_selection("range:0:34", &values), None);
+        assert_eq!(parse_selection("range:12:34", &[]), None);
+        assert_eq!(parse_selection("range:12:34", &["0".to_string()]), None);
+        assert_eq!(parse_selection("range:12:34", &["21".to_string()]), None);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use crate::RouteData;
+use crate::component::{Component, ComponentInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::input::default_components;
+use crate::render::{bookmark_embeds, show_read_state};
+use crate::rest::{self, RestError};
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, SourceStatus, StoredBookmark};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use twilight_model::channel::Message;
+use twilight_model::http::interaction::{
+    InteractionResponse, InteractionResponseData, InteractionResponseType,
+};
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::{Date, RouteContext};
+
+pub(crate) struct Refresh {}
+
+// Changes are shown inside the bookmark message's content, which holds at most 2000 characters
+const MAX_DIFF_CHARS: usize = 1700;
+
+fn edited(snapshot: &Message, live: &Message) -> bool {
+    snapshot.edited_timestamp != live.edited_timestamp
+        || snapshot.content != live.content
+        || snapshot.attachments.len() != live.attachments.len()
+}
+
+/// The lines removed from and added to `old` to get `new`, as `- line` and `+ line`.
+fn line_diff(old: &str, new: &str) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// What changed between the saved copy of a message and its current version, attachments included.
+fn message_diff(snapshot: &Message, live: &Message) -> Vec<String> {
+    let mut diff = line_diff(&snapshot.content, &live.content);
+    let removed = snapshot
+        .attachments
+        .iter()
+        .filter(|a| !live.attachments.iter().any(|l| l.id == a.id));
+    diff.extend(removed.map(|a| format!("- 📎 {}", a.filename)));
+    diff
+}
+
+/// Renders the diffs of the edited messages as a `diff` code block, cut to fit in a message.
+fn diff_block(diffs: &[(String, Vec<String>)]) -> Option<String> {
+    let mut lines = Vec::new();
+    for (author, diff) in diffs.iter().filter(|(_, diff)| !diff.is_empty()) {
+        if diffs.len() > 1 {
+            lines.push(format!("@@ {} @@", author));
+        }
+        lines.extend(diff.iter().map(|line| line.replace("```", "`\u{200b}``")));
+    }
+    if lines.is_empty() {
+        return None;
+    }
+
+    let mut block = String::new();
+    for line in lines {
+        if block.chars().count() + line.chars().count() > MAX_DIFF_CHARS {
+            block.push_str("…\n");
+            break;
+        }
+        block.push_str(&line);
+        block.push('\n');
+    }
+    Some(format!("```diff\n{}```", block))
+}
+
+/// Reloads the original messages of `bookmark`, saves what changed and renders the bookmark message
+/// again in `color`. `Err` holds the message to show when the originals can't be reached.
+pub(crate) async fn refresh_bookmark(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    store: &BookmarkStore,
+    bookmark: &mut StoredBookmark,
+    color: Option<u32>,
+) -> Result<Result<InteractionResponseData, &'static str>, InteractionError> {
+    let t_url = bookmark.jump_url();
+    let Some(guild_id) = bookmark.guild_id else {
+        return Err(InteractionError::WorkerError("Bookmark without a server".into()));
+    };
+    // The server's rules apply to what is shown now, not just to what was saved
+    let config = GuildConfig::for_guild(ctx, guild_id).await;
+    if config.channel_disabled(bookmark.channel_id) {
+        return Ok(Err("Bookmarking is disabled in that channel now"));
+    }
+
+    // Messages that still exist, by id
+    let mut live: HashMap<_, Message> = if bookmark.messages.len() == 1 {
+        match rest::get_message(client, bookmark.channel_id, bookmark.messages[0].id).await {
+            Ok(message) => HashMap::from([(message.id, message)]),
+            Err(RestError::NotFound) => HashMap::new(),
+            Err(RestError::Forbidden) => return Ok(Err("I can no longer see the original message")),
+            Err(err) => return Err(err.into()),
+        }
+    } else {
+        let first = bookmark.messages[0].id;
+        let last = bookmark.messages[bookmark.messages.len() - 1].id;
+        rest::get_messages_after(client, bookmark.channel_id, first.get() - 1, 100)
+            .await?
+            .into_iter()
+            .filter(|m| m.id <= last)
+            .map(|m| (m.id, m))
+            .collect()
+    };
+    for message in live.values_mut() {
+        config.strip(message);
+    }
+
+    let deleted = bookmark
+        .messages
+        .iter()
+        .filter(|m| !live.contains_key(&m.id))
+        .count();
+    let changed = bookmark
+        .messages
+        .iter()
+        .filter(|m| live.get(&m.id).is_some_and(|l| edited(m, l)))
+        .count();
+    // Always against the saved copy, which refreshing never overwrites
+    let diffs: Vec<(String, Vec<String>)> = bookmark
+        .messages
+        .iter()
+        .filter_map(|m| Some((m.author.name.clone(), message_diff(m, live.get(&m.id)?))))
+        .collect();
+
+    // The saved copy is kept for deleted messages, everything else shows its current version
+    let current = bookmark
+        .messages
+        .iter()
+        .map(|m| live.get(&m.id).unwrap_or(m).clone())
+        .collect::<Vec<Message>>();
+
+    bookmark.status = if deleted == bookmark.messages.len() {
+        SourceStatus::Deleted
+    } else if deleted > 0 || changed > 0 {
+        SourceStatus::Edited
+    } else {
+        SourceStatus::Unchanged
+    };
+
+    let status = match (bookmark.messages.len(), bookmark.status) {
+        (_, SourceStatus::Deleted) => "🗑️ Original deleted, showing the saved copy".to_string(),
+        (1, SourceStatus::Edited) => "✏️ Edited since bookmarked".to_string(),
+        (_, SourceStatus::Edited) => format!(
+            "✏️ {} edited and {} deleted since bookmarked",
+            changed, deleted
+        ),
+        (_, SourceStatus::Unchanged) => "✅ Unchanged since bookmarked".to_string(),
+    };
+    let mut content = format!("{} · checked <t:{}:R>", status, Date::now().as_millis() / 1000);
+    if let Some(block) = diff_block(&diffs) {
+        content = format!("{}\n{}", content, block);
+    }
+
+    let guild = rest::get_guild(client, guild_id).await?;
+    let settings = Settings::for_user(ctx, bookmark.user_id).await;
+    let mut embeds = bookmark_embeds(ctx, client, &current, &guild, &t_url, &settings).await;
+    // Keep the colour the user picked
+    if let Some(color) = color {
+        for embed in embeds.iter_mut() {
+            embed.color = Some(color);
+        }
+    }
+    show_read_state(&mut embeds, bookmark.read_state);
+
+    store.put(bookmark).await?;
+
+    Ok(Ok(InteractionResponseDataBuilder::new()
+        .content(content)
+        .embeds(embeds)
+        .components(default_components(
+            &t_url,
+            bookmark.status == SourceStatus::Deleted,
+            bookmark.read_state,
+        ))
+        .build()))
+}
+
+#[async_trait(?Send)]
+impl Component for Refresh {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let dm_message = input.message.unwrap();
+        let store = BookmarkStore::new(input.ctx)?;
+        let Some(mut bookmark) = store.get(input.uid()?, dm_message.id).await? else {
+            // Bookmarks delivered to a channel can be seen by others, only their owner may refresh them
+            let message = if input.guild_id.is_some() {
+                "Only the member who saved this bookmark can refresh it"
+            } else {
+                "This bookmark was saved before refreshing was available"
+            };
+            return Ok(InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(ephemeral(message)),
+            });
+        };
+
+        let client = input.http_client()?;
+        let color = dm_message.embeds.first().and_then(|e| e.color);
+        match refresh_bookmark(input.ctx, &client, &store, &mut bookmark, color).await? {
+            Ok(data) => Ok(InteractionResponse {
+                kind: InteractionResponseType::UpdateMessage,
+                data: Some(data),
+            }),
+            Err(message) => Ok(InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(ephemeral(message)),
+            }),
+        }
+    }
+
+    fn custom_id(&self) -> String {
+        "refresh".into()
+    }
+
+    fn deferred(&self, _input: &ComponentInput) -> bool {
+        // Rendering can archive attachments, which may take longer than Discord waits
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_show_removed_and_added_lines() {
+        assert_eq!(line_diff("a\nb\nc", "a\nb\nc"), Vec::<String>::new());
+        assert_eq!(line_diff("a\nb\nc", "a\nB\nc"), ["- b", "+ B"]);
+        assert_eq!(line_diff("a", "a\nb"), ["+ b"]);
+        assert_eq!(line_diff("a\nb", ""), ["- a", "- b"]);
+    }
+
+    #[test]
+    fn diff_blocks_fit_in_a_message() {
+        let long = vec![("ann".to_string(), vec!["+ x".repeat(100); 20])];
+        let block = diff_block(&long).unwrap();
+        assert!(block.chars().count() < 2000 - 100);
+        assert!(block.ends_with("…\n```"));
+
+        let several = vec![
+            ("ann".to_string(), vec!["- a".to_string(), "+ ```b".to_string()]),
+            ("bob".to_string(), vec![]),
+        ];
+        assert_eq!(diff_block(&several).unwrap(), "```diff\n@@ ann @@\n- a\n+ `\u{200b}``b\n```");
+        assert_eq!(diff_block(&several[1..]), None);
+    }
+}
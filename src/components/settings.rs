@@ -0,0 +1,214 @@
+use crate::commands::settings::panel;
+use crate::component::{Component as ComponentTrait, ComponentInput};
+use crate::delivery::ephemeral;
+use crate::digest::set_opt_in;
+use crate::error::InteractionError;
+use crate::rest;
+use crate::settings::{
+    format_hex, format_utc_offset, parse_hex, parse_utc_offset, parse_webhook_url, Delivery,
+    Settings, LOCALES,
+};
+use crate::store::BookmarkStore;
+
+use async_trait::async_trait;
+use twilight_model::channel::message::component::{ActionRow, TextInput, TextInputStyle};
+use twilight_model::channel::message::Component;
+use twilight_model::guild::Permissions;
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+pub(crate) struct SettingsPanel {}
+
+// What the bot needs to post bookmarks in a channel
+const CHANNEL_PERMISSIONS: Permissions = Permissions::VIEW_CHANNEL
+    .union(Permissions::SEND_MESSAGES)
+    .union(Permissions::EMBED_LINKS);
+
+fn text_input(
+    custom_id: &str,
+    label: &str,
+    placeholder: &str,
+    value: Option<String>,
+    max_length: u16,
+) -> Component {
+    Component::ActionRow(ActionRow {
+        components: vec![Component::TextInput(TextInput {
+            custom_id: custom_id.to_string(),
+            label: label.to_string(),
+            max_length: Some(max_length),
+            min_length: None,
+            placeholder: Some(placeholder.to_string()),
+            required: Some(false),
+            style: TextInputStyle::Short,
+            value,
+        })],
+    })
+}
+
+impl SettingsPanel {
+    fn edit_modal(&self, settings: &Settings) -> InteractionResponse {
+        InteractionResponse {
+            kind: InteractionResponseType::Modal,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .custom_id("settings:modal")
+                    .title("Color & timezone")
+                    .components([
+                        text_input(
+                            "color",
+                            "Default color, empty to clear",
+                            "#5865F2",
+                            settings.default_color.map(format_hex),
+                            16,
+                        ),
+                        text_input(
+                            "timezone",
+                            "Reminder timezone as a UTC offset",
+                            "UTC+02:00",
+                            settings.timezone.clone(),
+                            16,
+                        ),
+                    ])
+                    .build(),
+            ),
+        }
+    }
+
+    fn webhook_modal(&self) -> InteractionResponse {
+        InteractionResponse {
+            kind: InteractionResponseType::Modal,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .custom_id("settings:webhook")
+                    .title("Deliver to a webhook")
+                    .components([text_input(
+                        "url",
+                        "Webhook URL",
+                        "https://discord.com/api/webhooks/…",
+                        None,
+                        200,
+                    )])
+                    .build(),
+            ),
+        }
+    }
+
+    // Applies the modal, `Err` holds the message for an invalid value
+    fn apply_modal(&self, input: &ComponentInput<'_>, settings: &mut Settings) -> Result<(), &'static str> {
+        settings.default_color = match input.field("color") {
+            Some(hex) => Some(parse_hex(hex).ok_or("That is not a hex color, try something like `#5865F2`")?),
+            None => None,
+        };
+        settings.timezone = match input.field("timezone") {
+            Some(tz) => Some(format_utc_offset(
+                parse_utc_offset(tz).ok_or("Enter the timezone as an offset, like `UTC+02:00`")?,
+            )),
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl ComponentTrait for SettingsPanel {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let store = BookmarkStore::new(input.ctx)?;
+        let user_id = input.uid()?;
+        let mut settings = Settings::load(&store, user_id).await?;
+        let value = input.values.first().map(String::as_str);
+
+        match input.custom_id.as_str() {
+            "settings:delivery" => match (value, input.channel_id) {
+                (Some("dm"), _) => settings.delivery = Delivery::Dm,
+                (Some("channel"), Some(channel_id)) => {
+                    // Bookmarks posted to a channel are seen by everyone in it
+                    let manages = input
+                        .member
+                        .and_then(|m| m.permissions)
+                        .is_some_and(|p| p.intersects(Permissions::MANAGE_CHANNELS | Permissions::MANAGE_GUILD));
+                    if !manages {
+                        return Ok(InteractionResponse {
+                            kind: InteractionResponseType::ChannelMessageWithSource,
+                            data: Some(ephemeral(
+                                "Delivering to a channel needs the Manage Channels or Manage Server permission",
+                            )),
+                        });
+                    }
+                    let allowed = input
+                        .app_permissions
+                        .is_some_and(|p| p.contains(CHANNEL_PERMISSIONS));
+                    if !allowed {
+                        return Ok(InteractionResponse {
+                            kind: InteractionResponseType::ChannelMessageWithSource,
+                            data: Some(ephemeral(
+                                "I need to be able to view, send messages and embed links in this channel",
+                            )),
+                        });
+                    }
+                    settings.delivery = Delivery::Channel {
+                        channel_id,
+                        guild_id: input.guild_id,
+                    }
+                }
+                (Some("webhook"), _) => return Ok(self.webhook_modal()),
+                // The current channel was picked again
+                _ => {}
+            },
+            "settings:webhook" => match input.field("url").and_then(parse_webhook_url) {
+                Some(url) => {
+                    // Also tells a deleted webhook apart from a typo
+                    let Ok(guild_id) = rest::get_webhook_guild(&url).await else {
+                        return Ok(InteractionResponse {
+                            kind: InteractionResponseType::ChannelMessageWithSource,
+                            data: Some(ephemeral("That webhook doesn't exist")),
+                        });
+                    };
+                    settings.delivery = Delivery::Webhook { url, guild_id }
+                }
+                None => {
+                    return Ok(InteractionResponse {
+                        kind: InteractionResponseType::ChannelMessageWithSource,
+                        data: Some(ephemeral("That is not a Discord webhook URL")),
+                    })
+                }
+            },
+            "settings:locale" => {
+                settings.locale = value
+                    .and_then(|code| LOCALES.iter().find(|(c, _)| *c == code))
+                    .map(|(code, _)| code.to_string());
+            }
+            "settings:toggle:reply" => settings.reply_context = !settings.reply_context,
+            "settings:toggle:archive" => {
+                settings.archive_attachments = !settings.archive_attachments
+            }
+            "settings:toggle:digest" => {
+                settings.digest = !settings.digest;
+                set_opt_in(&store, user_id, settings.digest).await?;
+            }
+            "settings:edit" => return Ok(self.edit_modal(&settings)),
+            "settings:modal" => {
+                if let Err(message) = self.apply_modal(input, &mut settings) {
+                    return Ok(InteractionResponse {
+                        kind: InteractionResponseType::ChannelMessageWithSource,
+                        data: Some(ephemeral(message)),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        settings.save(&store, user_id).await?;
+
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(panel(&settings, input.guild_id.is_some())),
+        })
+    }
+
+    fn custom_id(&self) -> String {
+        "settings".into()
+    }
+}
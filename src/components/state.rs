@@ -0,0 +1,60 @@
+use crate::component::{Component, ComponentInput};
+use crate::delivery::ephemeral;
+use crate::error::InteractionError;
+use crate::input::default_components;
+use crate::render::show_read_state;
+use crate::store::{BookmarkStore, ReadState, SourceStatus};
+
+use async_trait::async_trait;
+use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use twilight_util::builder::InteractionResponseDataBuilder;
+
+/// The read-later buttons on a bookmark, `state:read`, `state:unread` and `state:archive`.
+pub(crate) struct QueueState {}
+
+#[async_trait(?Send)]
+impl Component for QueueState {
+    async fn respond(
+        &self,
+        input: &ComponentInput,
+    ) -> Result<InteractionResponse, InteractionError> {
+        let state = match input.custom_id.trim_start_matches("state:") {
+            "read" => ReadState::Read,
+            "archive" => ReadState::Archived,
+            _ => ReadState::Unread,
+        };
+
+        let msg = input.message.unwrap();
+        let store = BookmarkStore::new(input.ctx)?;
+        let Some(mut bookmark) = store.get(input.uid()?, msg.id).await? else {
+            return Ok(InteractionResponse {
+                kind: InteractionResponseType::ChannelMessageWithSource,
+                data: Some(ephemeral("Only the member who saved this bookmark can change it")),
+            });
+        };
+        bookmark.read_state = state;
+        store.put(&bookmark).await?;
+
+        let mut embeds = msg.embeds.clone();
+        show_read_state(&mut embeds, state);
+
+        Ok(InteractionResponse {
+            kind: InteractionResponseType::UpdateMessage,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .embeds(embeds)
+                    .components(default_components(
+                        bookmark.jump_url(),
+                        bookmark.status == SourceStatus::Deleted,
+                        state,
+                    ))
+                    .content(msg.content.clone())
+                    .build(),
+            ),
+        })
+    }
+
+    fn custom_id(&self) -> String {
+        "state".into()
+    }
+}
@@ -0,0 +1,244 @@
+use reqwest::Client;
+use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle};
+use twilight_model::channel::message::{Component, Embed, MessageFlags, ReactionType};
+use twilight_model::channel::Message;
+use twilight_model::http::interaction::InteractionResponseData;
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, UserMarker},
+    Id,
+};
+use twilight_util::builder::InteractionResponseDataBuilder;
+use worker::{console_log, Date, RouteContext};
+
+use crate::RouteData;
+use crate::audit::log_bookmark;
+use crate::board::record_bookmark;
+use crate::error::InteractionError;
+use crate::events::{emit_bookmark, EventKind};
+use crate::rest::{self, RestError};
+use crate::settings::{Delivery, Settings};
+use crate::render::show_read_state;
+use crate::store::{BookmarkStore, ReadState, SourceStatus, StoredBookmark, MAX_BOOKMARKS};
+
+/// A bookmark that has been rendered but not yet sent to its owner.
+pub(crate) struct NewBookmark {
+    pub(crate) user_id: Id<UserMarker>,
+    pub(crate) guild_id: Option<Id<GuildMarker>>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    pub(crate) messages: Vec<Message>,
+}
+
+pub(crate) fn ephemeral<S: Into<String>>(content: S) -> InteractionResponseData {
+    InteractionResponseDataBuilder::new()
+        .content(content)
+        .flags(MessageFlags::EPHEMERAL)
+        .build()
+}
+
+/// The reply to a saved bookmark, with an optional note on where it went.
+pub(crate) fn bookmarked(note: Option<&str>) -> InteractionResponseData {
+    let mut data = InteractionResponseDataBuilder::new()
+        .components(vec![Component::ActionRow(ActionRow {
+            components: vec![Component::Button(Button {
+                custom_id: Some("bookmark".to_string()),
+                disabled: true,
+                emoji: Some(ReactionType::Unicode {
+                    name: "🔖".to_string(),
+                }),
+                label: Some("Bookmarked".to_string()),
+                style: ButtonStyle::Primary,
+                url: None,
+            })],
+        })])
+        .flags(MessageFlags::EPHEMERAL);
+    if let Some(note) = note {
+        data = data.content(note);
+    }
+    data.build()
+}
+
+// Posts to the channel or webhook picked in the settings, `None` means DMs should be used
+async fn post_to_target(
+    client: &Client,
+    delivery: &Delivery,
+    body: &serde_json::Value,
+) -> Option<Result<Message, RestError>> {
+    match delivery {
+        Delivery::Dm => None,
+        Delivery::Channel { channel_id, .. } => Some(rest::create_message(client, *channel_id, body).await),
+        Delivery::Webhook { url, .. } => {
+            // Buttons on webhook messages can't reach the bot, only the jump link is kept
+            let mut body = body.clone();
+            if let Some(rows) = body["components"].as_array_mut() {
+                rows.truncate(1);
+            }
+            if let Some(components) = body["components"][0]["components"].as_array_mut() {
+                components.retain(|c| c["url"].is_string());
+            }
+            Some(rest::execute_webhook(url, &body).await)
+        }
+    }
+}
+
+/// Sends the rendered bookmark where its owner asked for it, in their default color, and
+/// records it in the bookmark store.
+pub(crate) async fn deliver(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    bookmark: NewBookmark,
+    embeds: Vec<Embed>,
+    components: Vec<Component>,
+    settings: &Settings,
+) -> Result<InteractionResponseData, InteractionError> {
+    match send_bookmark(ctx, client, bookmark, embeds, components, settings, &mut None).await? {
+        Ok(note) => Ok(bookmarked(note)),
+        Err(data) => Ok(data),
+    }
+}
+
+/// Like `deliver`, for callers saving several bookmarks at once. `Ok` holds a note on where the
+/// bookmark went, `Err` the response explaining why it could not be sent. `dm_channel` keeps the
+/// DM channel once opened, so it is opened once for all of them.
+pub(crate) async fn send_bookmark(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    bookmark: NewBookmark,
+    mut embeds: Vec<Embed>,
+    components: Vec<Component>,
+    settings: &Settings,
+    dm_channel: &mut Option<Id<ChannelMarker>>,
+) -> Result<Result<Option<&'static str>, InteractionResponseData>, InteractionError> {
+    if let Some(color) = settings.default_color {
+        for embed in embeds.iter_mut() {
+            embed.color = Some(color);
+        }
+    }
+    show_read_state(&mut embeds, ReadState::Unread);
+
+    // The quota is checked before sending, a message posted by webhook can't be taken back. A
+    // storage failure should not fail the bookmark, the index checks the quota again when it is
+    // stored
+    let store = match BookmarkStore::new(ctx) {
+        Ok(store) => Some(store),
+        Err(err) => {
+            console_log!("[DELIVER] no bookmark store: {}", err);
+            None
+        }
+    };
+    let reserved = match &store {
+        Some(store) => match store.reserve(bookmark.user_id).await {
+            Ok(reserved) => Some(reserved),
+            Err(InteractionError::IndexFull) => {
+                return Ok(Err(ephemeral(format!(
+                    "You have reached the limit of {} bookmarks, delete some to make room",
+                    MAX_BOOKMARKS
+                ))))
+            }
+            Err(err) => {
+                console_log!("[DELIVER] reserving a place for the bookmark failed: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let body = serde_json::json!({
+        "embeds": embeds,
+        "components": components
+    });
+
+    let (sent, dm_guild_id, note) = match post_to_target(client, &settings.delivery, &body).await {
+        Some(Ok(message)) => {
+            let guild_id = settings.delivery.guild_id().or(message.guild_id);
+            (Ok(message), guild_id, None)
+        }
+        None => (send_dm(client, bookmark.user_id, dm_channel, &body).await, None, None),
+        Some(Err(err)) => {
+            console_log!("[DELIVER] delivery target failed: {}", err);
+            let note = "I couldn't post to your bookmark channel, so it was sent to your DMs. Check `/settings`";
+            (send_dm(client, bookmark.user_id, dm_channel, &body).await, None, Some(note))
+        }
+    };
+    let sent = match sent {
+        Ok(message) => message,
+        Err(data) => {
+            if let (Some(store), Some(reserved)) = (&store, reserved) {
+                if let Err(err) = store.release(bookmark.user_id, reserved).await {
+                    console_log!("[DELIVER] releasing the bookmark's place failed: {}", err);
+                }
+            }
+            return Ok(Err(data));
+        }
+    };
+
+    let stored = StoredBookmark {
+        user_id: bookmark.user_id,
+        guild_id: bookmark.guild_id,
+        channel_id: bookmark.channel_id,
+        messages: bookmark.messages,
+        dm_guild_id,
+        dm_channel_id: sent.channel_id,
+        dm_message_id: sent.id,
+        created_at: Date::now().as_millis(),
+        status: SourceStatus::Unchanged,
+        note: None,
+        tags: Vec::new(),
+        remind_at: None,
+        read_state: ReadState::Unread,
+    };
+    // The bookmark has been sent at this point, so a storage failure should not fail it
+    if let Some(store) = &store {
+        if let Some(reserved) = reserved {
+            if let Err(err) = store.claim(stored.user_id, reserved, stored.dm_message_id).await {
+                console_log!("[DELIVER] claiming the bookmark's place failed: {}", err);
+            }
+        }
+        if let Err(err) = store.put(&stored).await {
+            console_log!("[DELIVER] storing bookmark failed: {}", err);
+        }
+    }
+    log_bookmark(ctx, client, &stored).await;
+    record_bookmark(ctx, client, &stored).await;
+    emit_bookmark(ctx, EventKind::BookmarkCreated, &stored).await;
+
+    Ok(Ok(note))
+}
+
+// DMs the bookmark, `Err` holds the response explaining why it could not be sent
+async fn send_dm(
+    client: &Client,
+    user_id: Id<UserMarker>,
+    dm_channel: &mut Option<Id<ChannelMarker>>,
+    body: &serde_json::Value,
+) -> Result<Message, InteractionResponseData> {
+    let dm_channel_id = match *dm_channel {
+        Some(id) => id,
+        None => match rest::open_dm(client, user_id).await {
+            Ok(id) => *dm_channel.insert(id),
+            Err(RestError::Forbidden) => {
+                return Err(ephemeral(
+                    "The bot is not authorized to create a dm channel with you",
+                ))
+            }
+            Err(err) => {
+                return Err(ephemeral(format!(
+                    "An error occured while creating a dm channel with you ({})",
+                    err
+                )))
+            }
+        },
+    };
+
+    match rest::create_message(client, dm_channel_id, body).await {
+        Ok(message) => Ok(message),
+        Err(RestError::Forbidden) => Err(ephemeral(
+            "Open your dms in this server to use this command",
+        )),
+        Err(err) => {
+            console_log!("[DELIVER] {}", err);
+            Err(ephemeral(
+                "An error occured while sending a message in this channel",
+            ))
+        }
+    }
+}
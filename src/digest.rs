@@ -0,0 +1,280 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use twilight_model::channel::message::component::{ActionRow, Button, ButtonStyle};
+use twilight_model::channel::message::{Component, ReactionType};
+use twilight_model::id::{
+    marker::{MessageMarker, UserMarker},
+    Id,
+};
+use twilight_util::builder::embed::{EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder};
+use worker::{console_log, Env};
+
+use crate::error::InteractionError;
+use crate::rest;
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, ReadState};
+
+const DAY: u64 = 24 * 60 * 60 * 1000;
+
+// Only bookmarks left unread for this long are listed
+const MIN_AGE: u64 = 3 * DAY;
+
+// A digest is not sent again sooner than this, in case a run is repeated
+const RESEND_AFTER: u64 = 6 * DAY;
+
+/// How long "remind me next week" hides bookmarks from the digest. A little under a week, so they
+/// are back in time for the next one.
+pub(crate) const SNOOZE: u64 = 6 * DAY;
+
+// Snoozed bookmarks come back at this hour of the user's reminder timezone
+const SNOOZE_HOUR: i64 = 9;
+
+// Opted-in users listed at a time, a run works through them over as many invocations as it takes
+const BATCH: u64 = 20;
+
+// KV operations one cron invocation spends on digests, below the limit of 1000 per invocation so
+// the purge and webhook retries that run alongside have room
+const KV_BUDGET: usize = 600;
+
+// Bookmarks read per user, oldest first. Each is a KV read
+const MAX_SCANNED: usize = 200;
+
+// The most KV operations one user's digest takes: their settings and index, every bookmark read,
+// and storing the digest and their settings
+const MAX_USER_OPS: usize = MAX_SCANNED + 4;
+
+// Discord caps embed fields at 25
+const MAX_ENTRIES: usize = 25;
+
+// How long the buttons of a digest keep working, in seconds
+const DIGEST_TTL: u64 = 30 * 24 * 60 * 60;
+
+const RUN_KEY: &str = "digest-run";
+const OPT_IN_PREFIX: &str = "digest-user:";
+
+/// A weekly run in progress, continued by every cron invocation until all users are done.
+#[derive(Deserialize, Serialize)]
+struct DigestRun {
+    cursor: Option<String>,
+    /// Users of the last listed page the budget did not reach, handled before the next page
+    #[serde(default)]
+    pending: Vec<Id<UserMarker>>,
+    /// Whether the last page has been listed, the run ends once `pending` is empty
+    #[serde(default)]
+    listed: bool,
+}
+
+/// When "remind me next week" brings bookmarks back: the morning `SNOOZE` from `now`, in the
+/// timezone `offset_minutes` east of UTC. Never more than a day later than `now + SNOOZE`.
+pub(crate) fn snooze_until(now: u64, offset_minutes: i32) -> u64 {
+    let offset = offset_minutes as i64 * 60 * 1000;
+    let local = (now + SNOOZE) as i64 + offset;
+    let morning = local - local.rem_euclid(DAY as i64) + SNOOZE_HOUR * 60 * 60 * 1000;
+    (morning - offset) as u64
+}
+
+/// The bookmarks listed in a sent digest, which its buttons act on.
+pub(crate) fn digest_key(user_id: Id<UserMarker>, message_id: Id<MessageMarker>) -> String {
+    format!("digest:{}:{}", user_id, message_id)
+}
+
+/// Adds or removes `user_id` from the users a run goes through.
+pub(crate) async fn set_opt_in(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+    enabled: bool,
+) -> Result<(), InteractionError> {
+    let key = format!("{}{}", OPT_IN_PREFIX, user_id);
+    if enabled {
+        store.put_value(&key, &true, None).await
+    } else {
+        store.delete_value(&key).await
+    }
+}
+
+/// Starts this week's run, called from the weekly cron trigger.
+pub(crate) async fn start(store: &BookmarkStore) -> Result<(), InteractionError> {
+    store
+        .put_value(
+            RUN_KEY,
+            &DigestRun {
+                cursor: None,
+                pending: Vec::new(),
+                listed: false,
+            },
+            None,
+        )
+        .await
+}
+
+fn button(custom_id: &str, emoji: &str, label: &str) -> Component {
+    Component::Button(Button {
+        custom_id: Some(custom_id.to_string()),
+        disabled: false,
+        emoji: Some(ReactionType::Unicode {
+            name: emoji.to_string(),
+        }),
+        label: Some(label.to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    })
+}
+
+// What the digest says about the `total` unread bookmarks found, `all` when every bookmark was read
+fn waiting(total: usize, all: bool) -> String {
+    format!(
+        "{}{} bookmarks have been waiting for you for more than {} days",
+        if all { "" } else { "At least " },
+        total,
+        MIN_AGE / DAY
+    )
+}
+
+// Sends the digest of one user, `false` when there was nothing to send. Adds the KV operations it
+// took to `ops`, at most `MAX_USER_OPS`
+async fn send_digest(
+    store: &BookmarkStore,
+    client: &Client,
+    user_id: Id<UserMarker>,
+    now: u64,
+    ops: &mut usize,
+) -> Result<bool, InteractionError> {
+    *ops += 1;
+    let mut settings = Settings::load(store, user_id).await?;
+    if !settings.digest || settings.last_digest.is_some_and(|last| last + RESEND_AFTER > now) {
+        return Ok(false);
+    }
+
+    *ops += 1;
+    let ids = store.list(user_id).await?;
+    let mut unread = Vec::new();
+    for id in ids.iter().take(MAX_SCANNED) {
+        *ops += 1;
+        let Some(bookmark) = store.get(user_id, *id).await? else {
+            continue;
+        };
+        let snoozed = bookmark.remind_at.is_some_and(|at| at > now);
+        if bookmark.read_state == ReadState::Unread && bookmark.created_at + MIN_AGE < now && !snoozed {
+            unread.push(bookmark);
+        }
+    }
+    if unread.is_empty() {
+        return Ok(false);
+    }
+    let description = waiting(unread.len(), ids.len() <= MAX_SCANNED);
+    unread.truncate(MAX_ENTRIES);
+
+    let mut embed = EmbedBuilder::new()
+        .title("Your weekly bookmark digest")
+        .description(description)
+        .color(3092790)
+        .footer(EmbedFooterBuilder::new("Turn the digest off in /settings"));
+    for bookmark in &unread {
+        embed = embed.field(EmbedFieldBuilder::new(
+            bookmark.summary(),
+            format!(
+                "Saved <t:{}:R> · [original]({})",
+                bookmark.created_at / 1000,
+                bookmark.jump_url()
+            ),
+        ));
+    }
+    let body = serde_json::json!({
+        "embeds": [embed.build()],
+        "components": [Component::ActionRow(ActionRow {
+            components: vec![
+                button("digest:archive", "📦", "Archive all"),
+                button("digest:snooze", "⏰", "Remind me next week"),
+            ],
+        })],
+    });
+
+    let channel_id = rest::open_dm(client, user_id).await?;
+    let message = rest::create_message(client, channel_id, &body).await?;
+
+    *ops += 2;
+    let ids: Vec<Id<MessageMarker>> = unread.iter().map(|b| b.dm_message_id).collect();
+    store
+        .put_value(&digest_key(user_id, message.id), &ids, Some(DIGEST_TTL))
+        .await?;
+    settings.last_digest = Some(now);
+    settings.save(store, user_id).await?;
+    Ok(true)
+}
+
+/// Sends the digests of as many opted-in users as `KV_BUDGET` allows if a run is in progress,
+/// returning how many were sent.
+pub(crate) async fn run_batch(store: &BookmarkStore, env: &Env, now: u64) -> Result<usize, InteractionError> {
+    let Some(mut run) = store.get_value::<DigestRun>(RUN_KEY).await? else {
+        return Ok(0);
+    };
+    let client = rest::bot_client(env)?;
+
+    // Reading and writing the run itself
+    let mut ops = 2;
+    let mut sent = 0;
+    while ops + MAX_USER_OPS <= KV_BUDGET {
+        if run.pending.is_empty() {
+            if run.listed {
+                break;
+            }
+            ops += 1;
+            let (keys, cursor) = store.key_page(OPT_IN_PREFIX, run.cursor.take(), BATCH).await?;
+            run.pending = keys
+                .iter()
+                .filter_map(|key| key.trim_start_matches(OPT_IN_PREFIX).parse().ok())
+                .filter_map(Id::<UserMarker>::new_checked)
+                .collect();
+            run.listed = cursor.is_none();
+            run.cursor = cursor;
+            continue;
+        }
+
+        let user_id = run.pending.remove(0);
+        // One user's closed DMs must not stop everyone else's digest
+        match send_digest(store, &client, user_id, now, &mut ops).await {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            Err(err) => console_log!("[DIGEST] {} failed: {}", user_id, err),
+        }
+    }
+
+    if run.listed && run.pending.is_empty() {
+        store.delete_value(RUN_KEY).await?;
+    } else {
+        store.put_value(RUN_KEY, &run, None).await?;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Monday 2023-01-02 09:05 UTC, a few minutes after the digest went out
+    const MONDAY: u64 = 1_672_650_300_000;
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    #[test]
+    fn the_count_covers_every_unread_bookmark() {
+        assert_eq!(waiting(40, true), "40 bookmarks have been waiting for you for more than 3 days");
+        assert_eq!(waiting(40, false), "At least 40 bookmarks have been waiting for you for more than 3 days");
+    }
+
+    #[test]
+    fn snoozes_end_in_the_morning_before_the_next_digest() {
+        // Sunday 09:00 UTC
+        assert_eq!(snooze_until(MONDAY, 0), MONDAY + SNOOZE - 5 * 60 * 1000);
+        // Sunday 09:00 at UTC+02:00 is 07:00 UTC
+        assert_eq!(snooze_until(MONDAY, 120), MONDAY + SNOOZE - 2 * HOUR - 5 * 60 * 1000);
+
+        let next_digest = MONDAY + 7 * DAY - 5 * 60 * 1000;
+        for offset in [-12 * 60, -5 * 60, 0, 5 * 60 + 30, 14 * 60] {
+            let until = snooze_until(MONDAY, offset);
+            assert!(until < next_digest, "{}", offset);
+            assert!(until + DAY > MONDAY + SNOOZE, "{}", offset);
+            let local_hour = ((until as i64 + offset as i64 * 60 * 1000) / HOUR as i64).rem_euclid(24);
+            assert_eq!(local_hour, SNOOZE_HOUR, "{}", offset);
+        }
+    }
+}
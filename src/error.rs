@@ -50,10 +50,25 @@ pub(crate) enum InteractionError {
 
     #[error("HTTP error")]
     HttpError(#[from] reqwest::Error),
+
+    #[error("Bookmark limit reached")]
+    IndexFull,
 }
 
 impl From<worker::Error> for InteractionError {
     fn from(error: worker::Error) -> InteractionError {
         InteractionError::WorkerError(format!("{}", error))
     }
-}
\ No newline at end of file
+}
+
+impl From<worker::kv::KvError> for InteractionError {
+    fn from(error: worker::kv::KvError) -> InteractionError {
+        InteractionError::WorkerError(format!("KV: {}", error))
+    }
+}
+
+impl From<crate::store::IndexFull> for InteractionError {
+    fn from(_: crate::store::IndexFull) -> InteractionError {
+        InteractionError::IndexFull
+    }
+}
@@ -0,0 +1,301 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+    Id,
+};
+use worker::{console_log, Date, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+use crate::guild_config::GuildConfig;
+use crate::media::sign;
+use crate::settings::Settings;
+use crate::store::{BookmarkStore, StoredBookmark};
+use crate::utils::random_token;
+
+pub(crate) const SIGNATURE_HEADER: &str = "X-Bookmarker-Signature";
+pub(crate) const TIMESTAMP_HEADER: &str = "X-Bookmarker-Timestamp";
+
+// Seconds to wait before each retry, a delivery is dropped once they run out. Retries are sent by
+// the `*/5` cron trigger, so none is shorter than its period
+const BACKOFF: [u64; 5] = [5 * 60, 15 * 60, 60 * 60, 4 * 60 * 60, 12 * 60 * 60];
+
+const RETRY_PREFIX: &str = "event-retry:";
+
+// Retries sent per cron invocation, so a dead endpoint's backlog can't use up the subrequests the
+// digest and the trash purge need. The rest wait for the next invocation
+const MAX_RETRIES: u64 = 20;
+
+/// An HTTPS endpoint events are posted to. The secret signs every request and is only shown to
+/// whoever set the endpoint up.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Endpoint {
+    pub(crate) url: String,
+    pub(crate) secret: String,
+}
+
+impl Endpoint {
+    pub(crate) fn new(url: String) -> Endpoint {
+        Endpoint {
+            url,
+            secret: random_token(24),
+        }
+    }
+}
+
+/// Endpoints must use HTTPS, plain HTTP is allowed for a sink on the local machine.
+pub(crate) fn parse_endpoint_url(input: &str) -> Option<String> {
+    let url = reqwest::Url::parse(input.trim()).ok()?;
+    let local = matches!(url.host_str(), Some("localhost") | Some("127.0.0.1"));
+    match url.scheme() {
+        "https" => Some(url.to_string()),
+        "http" if local => Some(url.to_string()),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    #[serde(rename = "bookmark.created")]
+    BookmarkCreated,
+    /// Sent when the note or tags are edited from the dashboard
+    #[serde(rename = "bookmark.note_changed")]
+    BookmarkNoteChanged,
+    #[serde(rename = "bookmark.deleted")]
+    BookmarkDeleted,
+    /// Sent when an endpoint is set up or tested
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct BookmarkData {
+    /// The id of the bookmark message, stable for the life of the bookmark
+    pub(crate) bookmark_id: Id<MessageMarker>,
+    pub(crate) user_id: Id<UserMarker>,
+    pub(crate) guild_id: Option<Id<GuildMarker>>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    pub(crate) message_ids: Vec<Id<MessageMarker>>,
+    pub(crate) jump_url: String,
+    pub(crate) created_at: u64,
+    #[serde(default)]
+    pub(crate) note: Option<String>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+}
+
+/// The body of every webhook request, see "Outbound webhooks" in the README.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Event {
+    pub(crate) id: String,
+    #[serde(rename = "type")]
+    pub(crate) kind: EventKind,
+    /// Unix time in ms
+    pub(crate) created_at: u64,
+    pub(crate) bookmark: Option<BookmarkData>,
+}
+
+impl Event {
+    pub(crate) fn new(kind: EventKind, bookmark: Option<&StoredBookmark>) -> Event {
+        Event {
+            id: random_token(16),
+            kind,
+            created_at: Date::now().as_millis(),
+            bookmark: bookmark.map(|b| BookmarkData {
+                bookmark_id: b.dm_message_id,
+                user_id: b.user_id,
+                guild_id: b.guild_id,
+                channel_id: b.channel_id,
+                message_ids: b.messages.iter().map(|m| m.id).collect(),
+                jump_url: b.jump_url(),
+                created_at: b.created_at,
+                note: b.note.clone(),
+                tags: b.tags.clone(),
+            }),
+        }
+    }
+}
+
+/// A delivery waiting for its next attempt.
+#[derive(Deserialize, Serialize)]
+struct PendingDelivery {
+    endpoint: Endpoint,
+    body: String,
+    attempt: usize,
+}
+
+fn retry_key(due_secs: u64, event_id: &str) -> String {
+    // Zero padded so keys list in due order, the random suffix tells endpoints of one event apart
+    format!("{}{:012}:{}:{}", RETRY_PREFIX, due_secs, event_id, random_token(4))
+}
+
+/// Posts a signed event body at `now_secs`. The signature is the hex HMAC-SHA256 of
+/// `<timestamp>.<body>`.
+pub(crate) async fn send(endpoint: &Endpoint, body: &str, now_secs: u64) -> Result<(), String> {
+    let timestamp = now_secs.to_string();
+    let signature = sign(&endpoint.secret, &format!("{}.{}", timestamp, body));
+
+    // A bare client, endpoints must never see the bot token
+    let response = Client::new()
+        .post(&endpoint.url)
+        .header("Content-Type", "application/json")
+        .header(TIMESTAMP_HEADER, &timestamp)
+        .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+        .body(body.to_string())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("{} responded with {}", endpoint.url, response.status()))
+    }
+}
+
+async fn schedule_retry(store: &BookmarkStore, event_id: &str, pending: &PendingDelivery) {
+    let Some(delay) = BACKOFF.get(pending.attempt - 1) else {
+        console_log!("[EVENTS] giving up on {} for {}", event_id, pending.endpoint.url);
+        return;
+    };
+    let due = Date::now().as_millis() / 1000 + delay;
+    let key = retry_key(due, event_id);
+    if let Err(err) = store.put_value(&key, pending, None).await {
+        console_log!("[EVENTS] scheduling a retry failed: {}", err);
+    }
+}
+
+/// The endpoints set up by the owner of a bookmark and by its server.
+pub(crate) async fn endpoints_for(
+    ctx: &RouteContext<RouteData>,
+    user_id: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+) -> Vec<Endpoint> {
+    let mut endpoints = Vec::new();
+    endpoints.extend(Settings::for_user(ctx, user_id).await.endpoint);
+    if let Some(guild_id) = guild_id {
+        endpoints.extend(GuildConfig::for_guild(ctx, guild_id).await.endpoint);
+    }
+    endpoints
+}
+
+/// Sends `event` to each endpoint after the response has gone out, so a slow endpoint never holds
+/// up an interaction. Failed deliveries are retried by the scheduled handler.
+pub(crate) fn emit(ctx: &RouteContext<RouteData>, event: &Event, endpoints: Vec<Endpoint>) {
+    if endpoints.is_empty() {
+        return;
+    }
+    let body = match serde_json::to_string(event) {
+        Ok(body) => body,
+        Err(err) => {
+            console_log!("[EVENTS] serializing {} failed: {}", event.id, err);
+            return;
+        }
+    };
+    let store = BookmarkStore::new(ctx).ok();
+    let event_id = event.id.clone();
+    ctx.data.wait_until(async move {
+        let now_secs = Date::now().as_millis() / 1000;
+        for endpoint in endpoints {
+            let Err(err) = send(&endpoint, &body, now_secs).await else {
+                continue;
+            };
+            console_log!("[EVENTS] {}", err);
+            if let Some(store) = &store {
+                let pending = PendingDelivery {
+                    endpoint,
+                    body: body.clone(),
+                    attempt: 1,
+                };
+                schedule_retry(store, &event_id, &pending).await;
+            }
+        }
+    });
+}
+
+/// Emits a bookmark event to the endpoints of its owner and server.
+pub(crate) async fn emit_bookmark(ctx: &RouteContext<RouteData>, kind: EventKind, bookmark: &StoredBookmark) {
+    let endpoints = endpoints_for(ctx, bookmark.user_id, bookmark.guild_id).await;
+    emit(ctx, &Event::new(kind, Some(bookmark)), endpoints);
+}
+
+/// Retries up to `MAX_RETRIES` of the deliveries that are due, called from the scheduled handler.
+pub(crate) async fn retry_due(store: &BookmarkStore, now_secs: u64) -> Result<usize, InteractionError> {
+    let mut retried = 0;
+    // The oldest due first, retried ones are moved further back
+    let (keys, _) = store.key_page(RETRY_PREFIX, None, MAX_RETRIES).await?;
+    for key in keys {
+        let mut parts = key.trim_start_matches(RETRY_PREFIX).splitn(3, ':');
+        let due: u64 = parts.next().and_then(|d| d.parse().ok()).unwrap_or(0);
+        if due > now_secs {
+            // Keys are ordered by due time, the rest are not due either
+            break;
+        }
+        let event_id = parts.next().unwrap_or_default().to_string();
+
+        let pending = store.get_value::<PendingDelivery>(&key).await?;
+        store.delete_value(&key).await?;
+        let Some(mut pending) = pending else {
+            continue;
+        };
+
+        retried += 1;
+        if let Err(err) = send(&pending.endpoint, &pending.body, now_secs).await {
+            console_log!("[EVENTS] retry {} failed: {}", pending.attempt, err);
+            pending.attempt += 1;
+            schedule_retry(store, &event_id, &pending).await;
+        }
+    }
+    Ok(retried)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, serve};
+
+    fn endpoint(url: String) -> Endpoint {
+        Endpoint {
+            url,
+            secret: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn events_are_signed() {
+        let (base, received) = serve(vec![(204, "text/plain", Vec::new())]);
+        let body = r#"{"type":"ping"}"#;
+        block_on(send(&endpoint(format!("{}/sink", base)), body, 1700000000)).unwrap();
+
+        let request = received.recv().unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/sink"));
+        assert_eq!(request.body, body);
+        assert_eq!(request.header(TIMESTAMP_HEADER), Some("1700000000"));
+        let signature = format!("sha256={}", sign("secret", &format!("1700000000.{}", body)));
+        assert_eq!(request.header(SIGNATURE_HEADER), Some(signature.as_str()));
+        assert_eq!(request.header("Authorization"), None);
+    }
+
+    #[test]
+    fn errors_are_reported_for_retrying() {
+        let (base, _received) = serve(vec![(500, "text/plain", b"down".to_vec())]);
+        let err = block_on(send(&endpoint(base.clone()), "{}", 0)).unwrap_err();
+        assert!(err.contains("500"), "{}", err);
+    }
+
+    #[test]
+    fn retries_list_in_due_order() {
+        assert!(BACKOFF.iter().all(|delay| *delay >= 5 * 60));
+        assert!(retry_key(999, "b") < retry_key(1000, "a"));
+        assert!(retry_key(1000, "a").starts_with("event-retry:000000001000:a:"));
+    }
+
+    #[test]
+    fn only_https_or_local_endpoints() {
+        assert!(parse_endpoint_url("https://example.com/hook").is_some());
+        assert!(parse_endpoint_url("http://localhost:8787/sink").is_some());
+        assert!(parse_endpoint_url("http://example.com/hook").is_none());
+        assert!(parse_endpoint_url("ftp://example.com").is_none());
+    }
+}
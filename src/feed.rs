@@ -0,0 +1,400 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use twilight_model::id::{marker::UserMarker, Id};
+use twilight_model::util::Timestamp;
+use worker::{Date, Headers, Request, Response, RouteContext};
+
+use crate::RouteData;
+use crate::api::newest;
+use crate::error::InteractionError;
+use crate::settings::{format_utc_offset, Settings};
+use crate::store::{BookmarkStore, StoredBookmark};
+use crate::utils::random_token;
+
+// Entries in the Atom feed, feed readers only look at the newest ones
+const FEED_ENTRIES: usize = 50;
+
+// Reminders stay in the calendar for a day after they are due
+const PAST_REMINDERS: u64 = 24 * 60 * 60 * 1000;
+
+/// Who a feed token belongs to, keyed by the hash of the token so KV never holds it in plain.
+#[derive(Deserialize, Serialize)]
+struct FeedToken {
+    user_id: Id<UserMarker>,
+    created_at: u64,
+}
+
+fn token_key(hash: &str) -> String {
+    format!("feed-token:{}", hash)
+}
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Creates a new token for `user_id`, revoking the previous one.
+pub(crate) async fn issue(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+    now: u64,
+) -> Result<String, InteractionError> {
+    revoke(store, user_id).await?;
+
+    let token = random_token(24);
+    let hashed = hash(&token);
+    store
+        .put_value(&token_key(&hashed), &FeedToken { user_id, created_at: now }, None)
+        .await?;
+
+    let mut settings = Settings::load(store, user_id).await?;
+    settings.feed_token = Some(hashed);
+    settings.save(store, user_id).await?;
+    Ok(token)
+}
+
+/// Revokes the token of `user_id`, `false` when there was none.
+pub(crate) async fn revoke(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+) -> Result<bool, InteractionError> {
+    let mut settings = Settings::load(store, user_id).await?;
+    let Some(hashed) = settings.feed_token.take() else {
+        return Ok(false);
+    };
+    store.delete_value(&token_key(&hashed)).await?;
+    settings.save(store, user_id).await?;
+    Ok(true)
+}
+
+/// The user a token was issued to.
+pub(crate) async fn authenticate(
+    store: &BookmarkStore,
+    token: &str,
+) -> Result<Option<Id<UserMarker>>, InteractionError> {
+    if token.is_empty() {
+        return Ok(None);
+    }
+    let record: Option<FeedToken> = store.get_value(&token_key(&hash(token))).await?;
+    Ok(record.map(|r| r.user_id))
+}
+
+/// The url of a feed, `route` is `feed` or `calendar`. Needs a public url to be configured.
+pub(crate) fn feed_url(
+    ctx: &RouteContext<RouteData>,
+    route: &str,
+    user_id: Id<UserMarker>,
+    file: &str,
+) -> Option<String> {
+    let base_url = ctx.var("PUBLIC_URL").ok()?.to_string();
+    Some(format!(
+        "{}/{}/{}/{}",
+        base_url.trim_end_matches('/'),
+        route,
+        user_id,
+        file
+    ))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rfc3339(millis: u64) -> String {
+    Timestamp::from_secs((millis / 1000) as i64)
+        .map(|t| t.iso_8601().to_string())
+        .unwrap_or_default()
+}
+
+fn atom(user_id: Id<UserMarker>, bookmarks: &[StoredBookmark]) -> String {
+    let updated = bookmarks.first().map_or(0, |b| b.created_at);
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>urn:bookmarker:user:{}</id>\n\
+         <title>Bookmarks</title>\n\
+         <updated>{}</updated>\n",
+        user_id,
+        rfc3339(updated)
+    );
+    for bookmark in bookmarks {
+        let content = bookmark
+            .messages
+            .iter()
+            .map(|m| format!("{}: {}", m.author.name, m.content))
+            .collect::<Vec<String>>()
+            .join("\n");
+        feed.push_str(&format!(
+            "<entry>\n\
+             <id>urn:bookmarker:bookmark:{}</id>\n\
+             <title>{}</title>\n\
+             <link href=\"{}\"/>\n\
+             <updated>{}</updated>\n\
+             <author><name>{}</name></author>\n\
+             <content type=\"text\">{}</content>\n\
+             </entry>\n",
+            bookmark.dm_message_id,
+            escape(&bookmark.summary()),
+            escape(&bookmark.jump_url()),
+            rfc3339(bookmark.created_at),
+            escape(&bookmark.messages[0].author.name),
+            escape(&content)
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+// `20230101T000000Z`
+fn ics_time(millis: u64) -> String {
+    ics_local_time(millis, 0) + "Z"
+}
+
+// `20230101T020000` for the same time at `offset_minutes` east of UTC, without a zone suffix
+fn ics_local_time(millis: u64, offset_minutes: i32) -> String {
+    let shifted = (millis as i64 + offset_minutes as i64 * 60 * 1000).max(0) as u64;
+    rfc3339(shifted)
+        .chars()
+        .take(19)
+        .filter(|c| *c != '-' && *c != ':')
+        .collect()
+}
+
+// A fixed offset timezone for the user's reminder timezone, so calendars show reminders in it
+fn ics_timezone(tzid: &str, offset_minutes: i32) -> Vec<String> {
+    let sign = if offset_minutes < 0 { '-' } else { '+' };
+    let offset = format!(
+        "{}{:02}{:02}",
+        sign,
+        offset_minutes.abs() / 60,
+        offset_minutes.abs() % 60
+    );
+    vec![
+        "BEGIN:VTIMEZONE".to_string(),
+        format!("TZID:{}", tzid),
+        "BEGIN:STANDARD".to_string(),
+        "DTSTART:19700101T000000".to_string(),
+        format!("TZOFFSETFROM:{}", offset),
+        format!("TZOFFSETTO:{}", offset),
+        format!("TZNAME:{}", tzid),
+        "END:STANDARD".to_string(),
+        "END:VTIMEZONE".to_string(),
+    ]
+}
+
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 bytes are continued on the next line after a space
+fn ics_fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+/// The calendar of `bookmarks`, with reminders in the timezone `offset_minutes` east of UTC.
+fn ics(bookmarks: &[StoredBookmark], now: u64, offset_minutes: i32) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Bookmarker//Reminders//EN".to_string(),
+        "X-WR-CALNAME:Bookmark reminders".to_string(),
+    ];
+    let tzid = format_utc_offset(offset_minutes);
+    if offset_minutes != 0 {
+        lines.extend(ics_timezone(&tzid, offset_minutes));
+    }
+    for bookmark in bookmarks {
+        let Some(remind_at) = bookmark.remind_at else {
+            continue;
+        };
+        let mut description = String::new();
+        if let Some(note) = &bookmark.note {
+            description.push_str(note);
+            description.push_str("\n\n");
+        }
+        description.push_str(&bookmark.jump_url());
+
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@bookmarker", bookmark.dm_message_id),
+            format!("DTSTAMP:{}", ics_time(now)),
+            if offset_minutes == 0 {
+                format!("DTSTART:{}", ics_time(remind_at))
+            } else {
+                format!("DTSTART;TZID=\"{}\":{}", tzid, ics_local_time(remind_at, offset_minutes))
+            },
+            "DURATION:PT15M".to_string(),
+            format!("SUMMARY:{}", ics_escape(&bookmark.summary())),
+            format!("DESCRIPTION:{}", ics_escape(&description)),
+            format!("URL:{}", bookmark.jump_url()),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| ics_fold(line) + "\r\n")
+        .collect()
+}
+
+// The owner of a `/<route>/:user/:file` url, if the file is `<token>.<extension>` for their token
+async fn feed_owner(
+    ctx: &RouteContext<RouteData>,
+    store: &BookmarkStore,
+    extension: &str,
+) -> Result<Option<Id<UserMarker>>, InteractionError> {
+    let (Some(user), Some(file)) = (ctx.param("user"), ctx.param("file")) else {
+        return Ok(None);
+    };
+    let (Some(user_id), Some(token)) = (
+        user.parse().ok().and_then(Id::<UserMarker>::new_checked),
+        file.strip_suffix(extension),
+    ) else {
+        return Ok(None);
+    };
+    Ok(authenticate(store, token)
+        .await?
+        .filter(|owner| *owner == user_id))
+}
+
+/// `GET /feed/:user/:file`, where the file is `<token>.atom`.
+pub(crate) async fn atom_feed(_req: Request, ctx: RouteContext<RouteData>) -> worker::Result<Response> {
+    let store = match BookmarkStore::new(&ctx) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    let user_id = match feed_owner(&ctx, &store, ".atom").await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Response::error("Not Found", 404),
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+
+    let bookmarks = match newest(&store, user_id, None, FEED_ENTRIES, None).await {
+        Ok((bookmarks, _)) => bookmarks,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "application/atom+xml; charset=utf-8")?;
+    headers.set("Cache-Control", "private, max-age=300")?;
+    Ok(Response::ok(atom(user_id, &bookmarks))?.with_headers(headers))
+}
+
+// Whether a reminder belongs in the calendar at `now`, the others are dropped from the index
+fn shown(remind_at: Option<u64>, now: u64) -> bool {
+    remind_at.is_some_and(|at| at + PAST_REMINDERS >= now)
+}
+
+// The bookmarks in the user's reminder index, reading only those rather than every bookmark
+async fn reminders(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+    now: u64,
+) -> Result<Vec<StoredBookmark>, InteractionError> {
+    let mut reminders = Vec::new();
+    for id in store.list_reminders(user_id).await? {
+        match store.get(user_id, id).await? {
+            Some(bookmark) if shown(bookmark.remind_at, now) => reminders.push(bookmark),
+            _ => store.forget_reminder(user_id, id).await?,
+        }
+    }
+    Ok(reminders)
+}
+
+/// `GET /calendar/:user/:file`, where the file is `<token>.ics`. Lists reminders that are
+/// upcoming or were due in the last day.
+pub(crate) async fn calendar(_req: Request, ctx: RouteContext<RouteData>) -> worker::Result<Response> {
+    let store = match BookmarkStore::new(&ctx) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    let user_id = match feed_owner(&ctx, &store, ".ics").await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Response::error("Not Found", 404),
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+
+    let now = Date::now().as_millis();
+    let mut reminders = match reminders(&store, user_id, now).await {
+        Ok(reminders) => reminders,
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    reminders.sort_by_key(|b| b.remind_at);
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", "text/calendar; charset=utf-8")?;
+    headers.set("Cache-Control", "private, max-age=300")?;
+    let offset = match Settings::load(&store, user_id).await {
+        Ok(settings) => settings.utc_offset_minutes(),
+        Err(e) => return Response::error(e.to_string(), 500),
+    };
+    Ok(Response::ok(ics(&reminders, now, offset))?.with_headers(headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2023-01-02 09:05:00 UTC
+    const TIME: u64 = 1_672_650_300_000;
+
+    #[test]
+    fn reminders_are_shifted_into_the_users_timezone() {
+        assert_eq!(ics_time(TIME), "20230102T090500Z");
+        assert_eq!(ics_local_time(TIME, 120), "20230102T110500");
+        assert_eq!(ics_local_time(TIME, -10 * 60), "20230101T230500");
+
+        let timezone = ics_timezone("UTC-05:30", -330);
+        assert!(timezone.contains(&"TZID:UTC-05:30".to_string()));
+        assert!(timezone.contains(&"TZOFFSETTO:-0530".to_string()));
+    }
+
+    #[test]
+    fn long_lines_are_folded_at_75_bytes() {
+        assert_eq!(ics_fold("SUMMARY:short"), "SUMMARY:short");
+
+        let line = format!("DESCRIPTION:{}", "a".repeat(150));
+        let folded = ics_fold(&line);
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|part| part.len() <= 75));
+        assert!(parts[1..].iter().all(|part| part.starts_with(' ')));
+        // Unfolding gives the line back
+        assert_eq!(folded.replace("\r\n ", ""), line);
+
+        // Multi-byte characters are never split
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let folded = ics_fold(&line);
+        assert!(folded.split("\r\n").all(|part| part.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(ics_escape("a; b, c\\d\nnext"), "a\\; b\\, c\\\\d\\nnext");
+    }
+
+    #[test]
+    fn past_and_cleared_reminders_are_left_out() {
+        assert!(shown(Some(TIME + 1), TIME));
+        assert!(shown(Some(TIME - PAST_REMINDERS), TIME));
+        assert!(!shown(Some(TIME - PAST_REMINDERS - 1), TIME));
+        assert!(!shown(None, TIME));
+    }
+}
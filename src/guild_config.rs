@@ -0,0 +1,152 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use twilight_model::channel::Message;
+use twilight_model::guild::{PartialMember, Permissions};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, RoleMarker},
+    Id,
+};
+use worker::{console_log, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+use crate::events::Endpoint;
+use crate::rest::{self, RestError};
+use crate::store::BookmarkStore;
+
+fn default_threshold() -> u32 {
+    5
+}
+
+/// Per-server rules set with `/bookmark-admin`, stored as one KV record.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct GuildConfig {
+    /// Channels whose messages can't be bookmarked
+    #[serde(default)]
+    pub(crate) disabled_channels: Vec<Id<ChannelMarker>>,
+    /// Messages from members with any of these roles can't be bookmarked
+    #[serde(default)]
+    pub(crate) blocked_roles: Vec<Id<RoleMarker>>,
+    /// Bookmarks from this server never include attachments
+    #[serde(default)]
+    pub(crate) strip_attachments: bool,
+    #[serde(default)]
+    pub(crate) log_channel: Option<Id<ChannelMarker>>,
+    /// Messages bookmarked `starboard_threshold` times are announced here
+    #[serde(default)]
+    pub(crate) starboard_channel: Option<Id<ChannelMarker>>,
+    #[serde(default = "default_threshold")]
+    pub(crate) starboard_threshold: u32,
+    /// Receives signed events about bookmarks of this server's messages
+    #[serde(default)]
+    pub(crate) endpoint: Option<Endpoint>,
+}
+
+impl Default for GuildConfig {
+    fn default() -> Self {
+        GuildConfig {
+            disabled_channels: Vec::new(),
+            blocked_roles: Vec::new(),
+            strip_attachments: false,
+            log_channel: None,
+            starboard_channel: None,
+            starboard_threshold: default_threshold(),
+            endpoint: None,
+        }
+    }
+}
+
+impl GuildConfig {
+    fn key(guild_id: Id<GuildMarker>) -> String {
+        format!("guild:{}", guild_id)
+    }
+
+    pub(crate) async fn load(
+        store: &BookmarkStore,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<GuildConfig, InteractionError> {
+        Ok(store
+            .get_value(&Self::key(guild_id))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// The config to enforce, an unreadable config allows everything rather than blocking
+    /// every bookmark in the server.
+    pub(crate) async fn for_guild(ctx: &RouteContext<RouteData>, guild_id: Id<GuildMarker>) -> GuildConfig {
+        let store = match BookmarkStore::new(ctx) {
+            Ok(store) => store,
+            Err(_) => return GuildConfig::default(),
+        };
+        match GuildConfig::load(&store, guild_id).await {
+            Ok(config) => config,
+            Err(err) => {
+                console_log!("[GUILD CONFIG] loading failed: {}", err);
+                GuildConfig::default()
+            }
+        }
+    }
+
+    pub(crate) async fn save(
+        &self,
+        store: &BookmarkStore,
+        guild_id: Id<GuildMarker>,
+    ) -> Result<(), InteractionError> {
+        store.put_value(&Self::key(guild_id), self, None).await
+    }
+
+    pub(crate) fn channel_disabled(&self, channel_id: Id<ChannelMarker>) -> bool {
+        self.disabled_channels.contains(&channel_id)
+    }
+
+    pub(crate) fn role_blocked(&self, roles: &[Id<RoleMarker>]) -> bool {
+        roles.iter().any(|role| self.blocked_roles.contains(role))
+    }
+
+    /// Whether `message` was sent by a member with a blocked role. Roles come from the message
+    /// when Discord included its member, otherwise they are fetched.
+    pub(crate) async fn author_blocked(
+        &self,
+        client: &Client,
+        guild_id: Id<GuildMarker>,
+        message: &Message,
+    ) -> Result<bool, RestError> {
+        if self.blocked_roles.is_empty() {
+            return Ok(false);
+        }
+        if let Some(member) = message.member.as_ref() {
+            return Ok(self.role_blocked(&member.roles));
+        }
+        match rest::get_member_roles(client, guild_id, message.author.id).await {
+            Ok(roles) => Ok(self.role_blocked(&roles)),
+            // Authors who left the server, and webhooks, have no roles
+            Err(RestError::NotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Removes what the server does not allow to be copied into bookmarks.
+    pub(crate) fn strip(&self, message: &mut Message) {
+        if self.strip_attachments {
+            message.attachments.clear();
+        }
+    }
+}
+
+/// Adds `id` to `list` when `present`, removes it otherwise.
+pub(crate) fn set_listed<T: PartialEq>(list: &mut Vec<T>, id: T, present: bool) {
+    list.retain(|listed| *listed != id);
+    if present {
+        list.push(id);
+    }
+}
+
+/// Whether the invoking member may change the server config. Discord resolves the member's
+/// permissions in the interaction payload, including channel overwrites.
+pub(crate) fn can_manage(member: Option<&PartialMember>) -> bool {
+    member
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| {
+            p.contains(Permissions::MANAGE_GUILD) || p.contains(Permissions::ADMINISTRATOR)
+        })
+}
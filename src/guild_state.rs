@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{GuildMarker, MessageMarker},
+    Id,
+};
+use worker::{durable_object, Env, ListOptions, Request, Response, Result, State};
+
+use crate::board::{Board, BoardOp, Tally};
+use crate::limits::{self, Bucket};
+use crate::store::BookmarkStore;
+
+const TALLY_PREFIX: &str = "tally:";
+
+/// What the worker sends a server's Durable Object.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct GuildRequest {
+    pub(crate) guild_id: Id<GuildMarker>,
+    pub(crate) op: GuildOp,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) enum GuildOp {
+    Board(BoardOp),
+    // Takes a token from the server's rate limit bucket, answering how long to wait if it's empty
+    TakeToken { now: u64 },
+}
+
+/// One per server, named by its id: owns the server's board. Requests run one at a time, so
+/// counts add up and a message reaching the starboard threshold is posted once, where a board in
+/// KV is read and written back by every bookmark at the same time.
+#[durable_object]
+pub struct GuildState {
+    state: State,
+    env: Env,
+    // Loaded on the first request, each tally is stored under its own key to stay well below the
+    // size limit of a stored value
+    board: Option<Board>,
+    // Only kept in memory: the object is evicted after sitting idle for longer than the bucket
+    // takes to fill up again, so it would start full anyway
+    rate_limit: Option<Bucket>,
+}
+
+fn tally_key(message_id: Id<MessageMarker>) -> String {
+    format!("{}{}", TALLY_PREFIX, message_id)
+}
+
+impl GuildState {
+    async fn board(&mut self, guild_id: Id<GuildMarker>) -> Result<&mut Board> {
+        if self.board.is_none() {
+            let stored = self
+                .state
+                .storage()
+                .list_with_options(ListOptions::new().prefix(TALLY_PREFIX))
+                .await?;
+            let mut board = Board::default();
+            let mut invalid = None;
+            stored.for_each(&mut |value, key| {
+                let tally = value.as_string().map(|value| serde_json::from_str::<Tally>(&value));
+                let id = key
+                    .as_string()
+                    .and_then(|key| key.strip_prefix(TALLY_PREFIX)?.parse().ok())
+                    .and_then(Id::new_checked);
+                match (id, tally) {
+                    (Some(id), Some(Ok(tally))) => {
+                        board.messages.insert(id, tally);
+                    }
+                    _ => invalid = key.as_string(),
+                }
+            });
+            if let Some(key) = invalid {
+                return Err(worker::Error::RustError(format!("Invalid tally at {}", key)));
+            }
+
+            // First use, start from the board kept in KV until now
+            if board.messages.is_empty() {
+                let store = BookmarkStore::kv_only(&self.env)
+                    .map_err(|err| worker::Error::RustError(err.to_string()))?;
+                board = Board::load(&store, guild_id)
+                    .await
+                    .map_err(|err| worker::Error::RustError(err.to_string()))?;
+                let ids: Vec<_> = board.messages.keys().copied().collect();
+                self.persist(&board, &ids, &[]).await?;
+            }
+            self.board = Some(board);
+        }
+        Ok(self.board.as_mut().expect("loaded above"))
+    }
+
+    async fn persist(
+        &self,
+        board: &Board,
+        updated: &[Id<MessageMarker>],
+        removed: &[Id<MessageMarker>],
+    ) -> Result<()> {
+        let mut storage = self.state.storage();
+        for id in updated {
+            if let Some(tally) = board.messages.get(id) {
+                storage.put(&tally_key(*id), serde_json::to_string(tally)?).await?;
+            }
+        }
+        if !removed.is_empty() {
+            storage
+                .delete_multiple(removed.iter().map(|id| tally_key(*id)).collect())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[durable_object]
+impl DurableObject for GuildState {
+    fn new(state: State, env: Env) -> Self {
+        Self {
+            state,
+            env,
+            board: None,
+            rate_limit: None,
+        }
+    }
+
+    async fn fetch(&mut self, mut req: Request) -> Result<Response> {
+        let request: GuildRequest = req.json().await?;
+        match request.op {
+            GuildOp::Board(op) => {
+                let board = self.board(request.guild_id).await?;
+                let applied = board.apply(op);
+                // Persisted before answering, a failed write drops the cached board so the next
+                // request reads what was actually stored
+                let board = self.board.take().expect("loaded above");
+                self.persist(&board, &applied.updated, &applied.removed).await?;
+                self.board = Some(board);
+                Response::from_json(&applied.entries)
+            }
+            GuildOp::TakeToken { now } => {
+                Response::from_json(&limits::take_guild(&mut self.rate_limit, now))
+            }
+        }
+    }
+}
@@ -1,42 +1,80 @@
-use async_trait::async_trait;
 use twilight_model::channel::{message::{Component, component::{ActionRow, Button, ButtonStyle}, ReactionType}};
-#[async_trait(?Send)]
-pub(crate) trait SharedInput<'a> {
-    fn default_components<S: ToString>(&self, jump_url: S) -> Vec<twilight_model::channel::message::component::Component> {
-        vec![Component::ActionRow(ActionRow {
-                        components: vec![
-                            Component::Button(Button {
-                                custom_id: Some("color".to_string()),
-                                disabled: false,
-                                emoji: Some(ReactionType::Unicode {
-                                    name: "🎨".to_string(),
-                                }),
-                                label: None,
-                                style: ButtonStyle::Secondary,
-                                url: None,
+
+use crate::store::ReadState;
+
+fn state_button(custom_id: &str, emoji: &str, label: &str) -> Component {
+    Component::Button(Button {
+        custom_id: Some(custom_id.to_string()),
+        disabled: false,
+        emoji: Some(ReactionType::Unicode {
+            name: emoji.to_string(),
+        }),
+        label: Some(label.to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    })
+}
+
+/// The bookmark DM buttons, the jump link is disabled once the original message is gone. The
+/// second row moves the bookmark through the read-later queue, see `components::state`.
+pub(crate) fn default_components<S: ToString>(jump_url: S, source_deleted: bool, state: ReadState) -> Vec<twilight_model::channel::message::component::Component> {
+    let queue = match state {
+        ReadState::Unread => vec![
+            state_button("state:read", "✅", "Mark read"),
+            state_button("state:archive", "📦", "Archive"),
+        ],
+        ReadState::Read => vec![
+            state_button("state:unread", "📬", "Mark unread"),
+            state_button("state:archive", "📦", "Archive"),
+        ],
+        ReadState::Archived => vec![state_button("state:unread", "📤", "Move to inbox")],
+    };
+
+    vec![Component::ActionRow(ActionRow {
+                    components: vec![
+                        Component::Button(Button {
+                            custom_id: Some("color".to_string()),
+                            disabled: false,
+                            emoji: Some(ReactionType::Unicode {
+                                name: "🎨".to_string(),
                             }),
-                            Component::Button(Button {
-                                custom_id: Some("delete".to_string()),
-                                disabled: false,
-                                emoji: Some(ReactionType::Unicode {
-                                    name: "❌".to_string(),
-                                }),
-                                label: None,
-                                style: ButtonStyle::Secondary,
-                                url: None,
+                            label: None,
+                            style: ButtonStyle::Secondary,
+                            url: None,
+                        }),
+                        Component::Button(Button {
+                            custom_id: Some("delete".to_string()),
+                            disabled: false,
+                            emoji: Some(ReactionType::Unicode {
+                                name: "❌".to_string(),
                             }),
-                            Component::Button(Button {
-                                custom_id: None,
-                                disabled: false,
-                                //  link emoji
-                                label: None,
-                                style: ButtonStyle::Link,
-                                url: Some(jump_url.to_string()),
-                                emoji: Some(ReactionType::Unicode {
-                                    name: "🔗".to_string(),
-                                }),
+                            label: None,
+                            style: ButtonStyle::Secondary,
+                            url: None,
+                        }),
+                        Component::Button(Button {
+                            custom_id: Some("refresh".to_string()),
+                            disabled: false,
+                            emoji: Some(ReactionType::Unicode {
+                                name: "🔄".to_string(),
                             }),
-                        ],
-                    })]
-    }
-}
\ No newline at end of file
+                            label: None,
+                            style: ButtonStyle::Secondary,
+                            url: None,
+                        }),
+                        Component::Button(Button {
+                            custom_id: None,
+                            disabled: source_deleted,
+                            //  link emoji
+                            label: None,
+                            style: ButtonStyle::Link,
+                            url: Some(jump_url.to_string()),
+                            emoji: Some(ReactionType::Unicode {
+                                name: "🔗".to_string(),
+                            }),
+                        }),
+                    ],
+                }),
+        Component::ActionRow(ActionRow { components: queue }),
+    ]
+}
@@ -1,15 +1,31 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
+use twilight_model::channel::message::component::ComponentType;
 
-use twilight_model::application::interaction::{Interaction, InteractionData, InteractionType};
+use twilight_model::application::interaction::{
+    application_command::CommandData, message_component::MessageComponentInteractionData,
+    Interaction, InteractionData, InteractionType,
+};
+use twilight_model::channel::message::MessageFlags;
+use twilight_util::builder::InteractionResponseDataBuilder;
 
+use crate::RouteData;
 use crate::command::{init_commands, CommandInput};
 use crate::component::{init_components, ComponentInput};
+use crate::delivery::ephemeral;
 use crate::error::{Error, InteractionError};
+use crate::limits;
+use crate::rest;
+use crate::store::BookmarkStore;
 use twilight_model::http::interaction::{InteractionResponse, InteractionResponseType};
+use worker::{console_log, Date};
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct Context {
     pub(crate) interaction: Interaction,
+    // The raw request body, for fields twilight does not model yet
+    pub(crate) body: String,
 }
 
 impl Context {
@@ -20,26 +36,57 @@ impl Context {
         }
     }
 
+    fn command_input<'a>(
+        &'a self,
+        data: &CommandData,
+        ctx: &'a mut worker::RouteContext<RouteData>,
+    ) -> CommandInput<'a> {
+        CommandInput {
+            id: data.id,
+            name: data.name.clone(),
+            resolved: data.resolved.clone(),
+            kind: data.kind,
+            target_id: data.target_id,
+            options: data.options.clone(),
+            guild_id: self.interaction.guild_id,
+            channel_id: self.interaction.channel_id,
+            user: self.interaction.user.as_ref(),
+            member: self.interaction.member.as_ref(),
+            body: &self.body,
+            application_id: self.interaction.application_id,
+            token: &self.interaction.token,
+            ctx,
+        }
+    }
+
+    fn component_input<'a>(
+        &'a self,
+        data: &MessageComponentInteractionData,
+        ctx: &'a mut worker::RouteContext<RouteData>,
+    ) -> ComponentInput<'a> {
+        ComponentInput {
+            custom_id: data.custom_id.clone(),
+            component_type: data.component_type,
+            values: data.values.clone(),
+            fields: HashMap::new(),
+            guild_id: self.interaction.guild_id,
+            channel_id: self.interaction.channel_id,
+            user: self.interaction.user.as_ref(),
+            member: self.interaction.member.as_ref(),
+            message: self.interaction.message.as_ref(),
+            app_permissions: self.interaction.app_permissions,
+            ctx,
+        }
+    }
+
     pub(crate) async fn handle_command(
         &self,
-        ctx: &mut worker::RouteContext<()>,
+        ctx: &mut worker::RouteContext<RouteData>,
     ) -> Result<InteractionResponse, InteractionError> {
         if let Some(InteractionData::ApplicationCommand(data)) = self.interaction.data.clone() {
             let commands = init_commands();
 
-            let command_input = CommandInput {
-                id: data.id,
-                name: data.name.clone(),
-                resolved: data.resolved,
-                kind: data.kind,
-                target_id: data.target_id,
-                options: data.options,
-                guild_id: self.interaction.guild_id,
-                channel_id: self.interaction.channel_id,
-                user: self.interaction.user.as_ref(),
-                member: self.interaction.member.as_ref(),
-                ctx: ctx,
-            };
+            let command_input = self.command_input(&data, ctx);
 
             for boxed in commands.iter() {
                 let com = boxed;
@@ -59,24 +106,12 @@ impl Context {
 
     pub(crate) async fn handle_autocomplete(
         &self,
-        ctx: &mut worker::RouteContext<()>,
+        ctx: &mut worker::RouteContext<RouteData>,
     ) -> Result<InteractionResponse, InteractionError> {
         if let Some(InteractionData::ApplicationCommand(data)) = self.interaction.data.clone() {
             let commands = init_commands();
 
-            let command_input = CommandInput {
-                id: data.id,
-                name: data.name.clone(),
-                resolved: data.resolved,
-                kind: data.kind,
-                target_id: data.target_id,
-                options: data.options,
-                guild_id: self.interaction.guild_id,
-                channel_id: self.interaction.channel_id,
-                user: self.interaction.user.as_ref(),
-                member: self.interaction.member.as_ref(),
-                ctx: ctx,
-            };
+            let command_input = self.command_input(&data, ctx);
 
             for boxed in commands.iter() {
                 let com = boxed;
@@ -96,20 +131,50 @@ impl Context {
 
     pub(crate) async fn handle_message_component(
         &self,
-        ctx: &mut worker::RouteContext<()>,
+        ctx: &mut worker::RouteContext<RouteData>,
     ) -> Result<InteractionResponse, InteractionError> {
         if let Some(InteractionData::MessageComponent(data)) = self.interaction.data.clone() {
             let components = init_components();
 
+            let component_input = self.component_input(&data, ctx);
+            for boxed in components.iter() {
+                let com = boxed;
+                if data.custom_id.starts_with(&com.custom_id()) {
+                    return com.respond(&component_input).await;
+                }
+            }
+            Err(InteractionError::UnknownCommand(data.custom_id))
+        } else {
+            unreachable!();
+        }
+    }
+
+    // Modals are opened by components, so their submits are routed to the same component
+    pub(crate) async fn handle_modal_submit(
+        &self,
+        ctx: &mut worker::RouteContext<RouteData>,
+    ) -> Result<InteractionResponse, InteractionError> {
+        if let Some(InteractionData::ModalSubmit(data)) = self.interaction.data.clone() {
+            let components = init_components();
+
+            let fields = data
+                .components
+                .into_iter()
+                .flat_map(|row| row.components)
+                .filter_map(|c| Some((c.custom_id, c.value?)))
+                .collect();
+
             let component_input = ComponentInput {
                 custom_id: data.custom_id.clone(),
-                component_type: data.component_type,
-                values: data.values,
+                component_type: ComponentType::TextInput,
+                values: Vec::new(),
+                fields,
                 guild_id: self.interaction.guild_id,
                 channel_id: self.interaction.channel_id,
-                user: self.interaction.member.as_ref(),
+                user: self.interaction.user.as_ref(),
                 member: self.interaction.member.as_ref(),
                 message: self.interaction.message.as_ref(),
+                app_permissions: self.interaction.app_permissions,
                 ctx: ctx,
             };
             for boxed in components.iter() {
@@ -124,9 +189,123 @@ impl Context {
         }
     }
 
+    /// The response telling the user to slow down when they or their server are over the rate
+    /// limit. Limits fail open, a storage hiccup should not lock everyone out
+    pub(crate) async fn rate_limited(
+        &self,
+        ctx: &worker::RouteContext<RouteData>,
+    ) -> Option<InteractionResponse> {
+        // Autocomplete fires on every keystroke and can't show a message, it is not limited
+        if !matches!(
+            self.interaction.kind,
+            InteractionType::ApplicationCommand
+                | InteractionType::MessageComponent
+                | InteractionType::ModalSubmit
+        ) {
+            return None;
+        }
+        let user_id = self.interaction.author_id()?;
+        let store = BookmarkStore::new(ctx).ok()?;
+        let now = Date::now().as_millis();
+        let wait = match limits::take(&store, user_id, self.interaction.guild_id, now).await {
+            Ok(wait) => wait?,
+            Err(err) => {
+                console_log!("[LIMITS] checking the rate limit failed: {}", err);
+                return None;
+            }
+        };
+        Some(InteractionResponse {
+            kind: InteractionResponseType::ChannelMessageWithSource,
+            data: Some(ephemeral(format!(
+                "You're going a bit fast, try again <t:{}:R>",
+                now / 1000 + wait
+            ))),
+        })
+    }
+
+    /// The immediate response for interactions whose handler may not finish within Discord's
+    /// three seconds, or `None` to answer them directly. Deferred interactions are then handled
+    /// with `perform_deferred`.
+    pub(crate) fn deferral(
+        &self,
+        ctx: &mut worker::RouteContext<RouteData>,
+    ) -> Option<InteractionResponse> {
+        match self.interaction.data.as_ref()? {
+            InteractionData::ApplicationCommand(data)
+                if self.interaction.kind == InteractionType::ApplicationCommand =>
+            {
+                let input = self.command_input(data, ctx);
+                init_commands()
+                    .iter()
+                    .find(|com| com.name() == data.name)
+                    .filter(|com| com.deferred(&input))?;
+                Some(InteractionResponse {
+                    kind: InteractionResponseType::DeferredChannelMessageWithSource,
+                    data: Some(
+                        InteractionResponseDataBuilder::new()
+                            .flags(MessageFlags::EPHEMERAL)
+                            .build(),
+                    ),
+                })
+            }
+            InteractionData::MessageComponent(data) => {
+                let input = self.component_input(data, ctx);
+                init_components()
+                    .iter()
+                    .find(|com| data.custom_id.starts_with(&com.custom_id()))
+                    .filter(|com| com.deferred(&input))?;
+                Some(InteractionResponse {
+                    kind: InteractionResponseType::DeferredUpdateMessage,
+                    data: None,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Handles an interaction that was answered with `deferral`, replacing the deferred response
+    /// with the real one. Replies to a component arrive as a follow-up, so an error can't
+    /// overwrite the message the component is on.
+    pub(crate) async fn perform_deferred(&self, ctx: &mut worker::RouteContext<RouteData>) {
+        let response = match self.perform(ctx).await {
+            Ok(response) => response,
+            Err(err) => {
+                console_log!("[DEFERRED] handling the interaction failed: {}", err);
+                InteractionResponse {
+                    kind: InteractionResponseType::ChannelMessageWithSource,
+                    data: Some(ephemeral("Something went wrong, please try again later")),
+                }
+            }
+        };
+        let mut body = match serde_json::to_value(response.data.unwrap_or_default()) {
+            Ok(body) => body,
+            Err(err) => {
+                console_log!("[DEFERRED] serializing the response failed: {}", err);
+                return;
+            }
+        };
+
+        let application_id = self.interaction.application_id;
+        let token = &self.interaction.token;
+        let result = if self.interaction.kind == InteractionType::ApplicationCommand
+            || response.kind == InteractionResponseType::UpdateMessage
+        {
+            // Whether the message is ephemeral was settled by the deferral
+            if let Some(body) = body.as_object_mut() {
+                body.remove("flags");
+            }
+            rest::edit_original_response(application_id, token, &body).await
+        } else {
+            rest::create_followup(application_id, token, &body).await
+        };
+        if let Err(err) = result {
+            console_log!("[DEFERRED] sending the response failed: {}", err);
+        }
+    }
+
     pub(crate) async fn perform(
         &self,
-        ctx: &mut worker::RouteContext<()>,
+        ctx: &mut worker::RouteContext<RouteData>,
     ) -> Result<InteractionResponse, Error> {
         match self.interaction.kind {
             InteractionType::Ping => Ok(self.handle_ping()),
@@ -142,6 +321,10 @@ impl Context {
                 .handle_autocomplete(ctx)
                 .await
                 .map_err(Error::InteractionFailed),
+            InteractionType::ModalSubmit => self
+                .handle_modal_submit(ctx)
+                .await
+                .map_err(Error::InteractionFailed),
             _ => Err(Error::InvalidPayload("Not implemented".into())),
         }
     }
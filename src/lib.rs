@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use worker::*;
 
 mod verification;
@@ -12,6 +14,31 @@ mod commands;
 mod component;
 mod components;
 mod embed;
+mod media;
+mod render;
+mod rest;
+mod delivery;
+mod store;
+mod settings;
+mod guild_config;
+mod limits;
+mod permissions;
+mod audit;
+mod board;
+mod collections;
+mod events;
+mod digest;
+mod feed;
+mod api;
+mod oauth;
+mod scheduled;
+mod user_index;
+mod guild_state;
+#[cfg(test)]
+mod testing;
+
+/// Shared with every route, so work can outlive the response with `wait_until`.
+pub(crate) type RouteData = Rc<worker::Context>;
 
 fn log_request(req: &Request) {
     console_log!(
@@ -24,16 +51,17 @@ fn log_request(req: &Request) {
 }
 
 #[event(fetch)]
-pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Response> {
+pub async fn main(req: Request, env: Env, ctx: worker::Context) -> Result<Response> {
     log_request(&req);
 
     // Optionally, get more helpful error messages written to the console in the case of a panic.
     utils::set_panic_hook();
+    rest::configure(&env);
 
     // Optionally, use the Router to handle matching endpoints, use ":name" placeholders, or "*name"
     // catch-alls to match on specific patterns. Alternatively, use `Router::with_data(D)` to
     // provide arbitrary data that will be accessible in each route via the `ctx.data()` method.
-    let router = Router::new();
+    let router = Router::with_data(Rc::new(ctx));
 
     // Add as many routes as your Worker needs! Each route will get a `Request` for handling HTTP
     // functionality and a `RouteContext` which you can use to  and get route parameters and
@@ -41,7 +69,7 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
     router
         .post_async("/", |req, ctx|  async move {
 
-            let mut app = bot::App::new(req, ctx);
+            let app = bot::App::new(req, ctx);
 
              match app.handle_request().await {
                 Ok(result) => {
@@ -56,6 +84,46 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
             }
 
         })
+        .get_async("/media/:key", |req, ctx| async move {
+            media::serve(req, ctx).await
+        })
+        .get_async("/feed/:user/:file", |req, ctx| async move {
+            feed::atom_feed(req, ctx).await
+        })
+        .get_async("/calendar/:user/:file", |req, ctx| async move {
+            feed::calendar(req, ctx).await
+        })
+        .get_async("/oauth/login", |req, ctx| async move {
+            oauth::login(req, ctx).await
+        })
+        .get_async("/oauth/callback", |req, ctx| async move {
+            oauth::callback(req, ctx).await
+        })
+        .post("/oauth/logout", |req, ctx| {
+            api::with_cors(&ctx, oauth::logout(&req, &ctx))
+        })
+        .options("/api/*path", |_, ctx| {
+            api::preflight(&ctx)
+        })
+        .get_async("/api/me", |req, ctx| async move {
+            api::with_cors(&ctx, api::me(req, &ctx))
+        })
+        .get_async("/api/bookmarks", |req, ctx| async move {
+            let response = api::list(req, &ctx).await;
+            api::with_cors(&ctx, response)
+        })
+        .patch_async("/api/bookmarks/:id", |req, ctx| async move {
+            let response = api::edit(req, &ctx).await;
+            api::with_cors(&ctx, response)
+        })
+        .delete_async("/api/bookmarks/:id", |req, ctx| async move {
+            let response = api::delete(req, &ctx).await;
+            api::with_cors(&ctx, response)
+        })
+        .get_async("/api/export", |req, ctx| async move {
+            let response = api::export(req, &ctx).await;
+            api::with_cors(&ctx, response)
+        })
         .post_async("/register", |_, ctx|  async move {
             let commands = command::init_commands();
 
@@ -69,7 +137,7 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
             let client = reqwest::Client::new();
             let app_id = ctx.var("DISCORD_APPLICATION_ID")?.to_string();
             let token = ctx.var("DISCORD_TOKEN")?.to_string();
-            let url = format!("https://discord.com/api/v10/applications/{}/commands", app_id);
+            let url = format!("{}/applications/{}/commands", rest::configured_api_base(&ctx.env), app_id);
 
             let serialized = serde_json::to_string(&to_register)?;
             worker::console_log!{"Sending  : {}", serialized};
@@ -81,3 +149,10 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
         .run(req, env)
         .await
 }
+
+#[event(scheduled)]
+pub async fn scheduled(event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    utils::set_panic_hook();
+    rest::configure(&env);
+    scheduled::run(event, env).await;
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::error::InteractionError;
+use crate::guild_state::{GuildOp, GuildRequest};
+use crate::store::{call_object, BookmarkStore, UserRequest};
+
+/// A token bucket: `capacity` interactions in a burst, refilled at `per_second`.
+struct Limit {
+    capacity: f64,
+    per_second: f64,
+}
+
+// Ten interactions in a burst, then one every six seconds
+const USER: Limit = Limit {
+    capacity: 10.0,
+    per_second: 1.0 / 6.0,
+};
+
+// Shared by every member of a server, so one busy server can't use up the bot's Discord limits
+const GUILD: Limit = Limit {
+    capacity: 120.0,
+    per_second: 2.0,
+};
+
+// KV rejects expirations sooner than a minute
+const MIN_TTL: u64 = 60;
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Bucket {
+    tokens: f64,
+    updated_at: u64,
+}
+
+impl Limit {
+    // The bucket refilled up to `now`, in milliseconds. A new bucket starts full
+    fn refill(&self, bucket: Option<Bucket>, now: u64) -> Bucket {
+        let Some(bucket) = bucket else {
+            return Bucket {
+                tokens: self.capacity,
+                updated_at: now,
+            };
+        };
+        let elapsed = now.saturating_sub(bucket.updated_at) as f64 / 1000.0;
+        Bucket {
+            tokens: (bucket.tokens + elapsed * self.per_second).min(self.capacity),
+            updated_at: now,
+        }
+    }
+
+    // Seconds until the bucket has a token again
+    fn wait(&self, bucket: &Bucket) -> u64 {
+        ((1.0 - bucket.tokens) / self.per_second).ceil() as u64
+    }
+
+    // Seconds until an untouched bucket is full again, after which it can be forgotten
+    fn ttl(&self, bucket: &Bucket) -> u64 {
+        (((self.capacity - bucket.tokens) / self.per_second).ceil() as u64).max(MIN_TTL)
+    }
+
+    // Takes a token from `bucket`, returning how many seconds to wait when it is empty
+    fn take(&self, bucket: &mut Option<Bucket>, now: u64) -> Option<u64> {
+        let mut refilled = self.refill(bucket.take(), now);
+        let wait = if refilled.tokens < 1.0 {
+            Some(self.wait(&refilled))
+        } else {
+            refilled.tokens -= 1.0;
+            None
+        };
+        *bucket = Some(refilled);
+        wait
+    }
+}
+
+/// Takes a token from a user's bucket, kept by their Durable Object.
+pub(crate) fn take_user(bucket: &mut Option<Bucket>, now: u64) -> Option<u64> {
+    USER.take(bucket, now)
+}
+
+/// Takes a token from a server's bucket, kept by its Durable Object.
+pub(crate) fn take_guild(bucket: &mut Option<Bucket>, now: u64) -> Option<u64> {
+    GUILD.take(bucket, now)
+}
+
+fn bucket_key(scope: &str, id: u64) -> String {
+    format!("ratelimit:{}:{}", scope, id)
+}
+
+// The bucket kept in KV, for when the Durable Objects are not bound. KV is eventually
+// consistent, so bursts spread across locations get more interactions through
+async fn take_kv(
+    store: &BookmarkStore,
+    limit: &Limit,
+    key: &str,
+    now: u64,
+) -> Result<Option<u64>, InteractionError> {
+    let mut bucket = store.get_value(key).await?;
+    let wait = limit.take(&mut bucket, now);
+    if let (None, Some(bucket)) = (wait, bucket) {
+        store.put_value(key, &bucket, Some(limit.ttl(&bucket))).await?;
+    }
+    Ok(wait)
+}
+
+/// Takes a token from the buckets of `user_id` and `guild_id`, returning how many seconds to wait
+/// when either is empty. Each bucket is kept by the Durable Object of its user or server, which
+/// handles one request at a time.
+pub(crate) async fn take(
+    store: &BookmarkStore,
+    user_id: Id<UserMarker>,
+    guild_id: Option<Id<GuildMarker>>,
+    now: u64,
+) -> Result<Option<u64>, InteractionError> {
+    let wait = match store.user_index() {
+        Some(namespace) => {
+            call_object(namespace, &user_id.to_string(), &UserRequest::TakeToken { now }).await?
+        }
+        None => take_kv(store, &USER, &bucket_key("user", user_id.get()), now).await?,
+    };
+    let Some(guild_id) = guild_id.filter(|_| wait.is_none()) else {
+        return Ok(wait);
+    };
+    match store.guild_state() {
+        Some(namespace) => {
+            let request = GuildRequest {
+                guild_id,
+                op: GuildOp::TakeToken { now },
+            };
+            call_object(namespace, &guild_id.to_string(), &request).await
+        }
+        None => take_kv(store, &GUILD, &bucket_key("guild", guild_id.get()), now).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_bucket_allows_a_burst() {
+        let mut bucket = None;
+        for _ in 0..10 {
+            assert_eq!(USER.take(&mut bucket, 1_000), None);
+        }
+        // One token every six seconds
+        assert_eq!(USER.take(&mut bucket, 1_000), Some(6));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut bucket = None;
+        for _ in 0..10 {
+            USER.take(&mut bucket, 0);
+        }
+        assert_eq!(USER.take(&mut bucket, 3_000), Some(3));
+        assert_eq!(USER.take(&mut bucket, 6_000), None);
+        assert_eq!(USER.take(&mut bucket, 6_000), Some(6));
+    }
+
+    #[test]
+    fn bucket_refills_up_to_capacity() {
+        let mut bucket = None;
+        take_guild(&mut bucket, 0);
+        assert_eq!(take_guild(&mut bucket, 3_600_000), None);
+        assert_eq!(bucket.as_ref().map(|bucket| bucket.tokens), Some(119.0));
+    }
+
+    #[test]
+    fn empty_bucket_is_not_overdrawn() {
+        let mut bucket = None;
+        for _ in 0..10 {
+            USER.take(&mut bucket, 0);
+        }
+        for _ in 0..5 {
+            assert_eq!(USER.take(&mut bucket, 0), Some(6));
+        }
+        assert_eq!(USER.take(&mut bucket, 6_000), None);
+    }
+
+}
@@ -0,0 +1,450 @@
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(test)]
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use twilight_model::channel::Attachment;
+use worker::{console_log, Bucket, Date, Headers, HttpMetadata, Request, Response, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+
+// Attachments bigger than this are left on Discord's CDN unless `MEDIA_MAX_BYTES` says otherwise
+const DEFAULT_MAX_BYTES: u64 = 8 * 1024 * 1024;
+
+/// How long a signed media url works, in seconds. Refreshing a bookmark signs its media again.
+pub(crate) const URL_TTL: u64 = 90 * 24 * 60 * 60;
+
+// Served inline, anything else is downloaded. SVG is an image that can run script
+const INLINE_TYPES: [&str; 3] = ["image/", "video/", "audio/"];
+
+#[derive(Clone)]
+pub(crate) struct StoredMedia {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) filename: Option<String>,
+}
+
+#[async_trait(?Send)]
+pub(crate) trait MediaStore {
+    async fn put(&self, key: &str, media: StoredMedia) -> Result<(), InteractionError>;
+
+    async fn get(&self, key: &str) -> Result<Option<StoredMedia>, InteractionError>;
+
+    async fn contains(&self, key: &str) -> Result<bool, InteractionError>;
+}
+
+pub(crate) struct R2Store {
+    bucket: Bucket,
+}
+
+#[async_trait(?Send)]
+impl MediaStore for R2Store {
+    async fn put(&self, key: &str, media: StoredMedia) -> Result<(), InteractionError> {
+        let metadata = HttpMetadata {
+            content_type: media.content_type,
+            content_disposition: media
+                .filename
+                .map(|name| format!("attachment; filename=\"{}\"", name.replace('"', ""))),
+            ..Default::default()
+        };
+        self.bucket
+            .put(key, media.bytes)
+            .http_metadata(metadata)
+            .execute()
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredMedia>, InteractionError> {
+        let object = match self.bucket.get(key).execute().await? {
+            Some(object) => object,
+            None => return Ok(None),
+        };
+        let metadata = object.http_metadata();
+        let bytes = match object.body() {
+            Some(body) => body.bytes().await?,
+            None => Vec::new(),
+        };
+        Ok(Some(StoredMedia {
+            bytes,
+            content_type: metadata.content_type,
+            filename: metadata
+                .content_disposition
+                .and_then(|d| d.split_once("filename=").map(|(_, n)| n.trim_matches('"').to_string())),
+        }))
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool, InteractionError> {
+        Ok(self.bucket.head(key).await?.is_some())
+    }
+}
+
+/// Keeps archived media in memory, standing in for R2 in tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryStore {
+    objects: RefCell<HashMap<String, StoredMedia>>,
+}
+
+#[cfg(test)]
+#[async_trait(?Send)]
+impl MediaStore for MemoryStore {
+    async fn put(&self, key: &str, media: StoredMedia) -> Result<(), InteractionError> {
+        self.objects.borrow_mut().insert(key.to_string(), media);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<StoredMedia>, InteractionError> {
+        Ok(self.objects.borrow().get(key).cloned())
+    }
+
+    async fn contains(&self, key: &str) -> Result<bool, InteractionError> {
+        Ok(self.objects.borrow().contains_key(key))
+    }
+}
+
+pub(crate) struct ArchiveConfig {
+    pub(crate) base_url: String,
+    pub(crate) signing_key: String,
+    pub(crate) max_bytes: u64,
+}
+
+impl ArchiveConfig {
+    /// Archiving is enabled only when the bucket, signing key and public url are all configured.
+    pub(crate) fn from_ctx(ctx: &RouteContext<RouteData>) -> Option<(R2Store, ArchiveConfig)> {
+        let bucket = ctx.bucket("MEDIA_BUCKET").ok()?;
+        let signing_key = ctx.secret("MEDIA_SIGNING_KEY").ok()?.to_string();
+        let base_url = ctx.var("PUBLIC_URL").ok()?.to_string();
+        let max_bytes = ctx
+            .var("MEDIA_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.to_string().parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+
+        Some((
+            R2Store { bucket },
+            ArchiveConfig {
+                base_url: base_url.trim_end_matches('/').to_string(),
+                signing_key,
+                max_bytes,
+            },
+        ))
+    }
+
+    /// A url for `key` that works for `URL_TTL` seconds from `now`.
+    pub(crate) fn url_for(&self, key: &str, now: u64) -> String {
+        let expires = now + URL_TTL;
+        format!(
+            "{}/media/{}?exp={}&sig={}",
+            self.base_url,
+            key,
+            expires,
+            sign(&self.signing_key, &signed_payload(key, expires))
+        )
+    }
+}
+
+// The expiry is signed along with the key, so it can't be pushed back
+fn signed_payload(key: &str, expires: u64) -> String {
+    format!("{}:{}", key, expires)
+}
+
+fn mac(secret: &str, key: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(key.as_bytes());
+    mac
+}
+
+pub(crate) fn sign(secret: &str, key: &str) -> String {
+    hex::encode(mac(secret, key).finalize().into_bytes())
+}
+
+pub(crate) fn verify(secret: &str, key: &str, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(bytes) => mac(secret, key).verify(&bytes).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Copies attachments into the media store and points them at the worker's `/media` route,
+/// signed from `now` (unix seconds). Attachments archived before are only signed again.
+///
+/// Archiving is best effort: attachments that are too large or fail to copy keep their CDN url.
+pub(crate) async fn archive_attachments(
+    store: &dyn MediaStore,
+    config: &ArchiveConfig,
+    attachments: &mut [Attachment],
+    now: u64,
+) {
+    // A bare client, the bot token must not be sent to the CDN
+    let client = reqwest::Client::new();
+
+    for attachment in attachments.iter_mut() {
+        if attachment.size > config.max_bytes {
+            continue;
+        }
+        let key = attachment.id.to_string();
+        if let Ok(true) = store.contains(&key).await {
+            attachment.url = config.url_for(&key, now);
+            attachment.proxy_url = attachment.url.clone();
+            continue;
+        }
+
+        let bytes = match client.get(&attachment.url).send().await {
+            Ok(response) if response.status().is_success() => match response.bytes().await {
+                Ok(bytes) => bytes.to_vec(),
+                Err(e) => {
+                    console_log!("[ARCHIVE] reading {} failed: {:?}", attachment.url, e);
+                    continue;
+                }
+            },
+            Ok(response) => {
+                console_log!("[ARCHIVE] fetching {} returned {}", attachment.url, response.status());
+                continue;
+            }
+            Err(e) => {
+                console_log!("[ARCHIVE] fetching {} failed: {:?}", attachment.url, e);
+                continue;
+            }
+        };
+
+        let media = StoredMedia {
+            bytes,
+            content_type: attachment.content_type.clone(),
+            filename: Some(attachment.filename.clone()),
+        };
+        if let Err(e) = store.put(&key, media).await {
+            console_log!("[ARCHIVE] storing {} failed: {:?}", key, e);
+            continue;
+        }
+
+        attachment.url = config.url_for(&key, now);
+        attachment.proxy_url = attachment.url.clone();
+    }
+}
+
+/// Loads the media a signed url points at, or the status to answer with. `now` in unix seconds.
+pub(crate) async fn lookup(
+    store: &dyn MediaStore,
+    config: &ArchiveConfig,
+    key: &str,
+    expires: u64,
+    signature: &str,
+    now: u64,
+) -> Result<StoredMedia, u16> {
+    if expires < now || !verify(&config.signing_key, &signed_payload(key, expires), signature) {
+        return Err(403);
+    }
+    match store.get(key).await {
+        Ok(Some(media)) => Ok(media),
+        Ok(None) => Err(404),
+        Err(e) => {
+            console_log!("[ARCHIVE] loading {} failed: {}", key, e);
+            Err(500)
+        }
+    }
+}
+
+/// The headers media is served with. Uploads come from any member with any content type, so only
+/// images, video and audio are shown inline, everything else is a download, and nothing served
+/// from the bot's origin (where the dashboard session lives) may run script.
+pub(crate) fn media_headers(media: &StoredMedia) -> Vec<(&'static str, String)> {
+    let content_type = media.content_type.as_deref().unwrap_or_default();
+    let inline = INLINE_TYPES.iter().any(|t| content_type.starts_with(t))
+        && !content_type.starts_with("image/svg");
+    let filename = media.filename.as_deref().unwrap_or("attachment").replace(['"', '\r', '\n'], "");
+
+    let (content_type, disposition) = if inline {
+        (content_type.to_string(), "inline")
+    } else {
+        ("application/octet-stream".to_string(), "attachment")
+    };
+    vec![
+        ("Content-Type", content_type),
+        ("Content-Disposition", format!("{}; filename=\"{}\"", disposition, filename)),
+        ("X-Content-Type-Options", "nosniff".to_string()),
+        ("Content-Security-Policy", "sandbox; default-src 'none'".to_string()),
+        ("Cache-Control", "public, max-age=86400".to_string()),
+    ]
+}
+
+/// Handler for `GET /media/:key`, serving archived attachments with a valid, unexpired signature.
+pub(crate) async fn serve(req: Request, ctx: RouteContext<RouteData>) -> worker::Result<Response> {
+    let (store, config) = match ArchiveConfig::from_ctx(&ctx) {
+        Some(archive) => archive,
+        None => return Response::error("Not Found", 404),
+    };
+    let key = match ctx.param("key") {
+        Some(key) => key.to_string(),
+        None => return Response::error("Not Found", 404),
+    };
+    let url = req.url()?;
+    let query = |name: &str| {
+        url.query_pairs()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_default()
+    };
+    let expires = query("exp").parse().unwrap_or_default();
+    let now = Date::now().as_millis() / 1000;
+
+    let media = match lookup(&store, &config, &key, expires, &query("sig"), now).await {
+        Ok(media) => media,
+        Err(403) => return Response::error("Forbidden", 403),
+        Err(404) => return Response::error("Not Found", 404),
+        Err(status) => return Response::error("Internal Server Error", status),
+    };
+
+    let mut headers = Headers::new();
+    for (name, value) in media_headers(&media) {
+        headers.set(name, &value)?;
+    }
+    Ok(Response::from_bytes(media.bytes)?.with_headers(headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, serve};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn config(base_url: &str) -> ArchiveConfig {
+        ArchiveConfig {
+            base_url: base_url.to_string(),
+            signing_key: "secret".to_string(),
+            max_bytes: 100,
+        }
+    }
+
+    fn attachment(id: u64, url: &str, size: u64) -> Attachment {
+        serde_json::from_value(serde_json::json!({
+            "id": id.to_string(),
+            "filename": "page.html",
+            "content_type": "text/html",
+            "size": size,
+            "url": url,
+            "proxy_url": url,
+        }))
+        .unwrap()
+    }
+
+    fn media(content_type: Option<&str>) -> StoredMedia {
+        StoredMedia {
+            bytes: b"<script>alert(1)</script>".to_vec(),
+            content_type: content_type.map(str::to_string),
+            filename: Some("a\"b.html".to_string()),
+        }
+    }
+
+    fn query(url: &str, name: &str) -> String {
+        let (_, query) = url.split_once('?').unwrap();
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(&format!("{}=", name)))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn signatures_only_verify_for_their_key() {
+        let signature = sign("secret", "123");
+        assert!(verify("secret", "123", &signature));
+        assert!(!verify("secret", "124", &signature));
+        assert!(!verify("other", "123", &signature));
+        assert!(!verify("secret", "123", "not hex"));
+    }
+
+    #[test]
+    fn archives_small_attachments_and_signs_their_urls() {
+        let (cdn, requests) = serve(vec![(200, "text/html", b"<p>hi</p>".to_vec())]);
+        let store = MemoryStore::default();
+        let config = config("https://bot.example");
+        let mut attachments = vec![
+            attachment(1, &format!("{}/attachments/1/page.html", cdn), 9),
+            attachment(2, "https://cdn.discordapp.com/2", 1000),
+        ];
+
+        block_on(archive_attachments(&store, &config, &mut attachments, NOW));
+
+        let download = requests.recv().unwrap();
+        assert_eq!((download.method.as_str(), download.path.as_str()), ("GET", "/attachments/1/page.html"));
+        assert!(download.body.is_empty());
+        assert_eq!(download.header("Authorization"), None);
+        assert!(attachments[0].url.starts_with("https://bot.example/media/1?exp="));
+        assert_eq!(attachments[0].proxy_url, attachments[0].url);
+        assert_eq!(attachments[1].url, "https://cdn.discordapp.com/2");
+
+        let stored = block_on(store.get("1")).unwrap().unwrap();
+        assert_eq!(stored.bytes, b"<p>hi</p>");
+        assert_eq!(stored.filename.as_deref(), Some("page.html"));
+        assert!(!block_on(store.contains("2")).unwrap());
+    }
+
+    #[test]
+    fn archived_attachments_are_signed_again_without_downloading() {
+        let store = MemoryStore::default();
+        block_on(store.put("1", media(Some("text/html")))).unwrap();
+        // Nothing listens here, a download would leave the CDN url in place
+        let mut attachments = vec![attachment(1, "http://127.0.0.1:9/1", 9)];
+
+        block_on(archive_attachments(&store, &config("https://bot.example"), &mut attachments, NOW));
+
+        assert_eq!(query(&attachments[0].url, "exp"), (NOW + URL_TTL).to_string());
+    }
+
+    #[test]
+    fn lookup_checks_signature_and_expiry() {
+        let store = MemoryStore::default();
+        let config = config("https://bot.example");
+        block_on(store.put("1", media(Some("image/png")))).unwrap();
+        let url = config.url_for("1", NOW);
+        let expires: u64 = query(&url, "exp").parse().unwrap();
+        let signature = query(&url, "sig");
+
+        let found = block_on(lookup(&store, &config, "1", expires, &signature, NOW + 60));
+        assert!(found.is_ok());
+        let expired = block_on(lookup(&store, &config, "1", expires, &signature, expires + 1));
+        assert_eq!(expired.err(), Some(403));
+        let extended = block_on(lookup(&store, &config, "1", expires + URL_TTL, &signature, NOW));
+        assert_eq!(extended.err(), Some(403));
+        let other_key = block_on(lookup(&store, &config, "2", expires, &signature, NOW));
+        assert_eq!(other_key.err(), Some(403));
+
+        let missing = config.url_for("2", NOW);
+        let gone = block_on(lookup(&store, &config, "2", expires, &query(&missing, "sig"), NOW));
+        assert_eq!(gone.err(), Some(404));
+    }
+
+    #[test]
+    fn only_media_is_served_inline() {
+        let header = |media: &StoredMedia, name: &str| {
+            media_headers(media)
+                .into_iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| value)
+                .unwrap()
+        };
+
+        let html = media(Some("text/html"));
+        assert_eq!(header(&html, "Content-Type"), "application/octet-stream");
+        assert_eq!(header(&html, "Content-Disposition"), "attachment; filename=\"ab.html\"");
+
+        let svg = media(Some("image/svg+xml"));
+        assert_eq!(header(&svg, "Content-Type"), "application/octet-stream");
+        assert!(header(&media(None), "Content-Disposition").starts_with("attachment"));
+
+        let png = media(Some("image/png"));
+        assert_eq!(header(&png, "Content-Type"), "image/png");
+        assert!(header(&png, "Content-Disposition").starts_with("inline"));
+
+        for media in [html, svg, png] {
+            assert_eq!(header(&media, "X-Content-Type-Options"), "nosniff");
+            assert!(header(&media, "Content-Security-Policy").starts_with("sandbox"));
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use twilight_model::id::{marker::UserMarker, Id};
+use worker::{console_log, Date, Headers, Request, Response, RouteContext};
+
+use crate::RouteData;
+use crate::api;
+use crate::media::{sign, verify};
+use crate::rest;
+use crate::utils::random_token;
+
+const SESSION_COOKIE: &str = "bookmarker_session";
+const STATE_COOKIE: &str = "bookmarker_oauth_state";
+
+// How long a dashboard login lasts, in seconds
+const SESSION_TTL: u64 = 7 * 24 * 60 * 60;
+// How long the user has to approve the login on Discord
+const STATE_TTL: u64 = 10 * 60;
+
+const AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+
+/// Dashboard login is enabled only when the client secret, session secret and public url are all
+/// configured.
+pub(crate) struct OAuthConfig {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+    /// Where the code is exchanged and the user is fetched, `DISCORD_API_BASE` points it at a mock
+    api_base: String,
+    pub(crate) session_secret: String,
+    /// The dashboard users are sent back to, also the only origin allowed to call the API
+    pub(crate) dashboard_url: Option<String>,
+}
+
+impl OAuthConfig {
+    pub(crate) fn from_ctx(ctx: &RouteContext<RouteData>) -> Option<OAuthConfig> {
+        let client_id = ctx.var("DISCORD_APPLICATION_ID").ok()?.to_string();
+        let client_secret = ctx.secret("DISCORD_CLIENT_SECRET").ok()?.to_string();
+        let session_secret = ctx.secret("SESSION_SECRET").ok()?.to_string();
+        let public_url = ctx.var("PUBLIC_URL").ok()?.to_string();
+        let dashboard_url = ctx.var("DASHBOARD_URL").ok().map(|v| v.to_string());
+
+        Some(OAuthConfig {
+            client_id,
+            client_secret,
+            redirect_uri: format!("{}/oauth/callback", public_url.trim_end_matches('/')),
+            api_base: rest::configured_api_base(&ctx.env),
+            session_secret,
+            dashboard_url,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct CurrentUser {
+    id: Id<UserMarker>,
+}
+
+fn now_secs() -> u64 {
+    Date::now().as_millis() / 1000
+}
+
+/// A session is `<user id>.<expiry>.<signature>`, nothing else is stored for it.
+fn session_value(secret: &str, user_id: Id<UserMarker>, expires: u64) -> String {
+    let payload = format!("{}.{}", user_id, expires);
+    let signature = sign(secret, &payload);
+    format!("{}.{}", payload, signature)
+}
+
+fn verify_session(secret: &str, value: &str, now: u64) -> Option<Id<UserMarker>> {
+    let (payload, signature) = value.rsplit_once('.')?;
+    if !verify(secret, payload, signature) {
+        return None;
+    }
+    let (user, expires) = payload.split_once('.')?;
+    if expires.parse::<u64>().ok()? < now {
+        return None;
+    }
+    user.parse().ok().and_then(Id::new_checked)
+}
+
+fn cookie(req: &Request, name: &str) -> Option<String> {
+    let header = req.headers().get("Cookie").ok()??;
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+// The session cookie is sent cross-site from the dashboard, so it needs `SameSite=None`
+fn set_cookie(name: &str, value: &str, max_age: u64, same_site: &str) -> String {
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite={}",
+        name, value, max_age, same_site
+    )
+}
+
+/// The user of a valid session cookie.
+pub(crate) fn session_user(req: &Request, config: &OAuthConfig) -> Option<Id<UserMarker>> {
+    verify_session(&config.session_secret, &cookie(req, SESSION_COOKIE)?, now_secs())
+}
+
+fn redirect(location: &str, cookies: &[String]) -> worker::Result<Response> {
+    let mut headers = Headers::new();
+    headers.set("Location", location)?;
+    for cookie in cookies {
+        headers.append("Set-Cookie", cookie)?;
+    }
+    Ok(Response::empty()?.with_status(302).with_headers(headers))
+}
+
+/// `GET /oauth/login`, sends the user to Discord to approve the `identify` scope.
+pub(crate) async fn login(_req: Request, ctx: RouteContext<RouteData>) -> worker::Result<Response> {
+    let Some(config) = OAuthConfig::from_ctx(&ctx) else {
+        return Response::error("Not Found", 404);
+    };
+    let state = random_token(16);
+    let url = Url::parse_with_params(
+        AUTHORIZE_URL,
+        &[
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("scope", "identify"),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("state", state.as_str()),
+            ("prompt", "none"),
+        ],
+    )?;
+    redirect(
+        url.as_str(),
+        &[set_cookie(STATE_COOKIE, &state, STATE_TTL, "Lax")],
+    )
+}
+
+// Exchanges the authorization code and returns who approved it
+async fn identify(config: &OAuthConfig, code: &str) -> Result<Id<UserMarker>, String> {
+    let client = Client::new();
+    let token = client
+        .post(format!("{}/oauth2/token", config.api_base))
+        .form(&[
+            ("client_id", config.client_id.as_str()),
+            ("client_secret", config.client_secret.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", config.redirect_uri.as_str()),
+        ])
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let token: TokenResponse = serde_json::from_str(&token).map_err(|e| e.to_string())?;
+
+    let user = client
+        .get(format!("{}/users/@me", config.api_base))
+        .bearer_auth(token.access_token)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    let user: CurrentUser = serde_json::from_str(&user).map_err(|e| e.to_string())?;
+    Ok(user.id)
+}
+
+/// `GET /oauth/callback`, where Discord sends the user back with a code.
+pub(crate) async fn callback(req: Request, ctx: RouteContext<RouteData>) -> worker::Result<Response> {
+    let Some(config) = OAuthConfig::from_ctx(&ctx) else {
+        return Response::error("Not Found", 404);
+    };
+    let url = req.url()?;
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+
+    // The state must match the cookie set by `login`, so logins can't be started by another site
+    let (Some(code), Some(state)) = (param("code"), param("state")) else {
+        return Response::error("Bad Request", 400);
+    };
+    if cookie(&req, STATE_COOKIE).as_deref() != Some(state.as_str()) {
+        return Response::error("Forbidden", 403);
+    }
+
+    let user_id = match identify(&config, &code).await {
+        Ok(user_id) => user_id,
+        Err(err) => {
+            console_log!("[OAUTH] login failed: {}", err);
+            return Response::error("Login failed", 502);
+        }
+    };
+
+    let session = session_value(&config.session_secret, user_id, now_secs() + SESSION_TTL);
+    redirect(
+        config.dashboard_url.as_deref().unwrap_or("/"),
+        &[
+            set_cookie(SESSION_COOKIE, &session, SESSION_TTL, "None"),
+            set_cookie(STATE_COOKIE, "", 0, "Lax"),
+        ],
+    )
+}
+
+/// `POST /oauth/logout`, clears the session cookie. Like the API's changes, only the dashboard can
+/// ask for it, the cookie is sent along from any site.
+pub(crate) fn logout(req: &Request, ctx: &RouteContext<RouteData>) -> worker::Result<Response> {
+    let Some(config) = OAuthConfig::from_ctx(ctx) else {
+        return Response::error("Not Found", 404);
+    };
+    if !api::origin_allowed(req, &config) {
+        return Response::error("Forbidden", 403);
+    }
+    let mut headers = Headers::new();
+    headers.set("Set-Cookie", &set_cookie(SESSION_COOKIE, "", 0, "None"))?;
+    Ok(Response::empty()?.with_status(204).with_headers(headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, serve};
+
+    fn config(api_base: String) -> OAuthConfig {
+        OAuthConfig {
+            client_id: "123".to_string(),
+            client_secret: "client-secret".to_string(),
+            redirect_uri: "https://bot.example/oauth/callback".to_string(),
+            api_base,
+            session_secret: "session-secret".to_string(),
+            dashboard_url: None,
+        }
+    }
+
+    #[test]
+    fn codes_are_exchanged_for_the_user() {
+        let (base, received) = serve(vec![
+            (200, "application/json", br#"{"access_token":"token","token_type":"Bearer"}"#.to_vec()),
+            (200, "application/json", br#"{"id":"42","username":"someone"}"#.to_vec()),
+        ]);
+        let user_id = block_on(identify(&config(base), "the-code")).unwrap();
+        assert_eq!(user_id, Id::new(42));
+
+        let exchange = received.recv().unwrap();
+        assert_eq!((exchange.method.as_str(), exchange.path.as_str()), ("POST", "/oauth2/token"));
+        assert!(exchange.body.contains("grant_type=authorization_code"));
+        assert!(exchange.body.contains("code=the-code"));
+        assert!(exchange.body.contains("client_secret=client-secret"));
+        let me = received.recv().unwrap();
+        assert_eq!((me.method.as_str(), me.path.as_str()), ("GET", "/users/@me"));
+        assert_eq!(me.header("Authorization"), Some("Bearer token"));
+    }
+
+    #[test]
+    fn rejected_codes_fail_the_login() {
+        let (base, _received) = serve(vec![(400, "application/json", br#"{"error":"invalid_grant"}"#.to_vec())]);
+        assert!(block_on(identify(&config(base), "expired")).is_err());
+    }
+
+    #[test]
+    fn sessions_expire_and_are_signed() {
+        let session = session_value("secret", Id::new(42), 100);
+        assert_eq!(verify_session("secret", &session, 100), Some(Id::new(42)));
+        assert_eq!(verify_session("secret", &session, 101), None);
+        assert_eq!(verify_session("other", &session, 100), None);
+        let forged = session.replacen("42", "43", 1);
+        assert_eq!(verify_session("secret", &forged, 100), None);
+    }
+}
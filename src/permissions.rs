@@ -0,0 +1,244 @@
+use reqwest::Client;
+use twilight_model::channel::permission_overwrite::PermissionOverwriteType;
+use twilight_model::channel::{Channel, ChannelType};
+use twilight_model::guild::{Guild, Permissions};
+use twilight_model::id::{
+    marker::{ChannelMarker, RoleMarker, UserMarker},
+    Id,
+};
+
+use crate::rest::{self, RestError};
+
+/// A member's permissions in `channel`, the way Discord computes them: the roles' permissions,
+/// then the @everyone overwrite, all role overwrites together and finally the member's own.
+/// Threads have no overwrites, pass their parent channel.
+pub(crate) fn channel_permissions(
+    guild: &Guild,
+    channel: &Channel,
+    user_id: Id<UserMarker>,
+    roles: &[Id<RoleMarker>],
+) -> Permissions {
+    if guild.owner_id == user_id {
+        return Permissions::all();
+    }
+
+    let mut permissions = Permissions::empty();
+    for role in &guild.roles {
+        if role.id.get() == guild.id.get() || roles.contains(&role.id) {
+            permissions |= role.permissions;
+        }
+    }
+    if permissions.contains(Permissions::ADMINISTRATOR) {
+        return Permissions::all();
+    }
+
+    let overwrites = channel.permission_overwrites.as_deref().unwrap_or_default();
+    if let Some(everyone) = overwrites.iter().find(|o| o.id.get() == guild.id.get()) {
+        permissions = (permissions - everyone.deny) | everyone.allow;
+    }
+    let (mut allow, mut deny) = (Permissions::empty(), Permissions::empty());
+    for overwrite in overwrites {
+        if overwrite.kind == PermissionOverwriteType::Role
+            && roles.iter().any(|role| role.get() == overwrite.id.get())
+        {
+            allow |= overwrite.allow;
+            deny |= overwrite.deny;
+        }
+    }
+    permissions = (permissions - deny) | allow;
+    if let Some(member) = overwrites
+        .iter()
+        .find(|o| o.kind == PermissionOverwriteType::Member && o.id.get() == user_id.get())
+    {
+        permissions = (permissions - member.deny) | member.allow;
+    }
+    permissions
+}
+
+/// Checks the channels of `guild` for one member. Interactions only carry the member's permissions
+/// in the channel they were used in, commands that act on other channels compute them here.
+pub(crate) struct MemberAccess<'a> {
+    pub(crate) client: &'a Client,
+    pub(crate) guild: &'a Guild,
+    pub(crate) user_id: Id<UserMarker>,
+    pub(crate) roles: Vec<Id<RoleMarker>>,
+}
+
+impl<'a> MemberAccess<'a> {
+    /// Fetches the member's roles, `None` when they are not in the server.
+    pub(crate) async fn fetch(
+        client: &'a Client,
+        guild: &'a Guild,
+        user_id: Id<UserMarker>,
+    ) -> Result<Option<MemberAccess<'a>>, RestError> {
+        match rest::get_member_roles(client, guild.id, user_id).await {
+            Ok(roles) => Ok(Some(MemberAccess {
+                client,
+                guild,
+                user_id,
+                roles,
+            })),
+            Err(RestError::NotFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The member's permissions in `channel_id`, empty for channels of other servers and private
+    /// threads they have not joined.
+    pub(crate) async fn permissions(
+        &self,
+        channel_id: Id<ChannelMarker>,
+    ) -> Result<Permissions, RestError> {
+        let mut channel = match rest::get_channel(self.client, channel_id).await {
+            Ok(channel) => channel,
+            Err(RestError::NotFound) => return Ok(Permissions::empty()),
+            Err(err) => return Err(err),
+        };
+        if channel.guild_id != Some(self.guild.id) {
+            return Ok(Permissions::empty());
+        }
+        if self.guild.owner_id == self.user_id {
+            return Ok(Permissions::all());
+        }
+
+        // Threads take the permissions of their parent, private ones also need the member to be in them
+        let private_thread = channel.kind == ChannelType::PrivateThread;
+        if private_thread && !rest::is_thread_member(self.client, channel_id, self.user_id).await? {
+            return Ok(Permissions::empty());
+        }
+        if channel.kind.is_thread() {
+            let Some(parent_id) = channel.parent_id else {
+                return Ok(Permissions::empty());
+            };
+            channel = rest::get_channel(self.client, parent_id).await?;
+        }
+        Ok(channel_permissions(self.guild, &channel, self.user_id, &self.roles))
+    }
+}
+
+/// Whether `user_id` can read the message history of `channel_id` in `guild`.
+pub(crate) async fn can_read_history(
+    client: &Client,
+    guild: &Guild,
+    channel_id: Id<ChannelMarker>,
+    user_id: Id<UserMarker>,
+) -> Result<bool, RestError> {
+    let Some(access) = MemberAccess::fetch(client, guild, user_id).await? else {
+        return Ok(false);
+    };
+    Ok(access
+        .permissions(channel_id)
+        .await?
+        .contains(Permissions::VIEW_CHANNEL | Permissions::READ_MESSAGE_HISTORY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: u64 = 1;
+    const OWNER: u64 = 2;
+    const MEMBER: u64 = 3;
+    const MODS: u64 = 10;
+    const MUTED: u64 = 11;
+
+    fn guild(everyone: Permissions) -> Guild {
+        let role = |id: u64, permissions: Permissions| {
+            serde_json::json!({
+                "id": id.to_string(),
+                "name": id.to_string(),
+                "color": 0,
+                "hoist": false,
+                "managed": false,
+                "mentionable": false,
+                "position": 0,
+                "permissions": permissions.bits().to_string(),
+            })
+        };
+        serde_json::from_value(serde_json::json!({
+            "id": GUILD.to_string(),
+            "name": "guild",
+            "owner_id": OWNER.to_string(),
+            "afk_timeout": 300,
+            "default_message_notifications": 0,
+            "explicit_content_filter": 0,
+            "features": [],
+            "mfa_level": 0,
+            "nsfw_level": 0,
+            "preferred_locale": "en-US",
+            "premium_progress_bar_enabled": false,
+            "premium_tier": 0,
+            "system_channel_flags": 0,
+            "verification_level": 0,
+            "emojis": [],
+            "roles": [
+                role(GUILD, everyone),
+                role(MODS, Permissions::MANAGE_MESSAGES | Permissions::VIEW_CHANNEL),
+                role(MUTED, Permissions::empty()),
+            ],
+        }))
+        .unwrap()
+    }
+
+    // `overwrites` are (id, is member, allow, deny)
+    fn channel(overwrites: &[(u64, bool, Permissions, Permissions)]) -> Channel {
+        let overwrites: Vec<_> = overwrites
+            .iter()
+            .map(|(id, member, allow, deny)| {
+                serde_json::json!({
+                    "id": id.to_string(),
+                    "type": if *member { 1 } else { 0 },
+                    "allow": allow.bits().to_string(),
+                    "deny": deny.bits().to_string(),
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": "100",
+            "type": 0,
+            "guild_id": GUILD.to_string(),
+            "permission_overwrites": overwrites,
+        }))
+        .unwrap()
+    }
+
+    fn roles(ids: &[u64]) -> Vec<Id<RoleMarker>> {
+        ids.iter().map(|id| Id::new(*id)).collect()
+    }
+
+    const READ: Permissions = Permissions::VIEW_CHANNEL.union(Permissions::READ_MESSAGE_HISTORY);
+
+    #[test]
+    fn roles_and_overwrites_apply_in_order() {
+        let guild = guild(READ);
+        let open = channel(&[]);
+        assert!(channel_permissions(&guild, &open, Id::new(MEMBER), &[]).contains(READ));
+
+        // Hidden from @everyone, shown to mods, and a member overwrite beats role overwrites
+        let hidden = channel(&[
+            (GUILD, false, Permissions::empty(), Permissions::VIEW_CHANNEL),
+            (MODS, false, Permissions::VIEW_CHANNEL, Permissions::empty()),
+            (MUTED, false, Permissions::empty(), Permissions::VIEW_CHANNEL),
+            (MEMBER, true, Permissions::VIEW_CHANNEL, Permissions::empty()),
+        ]);
+        let member = |roles: &[u64]| channel_permissions(&guild, &hidden, Id::new(MEMBER), &self::roles(roles));
+        let other = |roles: &[u64]| channel_permissions(&guild, &hidden, Id::new(4), &self::roles(roles));
+        assert!(!other(&[]).contains(Permissions::VIEW_CHANNEL));
+        assert!(other(&[MODS]).contains(Permissions::VIEW_CHANNEL));
+        // Role overwrites are combined, allows win over denies
+        assert!(other(&[MODS, MUTED]).contains(Permissions::VIEW_CHANNEL));
+        assert!(!other(&[MUTED]).contains(Permissions::VIEW_CHANNEL));
+        assert!(member(&[MUTED]).contains(Permissions::VIEW_CHANNEL));
+    }
+
+    #[test]
+    fn owners_and_administrators_see_everything() {
+        let guild = guild(Permissions::empty());
+        let hidden = channel(&[(GUILD, false, Permissions::empty(), Permissions::all())]);
+        assert_eq!(channel_permissions(&guild, &hidden, Id::new(OWNER), &[]), Permissions::all());
+        assert_eq!(channel_permissions(&guild, &hidden, Id::new(MEMBER), &[]), Permissions::empty());
+
+        let admins = self::guild(Permissions::ADMINISTRATOR);
+        assert_eq!(channel_permissions(&admins, &hidden, Id::new(MEMBER), &[]), Permissions::all());
+    }
+}
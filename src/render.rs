@@ -0,0 +1,682 @@
+use regex::Regex;
+use reqwest::Client;
+use serde::Deserialize;
+use twilight_model::channel::message::embed::{EmbedFooter, EmbedImage};
+use twilight_model::channel::message::sticker::{
+    MessageSticker, Sticker, StickerFormatType, StickerPack, StickerType,
+};
+use twilight_model::channel::message::Embed;
+use twilight_model::channel::{Attachment, Message};
+use twilight_model::guild::Guild;
+use twilight_model::id::marker::{AttachmentMarker, ChannelMarker, GuildMarker, MessageMarker};
+use twilight_model::id::Id;
+use twilight_model::user::User;
+use twilight_util::builder::embed::{
+    EmbedAuthorBuilder, EmbedBuilder, EmbedFieldBuilder, EmbedFooterBuilder, ImageSource,
+};
+use twilight_validate::embed::embed as validate_embed;
+use worker::{Date, RouteContext};
+
+use crate::RouteData;
+use crate::media::{archive_attachments, ArchiveConfig};
+use crate::rest;
+use crate::settings::Settings;
+use crate::store::ReadState;
+
+pub(crate) fn replace_links_with_markdown(text: &str) -> String {
+    let mdlink_regex = Regex::new(r#"\[.*?\]\(.*?\)"#).unwrap();
+    let mut replaced_text = text.to_string();
+
+    // Match and replace existing markdown links
+    // for mdlink in mdlink_regex.find_iter(text) {
+
+    //     let url = &text[mdlink.start()..mdlink.end()];
+    //     let markdown_link = format!("[{}]({})", url, url);
+    //     replaced_text = replaced_text.replace(&text[mdlink.start()..mdlink.end()], &markdown_link);
+    // }
+
+    // Match and replace non-markdown links.
+    let link_regex = Regex::new(r#"(?:[^\(])(?P<url>https?://[^\s]+)"#).unwrap();
+    replaced_text = link_regex
+        .replace_all(&replaced_text, "[${url}](${url})")
+        .to_string();
+
+    replaced_text
+}
+
+// Discord shows at most four images in a gallery
+const GALLERY_SIZE: usize = 4;
+
+// Discord rejects messages with more embeds than this
+const MAX_EMBEDS: usize = 10;
+
+// Attachment extensions shown as images when Discord did not send a content type
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+// `MessageFlags::IS_VOICE_MESSAGE`, which twilight-model 0.15 truncates away
+const IS_VOICE_MESSAGE: u64 = 1 << 13;
+
+#[derive(Deserialize)]
+struct RawAttachment {
+    id: Id<AttachmentMarker>,
+    duration_secs: Option<f64>,
+}
+
+/// The parts of a resolved message that twilight-model 0.15 drops when deserializing.
+#[derive(Deserialize)]
+pub(crate) struct RawMessage {
+    #[serde(default)]
+    flags: u64,
+    #[serde(default)]
+    attachments: Vec<RawAttachment>,
+}
+
+pub(crate) fn raw_message(body: &str, message_id: Id<MessageMarker>) -> Option<RawMessage> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let message = value.pointer(&format!("/data/resolved/messages/{}", message_id))?;
+    serde_json::from_value(message.clone()).ok()
+}
+
+// Videos have a width and height too, without a content type only the extension tells them apart
+fn is_image(attachment: &Attachment) -> bool {
+    match &attachment.content_type {
+        Some(kind) => kind.starts_with("image/"),
+        None => attachment
+            .filename
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())),
+    }
+}
+
+/// How many images besides the first fit in the gallery next to `used` embeds, keeping room for
+/// the attachment list and sticker embeds. Images left out are listed with the other attachments.
+fn gallery_extras(used: usize, images: usize, listed: bool, stickers: bool) -> usize {
+    let reserved = usize::from(listed) + usize::from(stickers);
+    (GALLERY_SIZE - 1)
+        .min(images.saturating_sub(1))
+        .min(MAX_EMBEDS.saturating_sub(used + reserved))
+}
+
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+pub(crate) fn describe_attachment(attachment: &Attachment, raw: Option<&RawMessage>) -> String {
+    let kind = attachment.content_type.as_deref().unwrap_or_default();
+    let voice = raw.is_some_and(|m| m.flags & IS_VOICE_MESSAGE != 0);
+    let duration = raw
+        .and_then(|m| m.attachments.iter().find(|a| a.id == attachment.id))
+        .and_then(|a| a.duration_secs);
+
+    let label = if voice {
+        "🎙️ Voice message"
+    } else if kind.starts_with("video/") {
+        "🎞️ Video"
+    } else if kind.starts_with("audio/") {
+        "🔊 Audio"
+    } else if kind.starts_with("image/") {
+        "🖼️ Image"
+    } else {
+        "📄 File"
+    };
+
+    let mut details = vec![format_size(attachment.size)];
+    if let Some(duration) = duration {
+        details.push(format_duration(duration));
+    }
+    format!(
+        "{} [{}]({}) · {}",
+        label,
+        attachment.filename,
+        attachment.url,
+        details.join(" · ")
+    )
+}
+
+struct RenderedSticker {
+    label: String,
+    image: Option<String>,
+    // Whether `image` is a preview standing in for a sticker that can't be shown itself
+    preview: bool,
+}
+
+// The application the sticker pack store assets are published under
+const STICKER_STORE_APPLICATION_ID: u64 = 710982414301790216;
+
+fn sticker_url(sticker: &MessageSticker) -> String {
+    let typ = match sticker.format_type {
+        StickerFormatType::Png | StickerFormatType::Apng => "png",
+        StickerFormatType::Lottie => "json",
+        StickerFormatType::Gif => "gif",
+        _ => "png",
+    };
+    // GIF stickers are only served by the media proxy, not by cdn.discordapp.com
+    format!(
+        "https://media.discordapp.net/stickers/{}.{}",
+        sticker.id.get(),
+        typ
+    )
+}
+
+/// Where a sticker comes from, given the server the message was sent in.
+fn sticker_source(sticker: &Sticker, pack: Option<&StickerPack>, guild: &Guild) -> Option<String> {
+    match (sticker.kind, sticker.guild_id, pack) {
+        (StickerType::Guild, Some(guild_id), _) if guild_id == guild.id => {
+            Some(format!("sticker from {}", guild.name))
+        }
+        (StickerType::Guild, _, _) => Some("sticker from another server".to_string()),
+        (_, _, Some(pack)) => Some(format!("{} pack", pack.name)),
+        _ => None,
+    }
+}
+
+fn pack_banner(pack: &StickerPack) -> Option<String> {
+    pack.banner_asset_id.map(|banner| {
+        format!(
+            "https://cdn.discordapp.com/app-assets/{}/store/{}.png",
+            STICKER_STORE_APPLICATION_ID, banner
+        )
+    })
+}
+
+/// Shows image stickers as they are. Lottie stickers can't be embedded, they get a link labelled
+/// with their pack or server and, when the pack has one, its banner as a static preview.
+async fn render_sticker(client: &Client, sticker: &MessageSticker, guild: &Guild) -> RenderedSticker {
+    let url = sticker_url(sticker);
+    let full = rest::get_sticker(client, sticker.id).await.ok();
+    let pack = match full.as_ref().and_then(|full| full.pack_id) {
+        Some(pack_id) => rest::get_sticker_pack(client, pack_id).await.ok(),
+        None => None,
+    };
+    let source = full
+        .as_ref()
+        .and_then(|full| sticker_source(full, pack.as_ref(), guild))
+        .map(|source| format!(" · {}", source))
+        .unwrap_or_default();
+
+    match sticker.format_type {
+        StickerFormatType::Png | StickerFormatType::Apng | StickerFormatType::Gif => RenderedSticker {
+            label: format!("[{}]({}){}", sticker.name, url, source),
+            image: Some(url),
+            preview: false,
+        },
+        _ => RenderedSticker {
+            label: format!("🎞️ [{}]({}) · animated sticker{}", sticker.name, url, source),
+            image: pack.as_ref().and_then(pack_banner),
+            preview: true,
+        },
+    }
+}
+
+pub(crate) fn jump_url(
+    guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+) -> String {
+    match guild_id {
+        Some(guild_id) => format!(
+            "https://discord.com/channels/{}/{}/{}",
+            guild_id, channel_id, message_id
+        ),
+        None => format!(
+            "https://discord.com/channels/@me/{}/{}",
+            channel_id, message_id
+        ),
+    }
+}
+
+fn author(user: &User) -> EmbedAuthorBuilder {
+    let author = EmbedAuthorBuilder::new(format!("{} ({})", user.name, user.id.get()));
+
+    if let Some(icon) = user.avatar {
+        author.icon_url(
+            ImageSource::url(format!(
+                "https://cdn.discordapp.com/avatars/{}/{}.png",
+                user.id.get(),
+                icon
+            ))
+            .unwrap(),
+        )
+    } else {
+        author.icon_url(
+            ImageSource::url(format!(
+                "https://cdn.discordapp.com/embed/avatars/{}.png",
+                user.discriminator % 5
+            ))
+            .unwrap(),
+        )
+    }
+}
+
+fn footer(guild: &Guild) -> EmbedFooter {
+    let footer = EmbedFooterBuilder::new(format!("{} ({})", guild.name, guild.id.get()));
+
+    if let Some(icon) = guild.icon {
+        footer
+            .icon_url(
+                ImageSource::url(format!(
+                    "https://cdn.discordapp.com/icons/{}/{}.png",
+                    guild.id.get(),
+                    icon
+                ))
+                .unwrap(),
+            )
+            .build()
+    } else {
+        footer
+            .icon_url(ImageSource::url("https://cdn.discordapp.com/embed/avatars/0.png").unwrap())
+            .build()
+    }
+}
+
+/// Puts the read-later state in front of the footer of the first embed, replacing an earlier one.
+pub(crate) fn show_read_state(embeds: &mut [Embed], state: ReadState) {
+    let Some(embed) = embeds.first_mut() else {
+        return;
+    };
+    let Some(footer) = embed.footer.as_mut() else {
+        embed.footer = Some(EmbedFooterBuilder::new(state.label()).build());
+        return;
+    };
+    let text = [ReadState::Unread, ReadState::Read, ReadState::Archived]
+        .iter()
+        .find_map(|s| footer.text.strip_prefix(&format!("{} · ", s.label())))
+        .unwrap_or(&footer.text)
+        .to_string();
+    footer.text = format!("{} · {}", state.label(), text);
+}
+
+// A one line quote of the replied to message, kept short so it does not compete with the bookmark
+fn reply_context(reply: &Message, guild: &Guild, no_content: &str) -> String {
+    let content = if reply.content.trim().is_empty() {
+        no_content.to_string()
+    } else {
+        truncate(&reply.content.replace('\n', " "), 100)
+    };
+    format!(
+        "> ↩️ **{}**: [{}]({})",
+        reply.author.name,
+        content,
+        jump_url(Some(guild.id), reply.channel_id, reply.id)
+    )
+}
+
+/// Renders a single message as the embeds of its bookmark DM.
+///
+/// `raw` carries the attachment metadata twilight drops, it is optional as it is only known for
+/// messages resolved from the interaction payload.
+pub(crate) async fn message_embeds(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    msg_data: &Message,
+    raw: Option<&RawMessage>,
+    guild: &Guild,
+    t_url: &str,
+    settings: &Settings,
+) -> Vec<Embed> {
+    let mut embeds = msg_data
+        .embeds
+        .clone()
+        .into_iter()
+        .filter(|e| e.kind == "rich")
+        .collect::<Vec<Embed>>();
+
+    let phrases = settings.phrases();
+    let mut content = msg_data.content.clone();
+    if settings.reply_context {
+        if let Some(reply) = msg_data.referenced_message.as_ref() {
+            content = format!("{}\n{}", reply_context(reply, guild, phrases.no_content), content);
+        }
+    }
+
+    if content.len() > 0 {
+        embeds.insert(
+            0,
+            EmbedBuilder::new()
+                .description(content)
+                .build(),
+        );
+    };
+
+    let can_add = |embed: &Embed, desc: &String| {
+        let mut temp = embed.clone();
+        temp.description = Some(temp.description.unwrap_or_default() + &desc);
+        validate_embed(&temp).is_ok()
+    };
+
+    for embed in embeds.iter_mut() {
+        embed.description = embed.description.as_ref().map(|s| {
+            replace_links_with_markdown(&s)
+        });
+    }
+    // Attachments text
+    let mut attachments = msg_data.attachments.clone();
+
+    if settings.archive_attachments {
+        if let Some((store, archive)) = ArchiveConfig::from_ctx(ctx) {
+            let now = Date::now().as_millis() / 1000;
+            archive_attachments(&store, &archive, &mut attachments, now).await;
+        }
+    }
+
+    if attachments.len() > 0 {
+        let mut extras = 0;
+        let (images, files): (Vec<Attachment>, Vec<Attachment>) =
+            attachments.into_iter().partition(is_image);
+
+        // Discord folds embeds sharing the same url into a single image gallery
+        if images.len() > 0 {
+            if embeds.len() == 0 || embeds[0].url.is_some() || embeds[0].image.is_some() {
+                embeds.insert(0, EmbedBuilder::new().build());
+            }
+            embeds[0].url = Some(t_url.to_string());
+            embeds[0].image = Some(EmbedImage {
+                height: images[0].height,
+                proxy_url: Some(images[0].proxy_url.clone()),
+                url: images[0].url.clone(),
+                width: images[0].width,
+            });
+            extras = gallery_extras(
+                embeds.len(),
+                images.len(),
+                images.len() > 1 || !files.is_empty(),
+                !msg_data.sticker_items.is_empty(),
+            );
+            for (i, image) in images.iter().enumerate().take(1 + extras).skip(1) {
+                if let Ok(source) = ImageSource::url(&image.url) {
+                    embeds.insert(
+                        i,
+                        EmbedBuilder::new().url(t_url).image(source).build(),
+                    );
+                }
+            }
+        }
+
+        let fmt = images
+            .iter()
+            .skip(1 + extras)
+            .chain(files.iter())
+            .map(|a| describe_attachment(a, raw))
+            .collect::<Vec<String>>();
+
+        if !fmt.is_empty() {
+            if embeds.is_empty() {
+                embeds.push(EmbedBuilder::new().build());
+            }
+            let attachment_desc =
+                format!("\n**{}:**\n> {}", phrases.attachments, fmt.join("\n> "));
+
+            if can_add(&embeds[0], &attachment_desc) {
+                embeds[0].description = Some(format!(
+                    "{}{}",
+                    embeds[0].description.clone().unwrap_or_default(),
+                    attachment_desc
+                ));
+            } else if can_add(&embeds[embeds.len() - 1], &attachment_desc) {
+                let last = embeds.len() - 1;
+                embeds[last].description = Some(format!(
+                    "{}{}",
+                    embeds[last].description.clone().unwrap_or_default(),
+                    attachment_desc
+                ));
+            } else {
+                embeds.push(EmbedBuilder::new().description(attachment_desc).build());
+            }
+        }
+    }
+    // Stickers text
+    let mut rendered = Vec::new();
+    for sticker in msg_data.sticker_items.iter() {
+        rendered.push(render_sticker(client, sticker, guild).await);
+    }
+    if rendered.len() == 1 && rendered[0].image.is_some() {
+        let sticker = rendered.remove(0);
+        let mut embed = EmbedBuilder::new()
+            .image(ImageSource::url(sticker.image.unwrap()).unwrap());
+        if sticker.preview {
+            embed = embed.description(sticker.label);
+        }
+        embeds.push(embed.build());
+    } else if !rendered.is_empty() {
+        let desc = rendered
+            .iter()
+            .map(|s| s.label.clone())
+            .collect::<Vec<String>>()
+            .join("\n");
+        embeds.push(EmbedBuilder::new().description(desc).build());
+    }
+    if embeds.is_empty() {
+        embeds.push(EmbedBuilder::new().description(phrases.no_content).build());
+    }
+    // A message can carry up to 10 embeds of its own, one more for its content would be too many
+    embeds.truncate(MAX_EMBEDS);
+    // Overiding the footer(server) and author data
+    embeds[0].author = Some(author(&msg_data.author).build());
+    embeds[0].footer = Some(footer(guild));
+
+    embeds
+}
+
+// Field values are capped at 1024 characters and a whole message's embeds at 6000
+const FIELD_LIMIT: usize = 1024;
+const CONVERSATION_BUDGET: usize = 5000;
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        let mut truncated = text.chars().take(max.saturating_sub(1)).collect::<String>();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Renders several messages of one channel as a single embed with a field per message.
+pub(crate) fn conversation_embeds(messages: &[Message], guild: &Guild, t_url: &str) -> Vec<Embed> {
+    let budget = (CONVERSATION_BUDGET / messages.len().max(1)).min(FIELD_LIMIT);
+
+    let mut embed = EmbedBuilder::new()
+        .title(format!("Conversation ({} messages)", messages.len()))
+        .url(t_url);
+
+    for message in messages {
+        let mut value = replace_links_with_markdown(&message.content);
+        for attachment in &message.attachments {
+            value.push_str(&format!("\n> [{}]({})", attachment.filename, attachment.url));
+        }
+        for sticker in &message.sticker_items {
+            value.push_str(&format!("\n> [{}]({})", sticker.name, sticker_url(sticker)));
+        }
+        if value.trim().is_empty() {
+            value = "*No content*".to_string();
+        }
+        let value = format!("<t:{}:t> {}", message.timestamp.as_secs(), value);
+        embed = embed.field(EmbedFieldBuilder::new(
+            truncate(&message.author.name, 256),
+            truncate(&value, budget),
+        ));
+    }
+
+    let mut embed = embed.build();
+    embed.footer = Some(footer(guild));
+    vec![embed]
+}
+
+/// Renders a saved bookmark, picking the layout from the number of messages it holds.
+pub(crate) async fn bookmark_embeds(
+    ctx: &RouteContext<RouteData>,
+    client: &Client,
+    messages: &[Message],
+    guild: &Guild,
+    t_url: &str,
+    settings: &Settings,
+) -> Vec<Embed> {
+    if messages.len() == 1 {
+        message_embeds(ctx, client, &messages[0], None, guild, t_url, settings).await
+    } else {
+        conversation_embeds(messages, guild, t_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(filename: &str, content_type: Option<&str>) -> Attachment {
+        serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "filename": filename,
+            "content_type": content_type,
+            "size": 10,
+            "url": "https://cdn.discordapp.com/a",
+            "proxy_url": "https://media.discordapp.net/a",
+            "width": 640,
+            "height": 480,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn images_are_told_apart_from_videos() {
+        assert!(is_image(&attachment("a.mp4", Some("image/png"))));
+        assert!(!is_image(&attachment("a.png", Some("video/mp4"))));
+        assert!(is_image(&attachment("photo.JPG", None)));
+        assert!(!is_image(&attachment("clip.mp4", None)));
+        assert!(!is_image(&attachment("noextension", None)));
+    }
+
+    fn guild() -> Guild {
+        serde_json::from_value(serde_json::json!({
+            "id": "7",
+            "name": "Cats",
+            "owner_id": "2",
+            "afk_timeout": 300,
+            "default_message_notifications": 0,
+            "explicit_content_filter": 0,
+            "features": [],
+            "mfa_level": 0,
+            "nsfw_level": 0,
+            "preferred_locale": "en-US",
+            "premium_progress_bar_enabled": false,
+            "premium_tier": 0,
+            "system_channel_flags": 0,
+            "verification_level": 0,
+            "emojis": [],
+            "roles": [],
+        }))
+        .unwrap()
+    }
+
+    fn sticker(format_type: u8) -> MessageSticker {
+        serde_json::from_value(serde_json::json!({
+            "id": "42",
+            "name": "wave",
+            "format_type": format_type,
+        }))
+        .unwrap()
+    }
+
+    fn full_sticker(kind: u8, guild_id: Option<&str>, pack_id: Option<&str>) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "id": "42",
+            "name": "wave",
+            "tags": "wave",
+            "type": kind,
+            "format_type": 3,
+            "guild_id": guild_id,
+            "pack_id": pack_id,
+        }))
+        .unwrap()
+    }
+
+    fn render(responses: Vec<(u16, &'static str, Vec<u8>)>, format_type: u8) -> RenderedSticker {
+        let (base, _received) = crate::testing::serve(responses);
+        rest::set_api_base(base);
+        let rendered = crate::testing::block_on(render_sticker(&Client::new(), &sticker(format_type), &guild()));
+        rest::set_api_base(rest::DEFAULT_API_BASE.to_string());
+        rendered
+    }
+
+    #[test]
+    fn lottie_stickers_name_their_pack() {
+        let pack = serde_json::to_vec(&serde_json::json!({
+            "id": "9",
+            "name": "Wumpus Beyond",
+            "description": "",
+            "sku_id": "1",
+            "stickers": [],
+            "banner_asset_id": "5",
+        }))
+        .unwrap();
+        let lottie = render(
+            vec![
+                (200, "application/json", full_sticker(1, None, Some("9"))),
+                (200, "application/json", pack),
+            ],
+            3,
+        );
+        assert_eq!(
+            lottie.label,
+            "🎞️ [wave](https://media.discordapp.net/stickers/42.json) · animated sticker · Wumpus Beyond pack"
+        );
+        assert_eq!(
+            lottie.image.as_deref(),
+            Some("https://cdn.discordapp.com/app-assets/710982414301790216/store/5.png")
+        );
+        assert!(lottie.preview);
+    }
+
+    #[test]
+    fn guild_stickers_name_their_server() {
+        let own = render(vec![(200, "application/json", full_sticker(2, Some("7"), None))], 1);
+        assert_eq!(
+            own.label,
+            "[wave](https://media.discordapp.net/stickers/42.png) · sticker from Cats"
+        );
+        assert_eq!(own.image.as_deref(), Some("https://media.discordapp.net/stickers/42.png"));
+        assert!(!own.preview);
+
+        let other = render(vec![(200, "application/json", full_sticker(2, Some("8"), None))], 1);
+        assert!(other.label.ends_with(" · sticker from another server"));
+    }
+
+    #[test]
+    fn stickers_render_without_details() {
+        let lottie = render(vec![(404, "application/json", b"{}".to_vec())], 3);
+        assert_eq!(
+            lottie.label,
+            "🎞️ [wave](https://media.discordapp.net/stickers/42.json) · animated sticker"
+        );
+        assert!(lottie.image.is_none());
+    }
+
+    #[test]
+    fn gallery_leaves_room_for_other_embeds() {
+        assert_eq!(gallery_extras(1, 4, false, false), 3);
+        assert_eq!(gallery_extras(1, 2, false, false), 1);
+        assert_eq!(gallery_extras(1, 1, false, false), 0);
+        assert_eq!(gallery_extras(8, 4, true, false), 1);
+        assert_eq!(gallery_extras(8, 4, true, true), 0);
+        assert_eq!(gallery_extras(11, 4, false, false), 0);
+    }
+}
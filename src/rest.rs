@@ -0,0 +1,358 @@
+use std::cell::RefCell;
+
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Client, ClientBuilder, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use twilight_model::channel::message::sticker::{Sticker, StickerPack};
+use twilight_model::channel::{Channel, Message};
+use twilight_model::guild::Guild;
+use twilight_model::id::{
+    marker::{
+        ApplicationMarker, ChannelMarker, GuildMarker, MessageMarker, RoleMarker, StickerMarker,
+        StickerPackMarker, UserMarker,
+    },
+    Id,
+};
+use worker::{console_log, Env};
+
+use crate::error::InteractionError;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RestError {
+    #[error("Missing access")]
+    Forbidden,
+
+    #[error("Unknown resource")]
+    NotFound,
+
+    #[error("Discord responded with {0}")]
+    Status(StatusCode),
+
+    #[error("Request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl From<RestError> for InteractionError {
+    fn from(error: RestError) -> InteractionError {
+        InteractionError::UpstreamError(format!("Discord: {}", error))
+    }
+}
+
+/// A client authenticated as the bot, for code running outside an interaction.
+pub(crate) fn bot_client(env: &Env) -> Result<Client, InteractionError> {
+    let token = env.var("DISCORD_TOKEN")?.to_string();
+    let mut headers = HeaderMap::new();
+    headers.append(AUTHORIZATION, format!("Bot {}", token).parse().unwrap());
+    headers.append(CONTENT_TYPE, "application/json".parse().unwrap());
+    Ok(ClientBuilder::new().default_headers(headers).build()?)
+}
+
+// Where requests go, `DISCORD_API_BASE` can point it at a mock of the Discord API
+pub(crate) const DEFAULT_API_BASE: &str = "https://discord.com/api/v10";
+
+thread_local! {
+    static API_BASE: RefCell<String> = RefCell::new(DEFAULT_API_BASE.to_string());
+}
+
+/// The configured API base, without a trailing slash.
+pub(crate) fn configured_api_base(env: &Env) -> String {
+    env.var("DISCORD_API_BASE")
+        .map(|v| v.to_string())
+        .unwrap_or_else(|_| DEFAULT_API_BASE.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Points every request of this module at `DISCORD_API_BASE`, called as each event comes in.
+/// Variables are the same for every request an isolate serves.
+pub(crate) fn configure(env: &Env) {
+    set_api_base(configured_api_base(env));
+}
+
+pub(crate) fn set_api_base(base: String) {
+    API_BASE.with(|api_base| *api_base.borrow_mut() = base);
+}
+
+fn api_base() -> String {
+    API_BASE.with(|api_base| api_base.borrow().clone())
+}
+
+async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, RestError> {
+    let response = request.send().await?;
+    let status = response.status();
+    let text = response.text().await?;
+    match status {
+        s if s.is_success() => Ok(serde_json::from_str(&text)?),
+        StatusCode::FORBIDDEN => Err(RestError::Forbidden),
+        StatusCode::NOT_FOUND => Err(RestError::NotFound),
+        s => {
+            console_log!("[REST] {} response: {:?}", s, text);
+            Err(RestError::Status(s))
+        }
+    }
+}
+
+pub(crate) async fn open_dm(
+    client: &Client,
+    user_id: Id<UserMarker>,
+) -> Result<Id<ChannelMarker>, RestError> {
+    let body = serde_json::json!({ "recipient_id": user_id.to_string() });
+    let channel: serde_json::Value = send(
+        client
+            .post(format!("{}/users/@me/channels", api_base()))
+            .body(body.to_string()),
+    )
+    .await?;
+    Ok(serde_json::from_value(channel["id"].clone())?)
+}
+
+pub(crate) async fn create_message(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    body: &serde_json::Value,
+) -> Result<Message, RestError> {
+    send(
+        client
+            .post(format!(
+                "{}/channels/{}/messages",
+                api_base(), channel_id
+            ))
+            .body(body.to_string()),
+    )
+    .await
+}
+
+pub(crate) async fn edit_message(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+    body: &serde_json::Value,
+) -> Result<Message, RestError> {
+    send(
+        client
+            .patch(format!(
+                "{}/channels/{}/messages/{}",
+                api_base(), channel_id, message_id
+            ))
+            .body(body.to_string()),
+    )
+    .await
+}
+
+/// Posts to a webhook and returns the created message. Webhook URLs carry their own token, so
+/// this uses a bare client rather than one holding the bot token.
+pub(crate) async fn execute_webhook(url: &str, body: &serde_json::Value) -> Result<Message, RestError> {
+    send(
+        Client::new()
+            .post(format!("{}?wait=true", url))
+            .header("Content-Type", "application/json")
+            .body(body.to_string()),
+    )
+    .await
+}
+
+#[derive(Deserialize)]
+struct Webhook {
+    guild_id: Option<Id<GuildMarker>>,
+}
+
+/// The server a webhook posts in. Webhook URLs carry their own token, so this also uses a bare
+/// client.
+pub(crate) async fn get_webhook_guild(url: &str) -> Result<Option<Id<GuildMarker>>, RestError> {
+    let webhook: Webhook = send(Client::new().get(url)).await?;
+    Ok(webhook.guild_id)
+}
+
+/// Replaces the response to an interaction, i.e. after deferring it. Interaction tokens carry
+/// their own authorization, so this uses a bare client.
+pub(crate) async fn edit_original_response(
+    application_id: Id<ApplicationMarker>,
+    token: &str,
+    body: &serde_json::Value,
+) -> Result<Message, RestError> {
+    send(
+        Client::new()
+            .patch(format!(
+                "{}/webhooks/{}/{}/messages/@original",
+                api_base(), application_id, token
+            ))
+            .header("Content-Type", "application/json")
+            .body(body.to_string()),
+    )
+    .await
+}
+
+/// Sends another message in reply to an interaction.
+pub(crate) async fn create_followup(
+    application_id: Id<ApplicationMarker>,
+    token: &str,
+    body: &serde_json::Value,
+) -> Result<Message, RestError> {
+    send(
+        Client::new()
+            .post(format!("{}/webhooks/{}/{}", api_base(), application_id, token))
+            .header("Content-Type", "application/json")
+            .body(body.to_string()),
+    )
+    .await
+}
+
+pub(crate) async fn get_guild(client: &Client, guild_id: Id<GuildMarker>) -> Result<Guild, RestError> {
+    send(client.get(format!("{}/guilds/{}", api_base(), guild_id))).await
+}
+
+/// A sticker with the pack or server it comes from, which messages don't carry.
+pub(crate) async fn get_sticker(client: &Client, sticker_id: Id<StickerMarker>) -> Result<Sticker, RestError> {
+    send(client.get(format!("{}/stickers/{}", api_base(), sticker_id))).await
+}
+
+pub(crate) async fn get_sticker_pack(
+    client: &Client,
+    pack_id: Id<StickerPackMarker>,
+) -> Result<StickerPack, RestError> {
+    send(client.get(format!("{}/sticker-packs/{}", api_base(), pack_id))).await
+}
+
+#[derive(Deserialize)]
+struct MemberRoles {
+    roles: Vec<Id<RoleMarker>>,
+}
+
+/// The roles of a guild member, used when the interaction payload does not carry them.
+pub(crate) async fn get_member_roles(
+    client: &Client,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> Result<Vec<Id<RoleMarker>>, RestError> {
+    let member: MemberRoles = send(client.get(format!(
+        "{}/guilds/{}/members/{}",
+        api_base(), guild_id, user_id
+    )))
+    .await?;
+    Ok(member.roles)
+}
+
+/// Up to `limit` messages sent after `after` (exclusive), oldest first.
+pub(crate) async fn get_messages_after(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    after: u64,
+    limit: u8,
+) -> Result<Vec<Message>, RestError> {
+    let mut messages: Vec<Message> = send(client.get(format!(
+        "{}/channels/{}/messages?after={}&limit={}",
+        api_base(), channel_id, after, limit
+    )))
+    .await?;
+    messages.sort_by_key(|m| m.id);
+    Ok(messages)
+}
+
+/// The last `limit` messages of a channel, newest first.
+pub(crate) async fn get_recent_messages(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    limit: u8,
+) -> Result<Vec<Message>, RestError> {
+    send(client.get(format!(
+        "{}/channels/{}/messages?limit={}",
+        api_base(), channel_id, limit
+    )))
+    .await
+}
+
+pub(crate) async fn get_message(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+) -> Result<Message, RestError> {
+    send(client.get(format!(
+        "{}/channels/{}/messages/{}",
+        api_base(), channel_id, message_id
+    )))
+    .await
+}
+
+/// A message as Discord sent it, for the fields twilight-model drops, see `render::RawMessage`.
+pub(crate) async fn get_message_json(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+) -> Result<serde_json::Value, RestError> {
+    send(client.get(format!(
+        "{}/channels/{}/messages/{}",
+        api_base(), channel_id, message_id
+    )))
+    .await
+}
+
+/// The pinned messages of a channel, newest first, as Discord sent them.
+pub(crate) async fn get_pins(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+) -> Result<Vec<serde_json::Value>, RestError> {
+    send(client.get(format!("{}/channels/{}/pins", api_base(), channel_id))).await
+}
+
+pub(crate) async fn get_channel(client: &Client, channel_id: Id<ChannelMarker>) -> Result<Channel, RestError> {
+    send(client.get(format!("{}/channels/{}", api_base(), channel_id))).await
+}
+
+/// Whether `user_id` has joined the thread `channel_id`.
+pub(crate) async fn is_thread_member(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    user_id: Id<UserMarker>,
+) -> Result<bool, RestError> {
+    match send::<serde_json::Value>(client.get(format!(
+        "{}/channels/{}/thread-members/{}",
+        api_base(), channel_id, user_id
+    )))
+    .await
+    {
+        Ok(_) => Ok(true),
+        Err(RestError::NotFound) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+pub(crate) async fn delete_message(
+    client: &Client,
+    channel_id: Id<ChannelMarker>,
+    message_id: Id<MessageMarker>,
+) -> Result<(), RestError> {
+    let response = client
+        .delete(format!(
+            "{}/channels/{}/messages/{}",
+            api_base(), channel_id, message_id
+        ))
+        .send()
+        .await?;
+    match response.status() {
+        s if s.is_success() => Ok(()),
+        StatusCode::FORBIDDEN => Err(RestError::Forbidden),
+        StatusCode::NOT_FOUND => Err(RestError::NotFound),
+        s => Err(RestError::Status(s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{block_on, serve};
+
+    #[test]
+    fn requests_go_to_the_configured_api_base() {
+        let (base, received) = serve(vec![(404, "application/json", b"{}".to_vec())]);
+        set_api_base(format!("{}/api/v10", base));
+        let result = block_on(get_channel(&Client::new(), Id::new(5)));
+        set_api_base(DEFAULT_API_BASE.to_string());
+
+        assert!(matches!(result, Err(RestError::NotFound)));
+        assert_eq!(received.recv().unwrap().path, "/api/v10/channels/5");
+    }
+}
@@ -0,0 +1,51 @@
+use worker::{console_log, Date, Env, ScheduledEvent};
+
+use crate::digest;
+use crate::events::retry_due;
+use crate::store::BookmarkStore;
+
+// The daily and weekly triggers in wrangler.toml, the other one runs every few minutes
+const DAILY: &str = "0 3 * * *";
+const WEEKLY: &str = "0 9 * * 1";
+
+/// Entry point for the cron triggers in wrangler.toml.
+pub(crate) async fn run(event: ScheduledEvent, env: Env) {
+    let store = match BookmarkStore::from_env(&env) {
+        Ok(store) => store,
+        Err(err) => {
+            console_log!("[SCHEDULED] no bookmark store: {}", err);
+            return;
+        }
+    };
+
+    match retry_due(&store, Date::now().as_millis() / 1000).await {
+        Ok(0) => {}
+        Ok(retried) => console_log!("[SCHEDULED] retried {} webhook deliveries", retried),
+        Err(err) => console_log!("[SCHEDULED] retrying webhook deliveries failed: {}", err),
+    }
+
+    // The weekly trigger only starts the digest, the frequent one sends it a batch at a time
+    if event.cron() == WEEKLY {
+        if let Err(err) = digest::start(&store).await {
+            console_log!("[SCHEDULED] starting the digest failed: {}", err);
+        }
+    } else {
+        match digest::run_batch(&store, &env, Date::now().as_millis()).await {
+            Ok(0) => {}
+            Ok(sent) => console_log!("[SCHEDULED] sent {} digests", sent),
+            Err(err) => console_log!("[SCHEDULED] sending digests failed: {}", err),
+        }
+    }
+
+    // Like the digest, the daily trigger starts the purge and every invocation does a batch
+    if event.cron() == DAILY {
+        if let Err(err) = store.start_purge().await {
+            console_log!("[SCHEDULED] starting the trash purge failed: {}", err);
+        }
+    }
+    match store.purge_trash_batch(Date::now().as_millis()).await {
+        Ok(0) => {}
+        Ok(purged) => console_log!("[SCHEDULED] purged {} bookmarks from the trash", purged),
+        Err(err) => console_log!("[SCHEDULED] purging the trash failed: {}", err),
+    }
+}
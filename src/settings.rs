@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, UserMarker},
+    Id,
+};
+use worker::{console_log, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+use crate::events::Endpoint;
+use crate::store::BookmarkStore;
+
+/// Most colors a user can keep in their palette, a select menu holds 25 options in total.
+pub(crate) const MAX_PALETTE: usize = 10;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct PaletteColor {
+    pub(crate) name: String,
+    pub(crate) color: u32,
+}
+
+/// Where new bookmarks are sent, DMs are used whenever the other targets fail.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub(crate) enum Delivery {
+    #[default]
+    Dm,
+    Channel {
+        channel_id: Id<ChannelMarker>,
+        /// The channel's server, for links to bookmarks posted there
+        #[serde(default)]
+        guild_id: Option<Id<GuildMarker>>,
+    },
+    /// A Discord webhook URL, which holds its token so it is never shown back in full
+    Webhook {
+        url: String,
+        #[serde(default)]
+        guild_id: Option<Id<GuildMarker>>,
+    },
+}
+
+impl Delivery {
+    /// The server bookmarks are posted in, `None` for DMs and targets set up before it was kept.
+    pub(crate) fn guild_id(&self) -> Option<Id<GuildMarker>> {
+        match self {
+            Delivery::Dm => None,
+            Delivery::Channel { guild_id, .. } | Delivery::Webhook { guild_id, .. } => *guild_id,
+        }
+    }
+}
+
+const WEBHOOK_HOSTS: [&str; 4] = [
+    "https://discord.com/api/webhooks/",
+    "https://ptb.discord.com/api/webhooks/",
+    "https://canary.discord.com/api/webhooks/",
+    "https://discordapp.com/api/webhooks/",
+];
+
+/// Accepts Discord webhook URLs only, bookmarks must not be posted to arbitrary hosts.
+pub(crate) fn parse_webhook_url(input: &str) -> Option<String> {
+    let url = input.trim();
+    let path = WEBHOOK_HOSTS.iter().find_map(|host| url.strip_prefix(host))?;
+    let (id, token) = path.split_once('/')?;
+    let valid_id = !id.is_empty() && id.chars().all(|c| c.is_ascii_digit());
+    let valid_token = !token.is_empty()
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    (valid_id && valid_token).then(|| format!("https://discord.com/api/webhooks/{}/{}", id, token))
+}
+
+/// Languages offered in the settings panel, as Discord locale codes.
+pub(crate) const LOCALES: [(&str, &str); 6] = [
+    ("en-US", "English"),
+    ("de", "Deutsch"),
+    ("es-ES", "Español"),
+    ("fr", "Français"),
+    ("pt-BR", "Português do Brasil"),
+    ("ja", "日本語"),
+];
+
+/// The text bookmarks are rendered with besides the message itself.
+pub(crate) struct Phrases {
+    pub(crate) no_content: &'static str,
+    pub(crate) attachments: &'static str,
+}
+
+/// `Phrases` for each of `LOCALES`, in the same order.
+const PHRASES: [Phrases; 6] = [
+    Phrases {
+        no_content: "*No content*",
+        attachments: "Attachments",
+    },
+    Phrases {
+        no_content: "*Kein Inhalt*",
+        attachments: "Anhänge",
+    },
+    Phrases {
+        no_content: "*Sin contenido*",
+        attachments: "Archivos adjuntos",
+    },
+    Phrases {
+        no_content: "*Aucun contenu*",
+        attachments: "Pièces jointes",
+    },
+    Phrases {
+        no_content: "*Sem conteúdo*",
+        attachments: "Anexos",
+    },
+    Phrases {
+        no_content: "*内容なし*",
+        attachments: "添付ファイル",
+    },
+];
+
+fn enabled() -> bool {
+    true
+}
+
+/// Per-user preferences, stored as one KV record. Every field has a serde default so records
+/// written by older versions keep loading.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct Settings {
+    /// Applied to every new bookmark when set
+    #[serde(default)]
+    pub(crate) default_color: Option<u32>,
+    #[serde(default)]
+    pub(crate) palette: Vec<PaletteColor>,
+    /// Quote the message being replied to above the bookmarked one
+    #[serde(default = "enabled")]
+    pub(crate) reply_context: bool,
+    /// Copy attachments to the media bucket, when the deployment has one
+    #[serde(default = "enabled")]
+    pub(crate) archive_attachments: bool,
+    /// A `UTC±HH:MM` offset reminders are scheduled in, UTC when unset
+    #[serde(default)]
+    pub(crate) timezone: Option<String>,
+    #[serde(default)]
+    pub(crate) delivery: Delivery,
+    /// Discord locale code bookmarks are rendered in, English when unset
+    #[serde(default)]
+    pub(crate) locale: Option<String>,
+    /// Receives signed events about this user's bookmarks
+    #[serde(default)]
+    pub(crate) endpoint: Option<Endpoint>,
+    /// Hash of the token for the feed and JSON API, see `feed`
+    #[serde(default)]
+    pub(crate) feed_token: Option<String>,
+    /// Opted in to the weekly digest of unread bookmarks
+    #[serde(default)]
+    pub(crate) digest: bool,
+    /// When the last digest was sent, in ms
+    #[serde(default)]
+    pub(crate) last_digest: Option<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            default_color: None,
+            palette: Vec::new(),
+            reply_context: true,
+            archive_attachments: true,
+            timezone: None,
+            delivery: Delivery::Dm,
+            locale: None,
+            endpoint: None,
+            feed_token: None,
+            digest: false,
+            last_digest: None,
+        }
+    }
+}
+
+impl Settings {
+    /// The rendering text in the user's language.
+    pub(crate) fn phrases(&self) -> &'static Phrases {
+        let index = self
+            .locale
+            .as_deref()
+            .and_then(|code| LOCALES.iter().position(|(c, _)| *c == code))
+            .unwrap_or(0);
+        &PHRASES[index]
+    }
+
+    fn key(user_id: Id<UserMarker>) -> String {
+        format!("settings:{}", user_id)
+    }
+
+    pub(crate) async fn load(
+        store: &BookmarkStore,
+        user_id: Id<UserMarker>,
+    ) -> Result<Settings, InteractionError> {
+        Ok(store
+            .get_value(&Self::key(user_id))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Settings for rendering and delivery, which fall back to the defaults rather than failing a
+    /// bookmark when KV is unavailable.
+    pub(crate) async fn for_user(ctx: &RouteContext<RouteData>, user_id: Id<UserMarker>) -> Settings {
+        let store = match BookmarkStore::new(ctx) {
+            Ok(store) => store,
+            Err(_) => return Settings::default(),
+        };
+        match Settings::load(&store, user_id).await {
+            Ok(settings) => settings,
+            Err(err) => {
+                console_log!("[SETTINGS] loading failed: {}", err);
+                Settings::default()
+            }
+        }
+    }
+
+    /// Minutes east of UTC for `timezone`.
+    pub(crate) fn utc_offset_minutes(&self) -> i32 {
+        self.timezone.as_deref().and_then(parse_utc_offset).unwrap_or(0)
+    }
+
+    pub(crate) async fn save(
+        &self,
+        store: &BookmarkStore,
+        user_id: Id<UserMarker>,
+    ) -> Result<(), InteractionError> {
+        store.put_value(&Self::key(user_id), self, None).await
+    }
+
+    /// Adds `color` to the palette, replacing an entry with the same name and dropping the
+    /// oldest one when the palette is full.
+    pub(crate) fn save_color(&mut self, name: String, color: u32) {
+        self.palette.retain(|c| !c.name.eq_ignore_ascii_case(&name));
+        if self.palette.len() >= MAX_PALETTE {
+            self.palette.remove(0);
+        }
+        self.palette.push(PaletteColor { name, color });
+    }
+}
+
+/// Parses `#5865F2`, `5865f2`, `0x5865F2` or the short `#58F` form.
+pub(crate) fn parse_hex(input: &str) -> Option<u32> {
+    let hex = input.trim();
+    let hex = hex
+        .strip_prefix('#')
+        .or_else(|| hex.strip_prefix("0x"))
+        .unwrap_or(hex);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        3 => {
+            let long: String = hex.chars().flat_map(|c| [c, c]).collect();
+            u32::from_str_radix(&long, 16).ok()
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn format_hex(color: u32) -> String {
+    format!("#{:06X}", color)
+}
+
+/// Parses `UTC`, `UTC+2`, `UTC-05:30` or `+0530` into minutes east of UTC.
+pub(crate) fn parse_utc_offset(input: &str) -> Option<i32> {
+    let trimmed = input.trim();
+    let offset = trimmed
+        .strip_prefix("UTC")
+        .or_else(|| trimmed.strip_prefix("GMT"))
+        .unwrap_or(trimmed);
+    if offset.is_empty() {
+        return Some(0);
+    }
+    let (sign, rest) = if let Some(rest) = offset.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = offset.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        return None;
+    };
+    if !rest.is_ascii() {
+        return None;
+    }
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Formats minutes east of UTC the way `parse_utc_offset` reads them back.
+pub(crate) fn format_utc_offset(minutes: i32) -> String {
+    if minutes == 0 {
+        return "UTC".to_string();
+    }
+    let sign = if minutes < 0 { '-' } else { '+' };
+    let minutes = minutes.abs();
+    format!("UTC{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+}
@@ -0,0 +1,757 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use twilight_model::channel::Message;
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
+    Id,
+};
+use wasm_bindgen::JsValue;
+use worker::kv::KvStore;
+use worker::{Date, Env, Method, ObjectNamespace, Request, RequestInit, RouteContext};
+
+use crate::RouteData;
+use crate::error::InteractionError;
+use crate::render::jump_url;
+
+// The KV namespace binding holding bookmarks, see wrangler.toml
+pub(crate) const NAMESPACE: &str = "BOOKMARKS";
+
+// The optional Durable Object binding keeping each user's index, see `user_index`
+const INDEX_BINDING: &str = "USER_BOOKMARKS";
+
+// The optional Durable Object binding keeping each server's board, see `guild_state`
+const GUILD_BINDING: &str = "GUILD_STATE";
+
+// How long deleted bookmarks stay in the trash, in milliseconds
+pub(crate) const TRASH_RETENTION: u64 = 30 * 24 * 60 * 60 * 1000;
+
+// Most bookmarks one user can keep, trashed ones don't count
+pub(crate) const MAX_BOOKMARKS: usize = 1000;
+
+// Users listed at a time by the trash purge, like the digest a run spans several invocations
+const PURGE_BATCH: u64 = 50;
+
+// KV reads, writes and index changes one cron invocation spends on the purge, it shares the
+// invocation with the digest
+const PURGE_BUDGET: usize = 300;
+
+// What purging one bookmark costs: reading it, deleting it and removing it from the index
+const PURGE_OPS: usize = 3;
+
+const PURGE_RUN_KEY: &str = "purge-run";
+const TRASH_INDEX_PREFIX: &str = "trash-index:";
+
+// The first second of 2015, where Discord snowflakes count from
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+// Bookmarks saved before the unread index existed that each inbox checks, each one is a KV read
+const UNREAD_BACKFILL: usize = 100;
+
+/// A daily purge in progress, continued by every cron invocation until all users are done.
+#[derive(Deserialize, Serialize)]
+struct PurgeRun {
+    cursor: Option<String>,
+    /// Users of the last listed page not done yet, the first one may be partly purged
+    #[serde(default)]
+    pending: Vec<Id<UserMarker>>,
+    /// Whether the last page has been listed, the run ends once `pending` is empty
+    #[serde(default)]
+    listed: bool,
+}
+
+/// How far adding bookmarks saved before the unread index existed to it has got.
+#[derive(Default, Deserialize, Serialize)]
+struct UnreadBackfill {
+    after: Option<Id<MessageMarker>>,
+    done: bool,
+}
+
+/// A bookmark as saved in KV, keyed by the DM message it was delivered as.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct StoredBookmark {
+    pub(crate) user_id: Id<UserMarker>,
+    pub(crate) guild_id: Option<Id<GuildMarker>>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    // A single message, or every message of a bookmarked conversation in order
+    pub(crate) messages: Vec<Message>,
+    /// The server of the channel or webhook the bookmark was delivered to, `None` for DMs
+    #[serde(default)]
+    pub(crate) dm_guild_id: Option<Id<GuildMarker>>,
+    pub(crate) dm_channel_id: Id<ChannelMarker>,
+    pub(crate) dm_message_id: Id<MessageMarker>,
+    pub(crate) created_at: u64,
+    // What the last refresh found out about the original messages
+    #[serde(default)]
+    pub(crate) status: SourceStatus,
+    /// Set from the dashboard
+    #[serde(default)]
+    pub(crate) note: Option<String>,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+    /// Unix time in ms, shown in the calendar feed
+    #[serde(default)]
+    pub(crate) remind_at: Option<u64>,
+    #[serde(default)]
+    pub(crate) read_state: ReadState,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SourceStatus {
+    #[default]
+    Unchanged,
+    Edited,
+    Deleted,
+}
+
+/// Where a bookmark is in the user's read-later queue.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReadState {
+    #[default]
+    Unread,
+    Read,
+    Archived,
+}
+
+impl ReadState {
+    /// Shown in front of the footer of the bookmark message.
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            ReadState::Unread => "📬 Unread",
+            ReadState::Read => "✅ Read",
+            ReadState::Archived => "📦 Archived",
+        }
+    }
+}
+
+/// A deleted bookmark, restorable until the scheduled purge removes it.
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct TrashedBookmark {
+    pub(crate) bookmark: StoredBookmark,
+    pub(crate) deleted_at: u64,
+}
+
+impl StoredBookmark {
+    /// A one line description for autocomplete choices and lists, at most 100 characters.
+    pub(crate) fn summary(&self) -> String {
+        let first = &self.messages[0];
+        let mut text = format!("{}: {}", first.author.name, first.content.replace('\n', " "));
+        if self.messages.len() > 1 {
+            text = format!("[{} messages] {}", self.messages.len(), text);
+        }
+        if text.chars().count() > 100 {
+            text = text.chars().take(99).collect::<String>() + "…";
+        }
+        text
+    }
+
+    pub(crate) fn jump_url(&self) -> String {
+        jump_url(self.guild_id, self.channel_id, self.messages[0].id)
+    }
+
+    /// A link to the bookmark message itself, wherever it was delivered.
+    pub(crate) fn delivered_url(&self) -> String {
+        jump_url(self.dm_guild_id, self.dm_channel_id, self.dm_message_id)
+    }
+}
+
+/// The ordered lists of ids kept for each user.
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+pub(crate) enum IndexList {
+    #[default]
+    Bookmarks,
+    // Oldest deletion first
+    Trash,
+    // Bookmarks that have had a reminder set, for the calendar feed
+    Reminders,
+    // Bookmarks still unread, for the inbox
+    Unread,
+}
+
+impl IndexList {
+    /// Where the Durable Object keeps the list.
+    pub(crate) fn storage_key(self) -> &'static str {
+        match self {
+            IndexList::Bookmarks => "index",
+            IndexList::Trash => "trash",
+            IndexList::Reminders => "reminders",
+            IndexList::Unread => "unread",
+        }
+    }
+}
+
+/// A change to a user's index, the ordered list of their bookmark ids.
+#[derive(Deserialize, Serialize)]
+pub(crate) enum IndexOp {
+    List,
+    Add(Id<MessageMarker>),
+    AddAll(Vec<Id<MessageMarker>>),
+    Remove(Id<MessageMarker>),
+    // Puts the sent bookmark in the place reserved for it, see `BookmarkStore::reserve`
+    Replace {
+        reserved: Id<MessageMarker>,
+        id: Id<MessageMarker>,
+    },
+}
+
+/// A bookmark refused because the user already has `MAX_BOOKMARKS`.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct IndexFull;
+
+impl IndexOp {
+    /// Applies the change to `list`, returning whether it changed. The quota is checked here so
+    /// that, behind the Durable Object, bookmarks saved at the same time can't go past it.
+    pub(crate) fn apply(
+        &self,
+        list: IndexList,
+        index: &mut Vec<Id<MessageMarker>>,
+    ) -> Result<bool, IndexFull> {
+        Ok(match self {
+            IndexOp::List => false,
+            IndexOp::Add(id) if !index.contains(id) => {
+                if matches!(list, IndexList::Bookmarks) && index.len() >= MAX_BOOKMARKS {
+                    return Err(IndexFull);
+                }
+                index.push(*id);
+                true
+            }
+            IndexOp::Add(_) => false,
+            IndexOp::AddAll(ids) => {
+                let new: Vec<_> = ids.iter().filter(|id| !index.contains(id)).copied().collect();
+                if matches!(list, IndexList::Bookmarks) && index.len() + new.len() > MAX_BOOKMARKS {
+                    return Err(IndexFull);
+                }
+                index.extend(&new);
+                !new.is_empty()
+            }
+            IndexOp::Remove(id) => {
+                let len = index.len();
+                index.retain(|i| i != id);
+                index.len() != len
+            }
+            IndexOp::Replace { reserved, id } => match index.iter().position(|i| i == reserved) {
+                Some(i) => {
+                    index[i] = *id;
+                    true
+                }
+                // The reservation failed or was lost, the quota is checked again
+                None => return IndexOp::Add(*id).apply(list, index),
+            },
+        })
+    }
+}
+
+/// What the worker sends the user's Durable Object.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct IndexRequest {
+    pub(crate) user_id: Id<UserMarker>,
+    #[serde(default)]
+    pub(crate) list: IndexList,
+    pub(crate) op: IndexOp,
+}
+
+/// What the worker sends the user's Durable Object.
+#[derive(Deserialize, Serialize)]
+pub(crate) enum UserRequest {
+    Index(IndexRequest),
+    // Takes a token from the user's rate limit bucket, see `limits`
+    TakeToken { now: u64 },
+}
+
+pub(crate) struct BookmarkStore {
+    kv: KvStore,
+    // Strongly consistent indexes when the Durable Object is bound, KV is used otherwise
+    index: Option<ObjectNamespace>,
+    guilds: Option<ObjectNamespace>,
+}
+
+/// Sends `body` to the Durable Object named `name` and reads its JSON response.
+pub(crate) async fn call_object<T: DeserializeOwned>(
+    namespace: &ObjectNamespace,
+    name: &str,
+    body: &impl Serialize,
+) -> Result<T, InteractionError> {
+    let stub = namespace.id_from_name(name)?.get_stub()?;
+    let body = serde_json::to_string(body)?;
+    let mut init = RequestInit::new();
+    init.with_method(Method::Post)
+        .with_body(Some(JsValue::from_str(&body)));
+    let request = Request::new_with_init("https://durable-object/", &init)?;
+    let mut response = stub.fetch_with_request(request).await?;
+    if response.status_code() != 200 {
+        return Err(InteractionError::WorkerError(format!(
+            "Durable Object responded with {}",
+            response.status_code()
+        )));
+    }
+    Ok(response.json().await?)
+}
+
+impl BookmarkStore {
+    pub(crate) fn new(ctx: &RouteContext<RouteData>) -> Result<BookmarkStore, InteractionError> {
+        Self::from_env(&ctx.env)
+    }
+
+    pub(crate) fn from_env(env: &Env) -> Result<BookmarkStore, InteractionError> {
+        let kv = env
+            .kv(NAMESPACE)
+            .map_err(|_| InteractionError::WorkerError("Bind to kv".into()))?;
+        let index = env.durable_object(INDEX_BINDING).ok();
+        let guilds = env.durable_object(GUILD_BINDING).ok();
+        Ok(BookmarkStore { kv, index, guilds })
+    }
+
+    /// A store that keeps indexes and boards in KV only, for the Durable Objects themselves.
+    pub(crate) fn kv_only(env: &Env) -> Result<BookmarkStore, InteractionError> {
+        let kv = env
+            .kv(NAMESPACE)
+            .map_err(|_| InteractionError::WorkerError("Bind to kv".into()))?;
+        Ok(BookmarkStore {
+            kv,
+            index: None,
+            guilds: None,
+        })
+    }
+
+    /// The users' Durable Objects, when bound.
+    pub(crate) fn user_index(&self) -> Option<&ObjectNamespace> {
+        self.index.as_ref()
+    }
+
+    /// The servers' Durable Objects, when bound.
+    pub(crate) fn guild_state(&self) -> Option<&ObjectNamespace> {
+        self.guilds.as_ref()
+    }
+
+    pub(crate) async fn get_value<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<T>, InteractionError> {
+        Ok(self.kv.get(key).json().await?)
+    }
+
+    /// Stores `value` under `key`, letting KV expire it after `ttl` seconds when given.
+    pub(crate) async fn put_value<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<u64>,
+    ) -> Result<(), InteractionError> {
+        let mut put = self.kv.put(key, value)?;
+        if let Some(ttl) = ttl {
+            put = put.expiration_ttl(ttl);
+        }
+        put.execute().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete_value(&self, key: &str) -> Result<(), InteractionError> {
+        Ok(self.kv.delete(key).await?)
+    }
+
+    /// Up to `limit` keys starting with `prefix` from `cursor` on, with the cursor of the next page.
+    pub(crate) async fn key_page(
+        &self,
+        prefix: &str,
+        cursor: Option<String>,
+        limit: u64,
+    ) -> Result<(Vec<String>, Option<String>), InteractionError> {
+        let mut list = self.kv.list().prefix(prefix.to_string()).limit(limit);
+        if let Some(cursor) = cursor {
+            list = list.cursor(cursor);
+        }
+        let page = list.execute().await?;
+        let next = if page.list_complete { None } else { page.cursor };
+        Ok((page.keys.into_iter().map(|key| key.name).collect(), next))
+    }
+
+    fn bookmark_key(user_id: Id<UserMarker>, dm_message_id: Id<MessageMarker>) -> String {
+        format!("bookmark:{}:{}", user_id, dm_message_id)
+    }
+
+    fn index_key(user_id: Id<UserMarker>, list: IndexList) -> String {
+        match list {
+            IndexList::Bookmarks => format!("index:{}", user_id),
+            IndexList::Trash => format!("{}{}", TRASH_INDEX_PREFIX, user_id),
+            IndexList::Reminders => format!("reminder-index:{}", user_id),
+            IndexList::Unread => format!("unread-index:{}", user_id),
+        }
+    }
+
+    // The bookmark of a single message, for spotting duplicates
+    fn source_key(user_id: Id<UserMarker>, message_id: Id<MessageMarker>) -> String {
+        format!("source:{}:{}", user_id, message_id)
+    }
+
+    fn trash_key(user_id: Id<UserMarker>, dm_message_id: Id<MessageMarker>) -> String {
+        format!("trash:{}:{}", user_id, dm_message_id)
+    }
+
+    pub(crate) async fn get(
+        &self,
+        user_id: Id<UserMarker>,
+        dm_message_id: Id<MessageMarker>,
+    ) -> Result<Option<StoredBookmark>, InteractionError> {
+        self.get_value(&Self::bookmark_key(user_id, dm_message_id))
+            .await
+    }
+
+    /// The user's bookmark of the single message `message_id`, if they have one.
+    pub(crate) async fn find_by_source(
+        &self,
+        user_id: Id<UserMarker>,
+        message_id: Id<MessageMarker>,
+    ) -> Result<Option<StoredBookmark>, InteractionError> {
+        let Some(dm_message_id) = self
+            .get_value::<Id<MessageMarker>>(&Self::source_key(user_id, message_id))
+            .await?
+        else {
+            return Ok(None);
+        };
+        self.get(user_id, dm_message_id).await
+    }
+
+    /// Ids of the user's bookmarks, oldest first.
+    pub(crate) async fn list(
+        &self,
+        user_id: Id<UserMarker>,
+    ) -> Result<Vec<Id<MessageMarker>>, InteractionError> {
+        self.update_index(user_id, IndexList::Bookmarks, IndexOp::List).await
+    }
+
+    /// The user's index as kept in KV, which the Durable Object starts from and writes through to.
+    pub(crate) async fn kv_index(
+        &self,
+        user_id: Id<UserMarker>,
+        list: IndexList,
+    ) -> Result<Vec<Id<MessageMarker>>, InteractionError> {
+        Ok(self
+            .get_value(&Self::index_key(user_id, list))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Stores the user's index in KV, empty ones are removed so the purge does not visit them.
+    pub(crate) async fn put_kv_index(
+        &self,
+        user_id: Id<UserMarker>,
+        list: IndexList,
+        index: &[Id<MessageMarker>],
+    ) -> Result<(), InteractionError> {
+        let key = Self::index_key(user_id, list);
+        if index.is_empty() {
+            self.delete_value(&key).await
+        } else {
+            self.put_value(&key, &index, None).await
+        }
+    }
+
+    // Applies `op` to the user's index and returns it. Through the Durable Object the read and
+    // write happen in one place, in KV two requests can race and lose an update or both get
+    // under the quota
+    async fn update_index(
+        &self,
+        user_id: Id<UserMarker>,
+        list: IndexList,
+        op: IndexOp,
+    ) -> Result<Vec<Id<MessageMarker>>, InteractionError> {
+        let Some(namespace) = &self.index else {
+            let mut index = self.kv_index(user_id, list).await?;
+            if op.apply(list, &mut index)? {
+                self.put_kv_index(user_id, list, &index).await?;
+            }
+            return Ok(index);
+        };
+        let request = UserRequest::Index(IndexRequest { user_id, list, op });
+        Ok(call_object::<Result<_, IndexFull>>(namespace, &user_id.to_string(), &request).await??)
+    }
+
+    /// Stores the bookmark, failing with `IndexFull` when it is new and the user has no room left.
+    pub(crate) async fn put(&self, bookmark: &StoredBookmark) -> Result<(), InteractionError> {
+        // Indexed first, so a refused bookmark leaves nothing behind
+        self.update_index(bookmark.user_id, IndexList::Bookmarks, IndexOp::Add(bookmark.dm_message_id))
+            .await?;
+        self.kv
+            .put(
+                &Self::bookmark_key(bookmark.user_id, bookmark.dm_message_id),
+                bookmark,
+            )?
+            .execute()
+            .await?;
+
+        if let [message] = bookmark.messages.as_slice() {
+            self.put_value(
+                &Self::source_key(bookmark.user_id, message.id),
+                &bookmark.dm_message_id,
+                None,
+            )
+            .await?;
+        }
+
+        if bookmark.remind_at.is_some() {
+            self.update_index(bookmark.user_id, IndexList::Reminders, IndexOp::Add(bookmark.dm_message_id))
+                .await?;
+        }
+
+        let op = if bookmark.read_state == ReadState::Unread {
+            IndexOp::Add(bookmark.dm_message_id)
+        } else {
+            IndexOp::Remove(bookmark.dm_message_id)
+        };
+        self.update_index(bookmark.user_id, IndexList::Unread, op).await?;
+        Ok(())
+    }
+
+    /// Ids of the user's unread bookmarks, oldest first, and whether bookmarks saved before the
+    /// unread index existed have all been added to it. Each call adds up to `UNREAD_BACKFILL` of them.
+    pub(crate) async fn list_unread(
+        &self,
+        user_id: Id<UserMarker>,
+    ) -> Result<(Vec<Id<MessageMarker>>, bool), InteractionError> {
+        let key = format!("unread-backfill:{}", user_id);
+        let mut backfill: UnreadBackfill = self.get_value(&key).await?.unwrap_or_default();
+        if !backfill.done {
+            let ids = self.list(user_id).await?;
+            let pending: Vec<_> = ids.into_iter().filter(|id| Some(*id) > backfill.after).collect();
+            let batch = &pending[..pending.len().min(UNREAD_BACKFILL)];
+            let mut unread = Vec::new();
+            for id in batch {
+                if let Some(bookmark) = self.get(user_id, *id).await? {
+                    if bookmark.read_state == ReadState::Unread {
+                        unread.push(*id);
+                    }
+                }
+            }
+            if !unread.is_empty() {
+                self.update_index(user_id, IndexList::Unread, IndexOp::AddAll(unread))
+                    .await?;
+            }
+            backfill.after = batch.last().copied().or(backfill.after);
+            backfill.done = pending.len() <= UNREAD_BACKFILL;
+            self.put_value(&key, &backfill, None).await?;
+        }
+
+        // Backfilled bookmarks land after newer ones, snowflakes put them back in order
+        let mut ids = self.update_index(user_id, IndexList::Unread, IndexOp::List).await?;
+        ids.sort();
+        Ok((ids, backfill.done))
+    }
+
+    /// Drops a bookmark that is no longer unread from the unread index.
+    pub(crate) async fn forget_unread(
+        &self,
+        user_id: Id<UserMarker>,
+        dm_message_id: Id<MessageMarker>,
+    ) -> Result<(), InteractionError> {
+        self.update_index(user_id, IndexList::Unread, IndexOp::Remove(dm_message_id))
+            .await?;
+        Ok(())
+    }
+
+    /// Holds a place in the user's index for a bookmark about to be sent, failing with `IndexFull`
+    /// when they have no room left. The bookmark's id is only known once it is sent, until then
+    /// the place is held by the returned id, a snowflake of the current time with random low bits
+    /// that no bookmark in the index has.
+    pub(crate) async fn reserve(&self, user_id: Id<UserMarker>) -> Result<Id<MessageMarker>, InteractionError> {
+        let mut random = [0u8; 4];
+        getrandom::getrandom(&mut random).expect("crypto.getRandomValues is available in workers");
+        let timestamp = Date::now().as_millis() - DISCORD_EPOCH;
+        let reserved = Id::new(timestamp << 22 | u64::from(u32::from_le_bytes(random) & 0x3f_ffff));
+        self.update_index(user_id, IndexList::Bookmarks, IndexOp::Add(reserved))
+            .await?;
+        Ok(reserved)
+    }
+
+    /// Puts the bookmark sent in the place held by `reserve`, to be followed by `put`.
+    pub(crate) async fn claim(
+        &self,
+        user_id: Id<UserMarker>,
+        reserved: Id<MessageMarker>,
+        dm_message_id: Id<MessageMarker>,
+    ) -> Result<(), InteractionError> {
+        self.update_index(user_id, IndexList::Bookmarks, IndexOp::Replace { reserved, id: dm_message_id })
+            .await?;
+        Ok(())
+    }
+
+    /// Gives up the place held by `reserve`, for a bookmark that could not be sent.
+    pub(crate) async fn release(
+        &self,
+        user_id: Id<UserMarker>,
+        reserved: Id<MessageMarker>,
+    ) -> Result<(), InteractionError> {
+        self.update_index(user_id, IndexList::Bookmarks, IndexOp::Remove(reserved))
+            .await?;
+        Ok(())
+    }
+
+    /// Ids of the user's bookmarks that have had a reminder set, some may have been cleared since.
+    pub(crate) async fn list_reminders(
+        &self,
+        user_id: Id<UserMarker>,
+    ) -> Result<Vec<Id<MessageMarker>>, InteractionError> {
+        self.update_index(user_id, IndexList::Reminders, IndexOp::List).await
+    }
+
+    /// Drops a bookmark whose reminder was cleared or is long past from the reminder index.
+    pub(crate) async fn forget_reminder(
+        &self,
+        user_id: Id<UserMarker>,
+        dm_message_id: Id<MessageMarker>,
+    ) -> Result<(), InteractionError> {
+        self.update_index(user_id, IndexList::Reminders, IndexOp::Remove(dm_message_id))
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete(
+        &self,
+        user_id: Id<UserMarker>,
+        dm_message_id: Id<MessageMarker>,
+    ) -> Result<(), InteractionError> {
+        if let Some(bookmark) = self.get(user_id, dm_message_id).await? {
+            if let [message] = bookmark.messages.as_slice() {
+                let key = Self::source_key(user_id, message.id);
+                if self.get_value(&key).await? == Some(dm_message_id) {
+                    self.delete_value(&key).await?;
+                }
+            }
+            if bookmark.remind_at.is_some() {
+                self.forget_reminder(user_id, dm_message_id).await?;
+            }
+        }
+        self.kv
+            .delete(&Self::bookmark_key(user_id, dm_message_id))
+            .await?;
+
+        self.update_index(user_id, IndexList::Bookmarks, IndexOp::Remove(dm_message_id))
+            .await?;
+        self.forget_unread(user_id, dm_message_id).await?;
+        Ok(())
+    }
+
+    /// Moves a bookmark out of the user's bookmarks and into their trash.
+    pub(crate) async fn trash(
+        &self,
+        bookmark: StoredBookmark,
+        deleted_at: u64,
+    ) -> Result<(), InteractionError> {
+        let (user_id, dm_message_id) = (bookmark.user_id, bookmark.dm_message_id);
+        let trashed = TrashedBookmark {
+            bookmark,
+            deleted_at,
+        };
+        self.put_value(&Self::trash_key(user_id, dm_message_id), &trashed, None)
+            .await?;
+
+        self.update_index(user_id, IndexList::Trash, IndexOp::Add(dm_message_id))
+            .await?;
+
+        self.delete(user_id, dm_message_id).await
+    }
+
+    /// The user's trash, most recently deleted first.
+    pub(crate) async fn list_trash(
+        &self,
+        user_id: Id<UserMarker>,
+    ) -> Result<Vec<TrashedBookmark>, InteractionError> {
+        let index = self
+            .update_index(user_id, IndexList::Trash, IndexOp::List)
+            .await?;
+
+        let mut trashed = Vec::new();
+        for id in index.into_iter().rev() {
+            if let Some(t) = self.get_value(&Self::trash_key(user_id, id)).await? {
+                trashed.push(t);
+            }
+        }
+        Ok(trashed)
+    }
+
+    /// Removes a bookmark from the trash and hands it back, the caller re-delivers it.
+    pub(crate) async fn take_from_trash(
+        &self,
+        user_id: Id<UserMarker>,
+        dm_message_id: Id<MessageMarker>,
+    ) -> Result<Option<TrashedBookmark>, InteractionError> {
+        let key = Self::trash_key(user_id, dm_message_id);
+        let trashed: Option<TrashedBookmark> = self.get_value(&key).await?;
+        if trashed.is_some() {
+            self.kv.delete(&key).await?;
+            self.update_index(user_id, IndexList::Trash, IndexOp::Remove(dm_message_id))
+                .await?;
+        }
+        Ok(trashed)
+    }
+
+    /// Starts a purge of the trash, carried out by `purge_trash_batch`.
+    pub(crate) async fn start_purge(&self) -> Result<(), InteractionError> {
+        let run = PurgeRun {
+            cursor: None,
+            pending: Vec::new(),
+            listed: false,
+        };
+        self.put_value(PURGE_RUN_KEY, &run, None).await
+    }
+
+    /// Permanently removes the bookmarks deleted before `now - TRASH_RETENTION` from the trash of
+    /// as many users as `PURGE_BUDGET` allows, if a purge is in progress. Returns how many were
+    /// removed.
+    pub(crate) async fn purge_trash_batch(&self, now: u64) -> Result<usize, InteractionError> {
+        let Some(mut run) = self.get_value::<PurgeRun>(PURGE_RUN_KEY).await? else {
+            return Ok(0);
+        };
+        let cutoff = now.saturating_sub(TRASH_RETENTION);
+
+        // Reading and writing the run itself
+        let mut ops = 2;
+        let mut purged = 0;
+        'users: while ops + 1 + PURGE_OPS <= PURGE_BUDGET {
+            let Some(&user_id) = run.pending.first() else {
+                if run.listed {
+                    break;
+                }
+                ops += 1;
+                let (keys, cursor) = self.key_page(TRASH_INDEX_PREFIX, run.cursor.take(), PURGE_BATCH).await?;
+                run.pending = keys
+                    .iter()
+                    .filter_map(|key| key.trim_start_matches(TRASH_INDEX_PREFIX).parse().ok())
+                    .filter_map(Id::<UserMarker>::new_checked)
+                    .collect();
+                run.listed = cursor.is_none();
+                run.cursor = cursor;
+                continue;
+            };
+
+            // Oldest first, so only the expired ones and the first one that is not are read
+            ops += 1;
+            let index = self.update_index(user_id, IndexList::Trash, IndexOp::List).await?;
+            for id in index {
+                // Stopped mid-user, the next invocation lists what is left of their trash
+                if ops + PURGE_OPS > PURGE_BUDGET {
+                    break 'users;
+                }
+                let key = Self::trash_key(user_id, id);
+                ops += 1;
+                let trashed: Option<TrashedBookmark> = self.get_value(&key).await?;
+                if trashed.is_some_and(|t| t.deleted_at >= cutoff) {
+                    break;
+                }
+                ops += 2;
+                self.kv.delete(&key).await?;
+                self.update_index(user_id, IndexList::Trash, IndexOp::Remove(id))
+                    .await?;
+                purged += 1;
+            }
+            run.pending.remove(0);
+        }
+
+        if run.listed && run.pending.is_empty() {
+            self.delete_value(PURGE_RUN_KEY).await?;
+        } else {
+            self.put_value(PURGE_RUN_KEY, &run, None).await?;
+        }
+        Ok(purged)
+    }
+}
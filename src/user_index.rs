@@ -0,0 +1,128 @@
+use serde::de::DeserializeOwned;
+use twilight_model::id::{marker::MessageMarker, Id};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use worker::{console_log, durable_object, Env, Request, Response, Result, State, Storage};
+
+use crate::limits::{self, Bucket};
+use crate::store::{BookmarkStore, IndexFull, IndexRequest, UserRequest};
+
+/// One per user, named by their id: owns the order and count of their bookmarks and trash, and
+/// their rate limit. Requests to an object run one at a time, so a bookmark followed right away by
+/// a list or delete sees it, where KV can take up to a minute to agree with itself. The bookmarks
+/// themselves stay in KV.
+#[durable_object]
+pub struct UserBookmarks {
+    state: State,
+    env: Env,
+    // Only kept in memory: the object is evicted after sitting idle for longer than the bucket
+    // takes to fill up again, so it would start full anyway
+    rate_limit: Option<Bucket>,
+    // The latest write-through to KV. Other requests can run while one waits on KV, so each write
+    // waits for the one before it, otherwise an older index could land last
+    kv_write: Option<js_sys::Promise>,
+}
+
+// What `Storage::get` fails with when the key has never been stored
+const MISSING: &str = "No such value in storage.";
+
+fn is_missing(err: &worker::Error) -> bool {
+    matches!(err, worker::Error::JsError(message) if message == MISSING)
+}
+
+// `Storage::get`, with `None` for a missing key. Other failures stay errors, starting over from
+// KV on a failed read could bring back bookmarks that were deleted since
+async fn get_stored<T: DeserializeOwned>(storage: &Storage, key: &str) -> Result<Option<T>> {
+    match storage.get(key).await {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if is_missing(&err) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+impl UserBookmarks {
+    async fn load(&self, store: &BookmarkStore, request: &IndexRequest) -> Result<Vec<Id<MessageMarker>>> {
+        let key = request.list.storage_key();
+        let storage = self.state.storage();
+        if let Some(index) = get_stored(&storage, key).await? {
+            return Ok(index);
+        }
+        // First use, start from the index kept in KV until now
+        let index = store
+            .kv_index(request.user_id, request.list)
+            .await
+            .map_err(|err| worker::Error::RustError(err.to_string()))?;
+        // A request that came in while KV was read may have stored one already
+        Ok(get_stored(&storage, key).await?.unwrap_or(index))
+    }
+
+    // Copies the index to KV after the writes already under way, returning once it's there
+    async fn write_through(
+        &mut self,
+        store: BookmarkStore,
+        request: &IndexRequest,
+        index: &[Id<MessageMarker>],
+    ) {
+        let previous = self.kv_write.take();
+        let (user_id, list, index) = (request.user_id, request.list, index.to_vec());
+        let write = future_to_promise(async move {
+            if let Some(previous) = previous {
+                // Failures are logged by the write itself
+                let _ = JsFuture::from(previous).await;
+            }
+            if let Err(err) = store.put_kv_index(user_id, list, &index).await {
+                console_log!("[INDEX] writing through to KV failed: {}", err);
+            }
+            Ok(JsValue::UNDEFINED)
+        });
+        self.kv_write = Some(write.clone());
+        let _ = JsFuture::from(write).await;
+    }
+}
+
+#[durable_object]
+impl DurableObject for UserBookmarks {
+    fn new(state: State, env: Env) -> Self {
+        Self {
+            state,
+            env,
+            rate_limit: None,
+            kv_write: None,
+        }
+    }
+
+    async fn fetch(&mut self, mut req: Request) -> Result<Response> {
+        let request = match req.json().await? {
+            UserRequest::Index(request) => request,
+            UserRequest::TakeToken { now } => {
+                return Response::from_json(&limits::take_user(&mut self.rate_limit, now))
+            }
+        };
+        let store = BookmarkStore::kv_only(&self.env)
+            .map_err(|err| worker::Error::RustError(err.to_string()))?;
+
+        let mut index = self.load(&store, &request).await?;
+        let changed = match request.op.apply(request.list, &mut index) {
+            Ok(changed) => changed,
+            Err(full) => return Response::from_json(&Err::<(), _>(full)),
+        };
+        if changed {
+            self.state.storage().put(request.list.storage_key(), &index).await?;
+            // KV stays a copy, for the fallback when the binding is removed
+            self.write_through(store, &request, &index).await;
+        }
+        Response::from_json(&Ok::<_, IndexFull>(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_missing_key_is_missing() {
+        assert!(is_missing(&worker::Error::JsError(MISSING.to_string())));
+        assert!(!is_missing(&worker::Error::JsError("Storage operation failed".to_string())));
+        assert!(!is_missing(&worker::Error::RustError(MISSING.to_string())));
+    }
+}
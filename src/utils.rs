@@ -9,4 +9,11 @@ cfg_if! {
         #[inline]
         pub fn set_panic_hook() {}
     }
-}
\ No newline at end of file
+}
+
+/// A random, URL safe token of `bytes` random bytes, hex encoded.
+pub fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    getrandom::getrandom(&mut buf).expect("crypto.getRandomValues is available in workers");
+    hex::encode(buf)
+}